@@ -0,0 +1,341 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use naga::{
+    back::spv,
+    front::{glsl, wgsl},
+    valid::{Capabilities, ValidationFlags, Validator},
+    ImageClass, Module, ResourceBinding, ShaderStage, TypeInner,
+};
+
+// Where the shader sources live in the repository.
+const SHADER_SOURCE_ROOT: &str = "src/graphics/shaders";
+
+// Compiles every shader source under SHADER_SOURCE_ROOT to SPIR-V and writes the result into
+// target/<profile>/shaders/<stage>/<name>.spv, which is where shaders::load_shaders goes looking
+// for them at runtime.  GLSL is recognised by its .vert/.frag/.comp extension; WGSL files must
+// hold exactly one entry point, whose stage decides the output directory.  Every source is
+// attempted before the build fails, so one broken shader does not hide another.
+//
+// It also writes OUT_DIR/embedded_shaders.rs, the registry the embedded-shaders feature pulls
+// every compiled module into the executable through.
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHADER_SOURCE_ROOT);
+
+    let out_dir = out_dir();
+    let output_root = shader_output_root(&out_dir);
+
+    let mut sources = Vec::new();
+    collect_sources(Path::new(SHADER_SOURCE_ROOT), &mut sources);
+    sources.sort();
+
+    let mut failures = Vec::new();
+    let mut compiled: BTreeMap<String, PathBuf> = BTreeMap::new();
+
+    for source_path in &sources {
+        match compile_shader(source_path) {
+            Ok(Some((stage, words))) => {
+                let (name, output_path) = write_spirv(&output_root, source_path, stage, &words);
+                // Shaders are looked up by file name alone, whatever stage directory they land in.
+                if let Some(previous) = compiled.insert(name.clone(), output_path) {
+                    failures.push(format!(
+                        "{}: a shader named {} was already compiled to {}; shader names must be unique",
+                        source_path.display(),
+                        name,
+                        previous.display()
+                    ));
+                }
+            }
+            Ok(None) => {}
+            Err(diagnostic) => {
+                failures.push(diagnostic);
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        for diagnostic in &failures {
+            eprintln!("{}", diagnostic);
+        }
+        panic!(
+            "{} of {} shaders failed to compile; see the diagnostics above.",
+            failures.len(),
+            sources.len()
+        );
+    }
+
+    write_embedded_registry(&out_dir, &compiled);
+}
+
+fn out_dir() -> PathBuf {
+    match std::env::var_os("OUT_DIR") {
+        Some(out_dir) => PathBuf::from(out_dir),
+        None => {
+            panic!("OUT_DIR is not set; this must be run by cargo as a build script.");
+        }
+    }
+}
+
+// OUT_DIR is target/<profile>/build/<package>-<hash>/out; the executable - and so the shader
+// directory the engine reads - sits three levels above it.
+fn shader_output_root(out_dir: &Path) -> PathBuf {
+    match out_dir.ancestors().nth(3) {
+        Some(profile_dir) => profile_dir.join("shaders"),
+        None => {
+            panic!(
+                "OUT_DIR {:?} is not laid out the way cargo normally lays it out.",
+                out_dir
+            );
+        }
+    }
+}
+
+fn collect_sources(path: &Path, sources: &mut Vec<PathBuf>) {
+    let dir_contents = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(msg) => {
+            panic!(
+                "Unable to read the shader source directory {:?}: {:?}",
+                path, msg
+            );
+        }
+    };
+
+    for entry in dir_contents.flatten() {
+        if entry.path().is_dir() {
+            collect_sources(&entry.path(), sources);
+        } else if entry.path().is_file() {
+            sources.push(entry.path());
+        }
+    }
+}
+
+// Ok(None) means the file is not a shader source and was skipped.  Err carries a diagnostic
+// ready to print, already pointing at the offending line of the source.
+fn compile_shader(path: &Path) -> Result<Option<(ShaderStage, Vec<u32>)>, String> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+
+    let glsl_stage = match extension {
+        Some("vert") => Some(ShaderStage::Vertex),
+        Some("frag") => Some(ShaderStage::Fragment),
+        Some("comp") => Some(ShaderStage::Compute),
+        Some("wgsl") => None,
+        _ => {
+            return Ok(None);
+        }
+    };
+
+    let path_str = path.display().to_string();
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(msg) => {
+            return Err(format!(
+                "{}: unable to read the shader source: {}",
+                path_str, msg
+            ));
+        }
+    };
+
+    let mut module = match glsl_stage {
+        Some(stage) => {
+            match glsl::Frontend::default().parse(&glsl::Options::from(stage), &source) {
+                Ok(module) => module,
+                Err(errors) => {
+                    return Err(errors.emit_to_string_with_path(&source, &path_str));
+                }
+            }
+        }
+        None => match wgsl::Frontend::new().parse(&source) {
+            Ok(module) => module,
+            Err(error) => {
+                return Err(error.emit_to_string_with_path(&source, &path_str));
+            }
+        },
+    };
+
+    let stage = module_stage(&module, &path_str)?;
+    let binding_map = split_combined_image_samplers(&mut module);
+
+    let info = match Validator::new(ValidationFlags::all(), Capabilities::all()).validate(&module) {
+        Ok(info) => info,
+        Err(error) => {
+            return Err(error.emit_to_string_with_path(&source, &path_str));
+        }
+    };
+
+    // The sources are written against Vulkan's clip space already, so naga must not flip Y the
+    // way it would for WebGPU-style shaders.
+    let options = spv::Options {
+        flags: spv::Options::default().flags - spv::WriterFlags::ADJUST_COORDINATE_SPACE,
+        binding_map,
+        ..spv::Options::default()
+    };
+
+    match spv::write_vec(&module, &info, &options, None) {
+        Ok(words) => Ok(Some((stage, words))),
+        Err(error) => Err(format!("{}: SPIR-V generation failed: {}", path_str, error)),
+    }
+}
+
+// *** split_combined_image_samplers(module: &mut Module) -> spv::BindingMap
+//
+// naga will not parse GLSL's sampler2D, so textures are written as a texture2D and a sampler that
+// share a set and binding - which Vulkan happily backs with one COMBINED_IMAGE_SAMPLER
+// descriptor.  naga's validator, on the other hand, rejects two resources on one binding.  So
+// each such sampler is moved to a spare binding for validation, and the returned map moves it
+// back when the SPIR-V is written.  Every other binding collision is still caught.
+//
+fn split_combined_image_samplers(module: &mut Module) -> spv::BindingMap {
+    let mut images: HashMap<ResourceBinding, bool> = HashMap::new();
+    let mut next_free: HashMap<u32, u32> = HashMap::new();
+
+    for (_, variable) in module.global_variables.iter() {
+        if let Some(binding) = &variable.binding {
+            let is_sampled_image = matches!(
+                module.types[variable.ty].inner,
+                TypeInner::Image {
+                    class: ImageClass::Sampled { .. } | ImageClass::Depth { .. },
+                    ..
+                }
+            );
+            if is_sampled_image {
+                images.insert(*binding, false);
+            }
+            let free = next_free.entry(binding.group).or_insert(0);
+            *free = std::cmp::max(*free, binding.binding + 1);
+        }
+    }
+
+    let mut binding_map = spv::BindingMap::default();
+
+    for (_, variable) in module.global_variables.iter_mut() {
+        let binding = match &mut variable.binding {
+            Some(binding) => binding,
+            None => continue,
+        };
+
+        if !matches!(module.types[variable.ty].inner, TypeInner::Sampler { .. }) {
+            continue;
+        }
+
+        // Only the first sampler paired with an image is folded in; a second would be a genuine
+        // collision and is left for the validator to report.
+        match images.get_mut(binding) {
+            Some(paired) if !*paired => {
+                *paired = true;
+            }
+            _ => continue,
+        }
+
+        let original = *binding;
+        let free = next_free.get_mut(&original.group).unwrap();
+        binding.binding = *free;
+        *free += 1;
+
+        binding_map.insert(
+            *binding,
+            spv::BindingInfo {
+                descriptor_set: original.group,
+                binding: original.binding,
+                binding_array_size: None,
+            },
+        );
+    }
+
+    binding_map
+}
+
+// The engine keys shaders by file name and reflects a single entry point out of each module, so
+// a source must produce exactly one.
+fn module_stage(module: &Module, path_str: &str) -> Result<ShaderStage, String> {
+    match module.entry_points.as_slice() {
+        [entry_point] => Ok(entry_point.stage),
+        entry_points => Err(format!(
+            "{}: expected exactly one entry point, found {}",
+            path_str,
+            entry_points.len()
+        )),
+    }
+}
+
+fn write_spirv(
+    output_root: &Path,
+    source_path: &Path,
+    stage: ShaderStage,
+    words: &[u32],
+) -> (String, PathBuf) {
+    let stage_dir = match stage {
+        ShaderStage::Vertex => "vertex",
+        ShaderStage::Fragment => "fragment",
+        ShaderStage::Compute => "compute",
+        other => {
+            panic!("{:?}: unsupported shader stage {:?}", source_path, other);
+        }
+    };
+
+    let output_dir = output_root.join(stage_dir);
+    if let Err(msg) = fs::create_dir_all(&output_dir) {
+        panic!(
+            "Unable to create the shader output directory {:?}: {:?}",
+            output_dir, msg
+        );
+    }
+
+    let file_stem = match source_path
+        .file_stem()
+        .and_then(|file_stem| file_stem.to_str())
+    {
+        Some(file_stem) => file_stem,
+        None => {
+            panic!("The shader source {:?} has no file name.", source_path);
+        }
+    };
+
+    let output_path = output_dir.join(file_stem).with_extension("spv");
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_ne_bytes()).collect();
+
+    if let Err(msg) = fs::write(&output_path, bytes) {
+        panic!(
+            "Unable to write the compiled shader {:?}: {:?}",
+            output_path, msg
+        );
+    }
+
+    (String::from(file_stem), output_path)
+}
+
+fn write_embedded_registry(out_dir: &Path, compiled: &BTreeMap<String, PathBuf>) {
+    let mut registry = String::from(
+        "// Generated by build.rs from the shader sources; do not edit.\n\
+         pub static EMBEDDED_SHADERS: &[(&str, &[u8])] = &[\n",
+    );
+
+    for (name, path) in compiled {
+        let absolute_path = match path.canonicalize() {
+            Ok(absolute_path) => absolute_path,
+            Err(msg) => {
+                panic!(
+                    "Unable to resolve the compiled shader {:?}: {:?}",
+                    path, msg
+                );
+            }
+        };
+        registry.push_str(&format!(
+            "    ({:?}, include_bytes!({:?})),\n",
+            name, absolute_path
+        ));
+    }
+
+    registry.push_str("];\n");
+
+    let registry_path = out_dir.join("embedded_shaders.rs");
+    if let Err(msg) = fs::write(&registry_path, registry) {
+        panic!(
+            "Unable to write the embedded shader registry {:?}: {:?}",
+            registry_path, msg
+        );
+    }
+}
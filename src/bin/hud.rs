@@ -1,133 +1,210 @@
-use ash::vk::{
-    Extent3D, Format, ImageCreateFlags, ImageCreateInfo, ImageLayout, ImageTiling, ImageType,
-    ImageUsageFlags, SampleCountFlags, Semaphore, SharingMode,
+use ash::vk::Semaphore;
+use dust::{
+    bitmap, composite_hud, config, resident, shutdown, stats, xcb_window, Config, DustError, Flow,
+    Game, GameLoop, InputEvent, KeyStroke, ScaleMode, TextureId, TextureSource, VirtualResolution,
+    VkContext, DOOM_TICK_RATE,
 };
-use graphics::image::DustImage;
-use graphics::pools::{get_graphics_queue_family, get_transfer_queue_family};
-use graphics::{bitmap, pools, transfer};
-use log::debug;
-
-mod dust_errors;
-mod graphics;
-mod input;
-mod setup;
-
-use setup::{instance::VkContext, xcb_window};
-use std::fs::File;
-use std::io::Read;
-use std::{
-    thread::{self, sleep},
-    time::Duration,
-};
-use xcb::x::Window;
-
-use crate::{
-    input::input::KeyStroke,
-    setup::instance::{self},
-};
-
-fn main() {
+use log::{debug, error};
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::{mpsc::Receiver, Arc};
+use std::{thread, time::Duration};
+
+// The keysym for Escape, which quits the demo.
+const KEY_ESCAPE: u32 = 0xff1b;
+
+// The HUD demo: slides the Doom status bar up along the bottom of the window, until Escape, the
+// close button or Ctrl-C.
+// It uses nothing the dust library does not export, so it doubles as an example of driving it.
+fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help") {
+        println!("{}", config::usage());
+        return Ok(());
+    }
+    let config = match config::load(&args) {
+        Ok(config) => config,
+        Err(config_error) => {
+            eprintln!("{}", config_error);
+            process::exit(2);
+        }
+    };
+
     // window system setup
     debug!("Starting X-Windows initialization...");
-    let (conn, screen_num) = xcb_window::connect();
-    xcb_window::interrogate_keymaps(&conn);
-    xcb_window::extension_data(&conn);
-    let window: Window = xcb_window::create_window(&conn, screen_num);
-    let (upper_left, window_size) = xcb_window::interrogate_randr(&conn, window);
-    xcb_window::resize_window(&conn, window, upper_left, window_size);
+    let (conn, window) = xcb_window::open(&config.window)?;
 
-    let _xcb_ptr = conn.get_raw_conn();
-    let (sender, _receiver) = std::sync::mpsc::sync_channel::<KeyStroke>(16);
-    thread::spawn(move || xcb_window::event_loop(conn, sender));
+    shutdown::install_signal_handlers();
 
-    let vk_context = instance::default(_xcb_ptr, &window);
-    // show_physical_memory_stats(&vk_context);
+    let conn = Arc::new(conn);
+    let xcb_ptr = conn.get_raw_conn();
+    let (sender, receiver) = std::sync::mpsc::sync_channel::<InputEvent>(16);
+    let event_thread = {
+        let conn = conn.clone();
+        thread::spawn(move || xcb_window::event_loop(conn, sender))
+    };
+
+    let played = play(&config, xcb_ptr, &window, &receiver);
+
+    // Shut down in dependency order: play() has dropped the Vulkan context, which waits for the
+    // device and goes, taking the surface with it, before the window it was made for.  Dropping
+    // the receiver first means the event thread cannot be stuck sending when it is told to stop.
+    debug!("Vulkan instance destroyed...");
+    drop(receiver);
+    xcb_window::stop_event_loop(&conn, window);
+    if event_thread.join().is_err() {
+        error!("The event thread panicked.");
+    }
+    xcb_window::close_window(&conn, window);
+
+    played
+}
 
-    let sample_bmp_data = load_sample_bmp();
+// Everything between opening the window and closing it: Vulkan setup, the HUD upload and the
+// game loop.  The context is gone by the time this returns, however it returns.
+fn play(
+    config: &Config,
+    xcb_ptr: *mut xcb::ffi::xcb_connection_t,
+    window: &xcb::x::Window,
+    receiver: &Receiver<InputEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let vk_context = VkContext::builder()
+        .config(&config.graphics)
+        .build(xcb_ptr, window)?;
+
+    let sample_bmp_data = load_sample_bmp(&config.assets.path("Doom_status_bar.bmp"))?;
     let hud_bar = match bitmap::new(&sample_bmp_data) {
         Ok(bar) => bar,
         Err(bitmap_error) => {
-            panic!("The bitmap failed to load: {:?}", bitmap_error);
+            return Err(format!("the bitmap failed to load: {:?}", bitmap_error).into());
         }
     };
 
-    let full_image = embed_hud_in_huge_image(hud_bar);
-
-    let (finished, transfer_complete_semaphore) = transfer::copy_to_image(
-        &full_image, //.get_pixel_array(),
-        &vk_context,
-        &ImageCreateInfo::default()
-            .format(Format::R8G8B8A8_SRGB)
-            .flags(ImageCreateFlags::empty())
-            .extent(Extent3D::default().depth(1).width(1920).height(1080))
-            .usage(ImageUsageFlags::INPUT_ATTACHMENT | ImageUsageFlags::TRANSFER_DST)
-            .tiling(ImageTiling::OPTIMAL)
-            .samples(SampleCountFlags::TYPE_1)
-            .mip_levels(1)
-            .sharing_mode(SharingMode::EXCLUSIVE)
-            .array_layers(1)
-            .image_type(ImageType::TYPE_2D)
-            .initial_layout(ImageLayout::UNDEFINED),
-        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-        get_graphics_queue_family(),
+    let hud_width = hud_bar.get_width() as u32;
+    let hud_height = hud_bar.get_height() as u32;
+
+    let hud_source = TextureSource::new(
+        "hud_image",
+        hud_bar.get_pixel_array().concat(),
+        hud_width,
+        hud_height,
     );
+    let (hud, hud_ready) = resident::upload(&vk_context, hud_source)?;
 
     debug!(
         "The HUD bar has size {} x {}, total of {} pixels.",
-        1920,
-        1080,
-        full_image.len()
+        hud_width,
+        hud_height,
+        hud_bar.get_pixel_array().len()
     );
 
-    graphics::render::composite_hud(
-        &vk_context,
-        &finished.view,
-        finished.format,
-        transfer_complete_semaphore,
-    );
+    // The status bar is drawn for Doom's 320x200, so it is laid out in that logical resolution
+    // and scaled to whatever surface we ended up with.  It slides up into place along the bottom
+    // edge, which shows off the interpolation between ticks.
+    let resolution = VirtualResolution::new(320, 200)
+        .ok_or("the HUD's logical resolution is empty")?
+        .mode(ScaleMode::Aspect4By3);
+    let resting_y = resolution.extent.height as f32 - hud_height as f32 / 2.0;
+    let mut demo = HudDemo {
+        vk_context,
+        resolution,
+        hud,
+        x: resolution.extent.width as f32 / 2.0,
+        previous_y: resting_y + hud_height as f32,
+        y: resting_y + hud_height as f32,
+        resting_y,
+        images_ready: vec![hud_ready],
+        ticks: 0,
+    };
 
-    // let (gradient, semaphore) = load_gradient(&vk_context);
-    // graphics::render::composite_test(&vk_context, &gradient.view, gradient.format, semaphore);
-    // display_image(&vk_context);
-    // display_gradient(&vk_context);
-    sleep(Duration::from_secs(3));
+    GameLoop::new(DOOM_TICK_RATE).run(&mut demo, receiver)?;
 
-    debug!("Vulkan instance destroyed...");
+    Ok(())
 }
 
-fn load_sample_bmp() -> Vec<u8> {
-    let mut buffer = Vec::<u8>::new();
-    if let Ok(mut hud_file) = File::open("resources/Doom_status_bar.bmp") {
-        hud_file.read_to_end(&mut buffer);
-    }
-
-    buffer
+// The HUD demo's whole game state: where the status bar is, last tick and this one.
+struct HudDemo {
+    vk_context: VkContext,
+    resolution: VirtualResolution,
+    hud: TextureId,
+    x: f32,
+    previous_y: f32,
+    y: f32,
+    resting_y: f32,
+    // Semaphores the next frame has to wait on: the first upload, or a restore.
+    images_ready: Vec<Semaphore>,
+    ticks: u64,
 }
 
-fn show_physical_memory_stats(vk_ctxt: &VkContext) {
-    let temp = vk_ctxt.physical_memory_properties;
+// How far the status bar rises each tick, in logical pixels.
+const HUD_RISE_PER_TICK: f32 = 1.0;
 
-    for index in 0..temp.memory_type_count as usize {
-        debug!(
-            "mem_type {}: {:?}",
-            temp.memory_types[index].heap_index, temp.memory_types[index].property_flags
-        );
-    }
+impl Game for HudDemo {
+    fn tick(&mut self, input: &[InputEvent], _step: Duration) -> Flow {
+        if input.contains(&InputEvent::KeyDown(KeyStroke::Key(KEY_ESCAPE))) {
+            return Flow::Quit;
+        }
 
-    for index in 0..temp.memory_heap_count as usize {
-        if !temp.memory_heaps[index].flags.is_empty() {
-            debug!(
-                "Memory heap {:?}: {}",
-                temp.memory_heaps[index].flags, temp.memory_heaps[index].size
-            );
-        } else {
+        self.previous_y = self.y;
+        self.y = (self.y - HUD_RISE_PER_TICK).max(self.resting_y);
+
+        self.ticks += 1;
+        if self.ticks.is_multiple_of(DOOM_TICK_RATE as u64) {
+            let stats = stats::stats();
             debug!(
-                "Memory heap flags empty, size {}",
-                temp.memory_heaps[index].size
+                "{:.1} fps ({:?} a frame), {:.1} ticks a second ({:?} a tick)",
+                stats.fps, stats.frame_time, stats.tps, stats.tick_time
             );
         }
+
+        Flow::Continue
+    }
+
+    // Losing the device or the surface costs the frame: the context is rebuilt, the HUD uploaded
+    // again from the bitmap, and the next frame waits for it.
+    fn render(&mut self, alpha: f32) -> Result<(), DustError> {
+        let position = [self.x, self.previous_y + (self.y - self.previous_y) * alpha];
+        let images_ready = std::mem::take(&mut self.images_ready);
+
+        match draw_hud(
+            &self.vk_context,
+            &self.resolution,
+            self.hud,
+            position,
+            images_ready,
+        ) {
+            Err(render_error) if render_error.is_lost() => {
+                error!("{}; rebuilding the Vulkan context.", render_error);
+                self.images_ready = self.vk_context.recover()?;
+                Ok(())
+            }
+            drawn => drawn,
+        }
+    }
+}
+
+fn draw_hud(
+    vk_ctxt: &VkContext,
+    resolution: &VirtualResolution,
+    hud: TextureId,
+    position: [f32; 2],
+    images_ready: Vec<Semaphore>,
+) -> Result<(), DustError> {
+    let placement = resolution.placement(vk_ctxt.surface_capabilities.current_extent);
+    let quad = resident::with_texture(hud, |image| placement.quad(image, position))??;
+
+    composite_hud(vk_ctxt, quad, images_ready)
+}
+
+fn load_sample_bmp(path: &Path) -> Result<Vec<u8>, String> {
+    match fs::read(path) {
+        Ok(contents) => Ok(contents),
+        Err(read_error) => Err(format!("unable to read {:?}: {}", path, read_error)),
     }
 }
 
@@ -346,78 +423,6 @@ fn show_physical_memory_stats(vk_ctxt: &VkContext) {
 //     )
 // }
 
-fn load_gradient(ctxt: &VkContext) -> (DustImage, Semaphore) {
-    let image_width = ctxt.surface_capabilities.current_extent.width;
-    let image_height = ctxt.surface_capabilities.current_extent.height;
-
-    let horizontal_pixel_incr = 255.0 / image_width as f32;
-    let vertical_pixel_incr = 255.0 / image_height as f32;
-
-    debug!("Image width: {}", image_width);
-    debug!("Image height: {}", image_height);
-
-    // Just assume RGBA here - 4 bytes per pixel.
-    let pixel_stride = 4;
-    let buffer_size_in_bytes = image_width * image_height * pixel_stride;
-
-    let mut host_buffer: Vec<u8> = vec![0; buffer_size_in_bytes as usize];
-
-    let mut red_pixel_value: u8;
-    let mut green_pixel_value: u8;
-    let mut blue_pixel_value: u8;
-
-    for row_index in 0..image_height {
-        green_pixel_value = (vertical_pixel_incr * row_index as f32).floor() as u8;
-        for col_index in 0..image_width {
-            let red_index =
-                (row_index * (image_width * pixel_stride) + (col_index * pixel_stride)) as usize;
-            let green_index = red_index + 1;
-            let blue_index = red_index + 2;
-            let alpha_index = red_index + 3;
-
-            red_pixel_value = (horizontal_pixel_incr * col_index as f32).floor() as u8;
-            blue_pixel_value =
-                ((row_index * col_index) as f32 / (image_width * image_height) as f32 * 255.0)
-                    .floor() as u8;
-
-            host_buffer[red_index] = red_pixel_value;
-            host_buffer[green_index] = green_pixel_value;
-            host_buffer[blue_index] = blue_pixel_value;
-            host_buffer[alpha_index] = 255;
-        }
-    }
-
-    let target_image = &ImageCreateInfo::default()
-        .initial_layout(ImageLayout::UNDEFINED)
-        .sharing_mode(SharingMode::EXCLUSIVE)
-        .image_type(ImageType::TYPE_2D)
-        .array_layers(1)
-        .format(Format::R8G8B8A8_SRGB)
-        .extent(
-            Extent3D::default()
-                .height(image_height)
-                .width(image_width)
-                .depth(1),
-        )
-        .mip_levels(1)
-        .samples(SampleCountFlags::TYPE_1)
-        .flags(ImageCreateFlags::empty())
-        .usage(
-            ImageUsageFlags::TRANSFER_SRC
-                | ImageUsageFlags::TRANSFER_DST
-                | ImageUsageFlags::INPUT_ATTACHMENT,
-        )
-        .tiling(ImageTiling::OPTIMAL);
-
-    transfer::copy_to_image(
-        &host_buffer,
-        ctxt,
-        target_image,
-        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-        pools::get_graphics_queue_family(),
-    )
-}
-
 // fn display_image(vk_ctxt: &VkContext) {
 //     let image_width = vk_ctxt.surface_capabilities.current_extent.width;
 //     let image_height = vk_ctxt.surface_capabilities.current_extent.height;
@@ -766,35 +771,3 @@ fn load_gradient(ctxt: &VkContext) -> (DustImage, Semaphore) {
 //             .destroy_fence(swapchain_image_acq_fence, None);
 //     }
 // }
-
-fn embed_hud_in_huge_image(bmp: bitmap::Bitmap) -> Vec<[u8; 4]> {
-    let mut fake_screen = Vec::<[u8; 4]>::with_capacity(1920 * 1080);
-
-    for _ in 0..(1920 * 1080) {
-        fake_screen.push([0, 0, 0, 0]);
-    }
-
-    let bitmap = bmp.get_pixel_array();
-
-    let midpoint = 1920 / 2;
-    let bitmap_midpoint = bmp.get_width() / 2;
-
-    let left = midpoint - bitmap_midpoint;
-    let top = 1080 - bmp.get_height();
-
-    let mut current = 1920 * top + left;
-    let mut offset = 0;
-
-    while current < fake_screen.len() {
-        fake_screen.insert(current + offset, bitmap[offset]);
-
-        offset += 1;
-
-        if offset >= bmp.get_width() {
-            current += 1920;
-            offset = 0;
-        }
-    }
-
-    fake_screen
-}
@@ -1,7 +1,167 @@
-use ash::vk::Result;
+use std::fmt;
+
+use ash::vk::{DescriptorType, Result};
 
 #[derive(Debug)]
 pub enum DustError {
     NoMatchingMemoryType,
     CreateShaderModuleFailed(Result),
+    CreatePipelineFailed(Result),
+    ShaderNotFound(String),
+    // The directory compiled shaders are loaded from cannot be found or read.
+    ShaderDirectory(String),
+    NotAComputeShader(String),
+    // A write into a DustBuffer would run off its end, or the buffer is not host mapped.
+    BufferWrite(String),
+    // The palette framebuffer was handed lumps, rows or indices that do not fit it.
+    InvalidPalette(String),
+    // pipelines::get() or description_of() was asked for a pipeline never registered.
+    PipelineNotFound(String),
+    // A compute dispatch's bindings or push constants do not fit its shader; the reason says how.
+    InvalidDispatch(String),
+    VertexInputMismatch(u32),
+    // Two stages of one pipeline declare the same set and binding with different descriptor types.
+    ConflictingDescriptorType {
+        set: u32,
+        binding: u32,
+        first: DescriptorType,
+        second: DescriptorType,
+    },
+    MissingRequirements(Vec<String>),
+    // An image was drawn before make_texture gave it a descriptor set to be sampled through.
+    NotATexture,
+    // A resident texture id that was forgotten, or not yet restored after the device was lost.
+    TextureNotResident(u32),
+    // Something was used before the Vulkan context that sets it up was built.
+    NotInitialized(&'static str),
+    // The Vulkan loader could not be found or opened.
+    LoaderUnavailable(String),
+    // No physical device can run the engine; the reason says why.
+    NoSuitableDevice(String),
+    // The window system refused something: a connection, a window, an atom.
+    WindowSystem(String),
+    // The surface went away under us - the X server dropped the window, say.  Recreate the
+    // surface and everything built on it.
+    SurfaceLost(&'static str),
+    // The driver reset or the GPU fell off the bus.  Everything made from the logical device is
+    // gone; only a new device will do.
+    DeviceLost(&'static str),
+    // Host or device memory ran out.
+    OutOfMemory {
+        context: &'static str,
+        result: Result,
+    },
+    // A fence or acquire did not finish in the time given.
+    Timeout(&'static str),
+    // Any other Vulkan failure, with what was being attempted.
+    Vulkan {
+        context: &'static str,
+        result: Result,
+    },
+}
+
+impl DustError {
+    // *** vulkan(context: &'static str, result: Result) -> DustError
+    //
+    // Sorts a failed Vulkan call into the variant a caller can act on.  context is what was being
+    // attempted, in the form "creating the swapchain".
+    //
+    pub fn vulkan(context: &'static str, result: Result) -> DustError {
+        match result {
+            Result::ERROR_DEVICE_LOST => DustError::DeviceLost(context),
+            Result::ERROR_SURFACE_LOST_KHR => DustError::SurfaceLost(context),
+            Result::ERROR_OUT_OF_HOST_MEMORY | Result::ERROR_OUT_OF_DEVICE_MEMORY => {
+                DustError::OutOfMemory { context, result }
+            }
+            Result::TIMEOUT => DustError::Timeout(context),
+            _ => DustError::Vulkan { context, result },
+        }
+    }
+
+    // Whether the device or surface has to be rebuilt before anything else will work.
+    pub fn is_lost(&self) -> bool {
+        matches!(self, DustError::DeviceLost(_) | DustError::SurfaceLost(_))
+    }
+}
+
+impl fmt::Display for DustError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DustError::NoMatchingMemoryType => {
+                write!(f, "no memory type matches the resource's requirements")
+            }
+            DustError::CreateShaderModuleFailed(result) => {
+                write!(f, "creating a shader module failed: {}", result)
+            }
+            DustError::CreatePipelineFailed(result) => {
+                write!(f, "creating a pipeline failed: {}", result)
+            }
+            DustError::ShaderNotFound(name) => write!(f, "no shader named {} is loaded", name),
+            DustError::ShaderDirectory(reason) => {
+                write!(f, "the shader directory cannot be read: {}", reason)
+            }
+            DustError::NotAComputeShader(name) => write!(f, "{} is not a compute shader", name),
+            DustError::BufferWrite(reason) => write!(f, "unable to write to a buffer: {}", reason),
+            DustError::InvalidPalette(reason) => write!(f, "invalid palette data: {}", reason),
+            DustError::PipelineNotFound(name) => {
+                write!(f, "no pipeline named {} has been registered", name)
+            }
+            DustError::InvalidDispatch(reason) => {
+                write!(f, "the compute dispatch is invalid: {}", reason)
+            }
+            DustError::VertexInputMismatch(location) => write!(
+                f,
+                "the vertex shader's input at location {} has no matching vertex attribute",
+                location
+            ),
+            DustError::ConflictingDescriptorType {
+                set,
+                binding,
+                first,
+                second,
+            } => write!(
+                f,
+                "set {}, binding {} is declared as both {:?} and {:?} by the stages of one pipeline",
+                set, binding, first, second
+            ),
+            DustError::MissingRequirements(missing) => {
+                write!(f, "the device is missing {}", missing.join(", "))
+            }
+            DustError::NotATexture => {
+                write!(f, "the image has not been made into a texture")
+            }
+            DustError::TextureNotResident(id) => write!(
+                f,
+                "texture {} is not resident, or has not been restored since the device was lost",
+                id
+            ),
+            DustError::NotInitialized(what) => write!(f, "{} has not been set up yet", what),
+            DustError::LoaderUnavailable(reason) => {
+                write!(f, "the Vulkan loader is unavailable: {}", reason)
+            }
+            DustError::NoSuitableDevice(reason) => {
+                write!(f, "no suitable Vulkan device: {}", reason)
+            }
+            DustError::WindowSystem(reason) => write!(f, "window system error: {}", reason),
+            DustError::SurfaceLost(context) => write!(f, "the surface was lost while {}", context),
+            DustError::DeviceLost(context) => write!(f, "the device was lost while {}", context),
+            DustError::OutOfMemory { context, result } => {
+                write!(f, "ran out of memory while {}: {}", context, result)
+            }
+            DustError::Timeout(context) => write!(f, "timed out while {}", context),
+            DustError::Vulkan { context, result } => write!(f, "{} failed: {}", context, result),
+        }
+    }
+}
+
+impl std::error::Error for DustError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DustError::CreateShaderModuleFailed(result)
+            | DustError::CreatePipelineFailed(result)
+            | DustError::OutOfMemory { result, .. }
+            | DustError::Vulkan { result, .. } => Some(result),
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,448 @@
+use std::mem::{offset_of, size_of};
+
+use ash::vk::{
+    BufferUsageFlags, CommandBuffer, DescriptorSet, Extent2D, Format, Handle, IndexType,
+    PipelineBindPoint, PipelineLayout, ShaderStageFlags, VertexInputAttributeDescription,
+    VertexInputBindingDescription, VertexInputRate,
+};
+use log::debug;
+
+use crate::{dust_errors::DustError, setup::instance::VkContext};
+
+use super::{buffer::DustBuffer, transfer};
+
+const VERTICES_PER_QUAD: usize = 4;
+const INDICES_PER_QUAD: usize = 6;
+// Room for this many quads is reserved the first time a frame's buffers are built.  The buffers
+// grow to the next power of two whenever a frame submits more than they can hold.
+const INITIAL_QUAD_CAPACITY: usize = 256;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+// *** Quad
+//
+// A single textured, tinted and optionally rotated rectangle.  Positions and sizes are in pixels
+// with the origin at the upper left of the render target; position names the centre of the quad,
+// which is also the point the quad rotates around.  Quads are drawn in ascending layer order, so
+// anything on a higher layer lands on top.
+#[derive(Clone, Copy, Debug)]
+pub struct Quad {
+    pub texture: DescriptorSet,
+    pub layer: i32,
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub rotation: f32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    pub tint: [f32; 4],
+}
+
+impl Quad {
+    pub fn new(texture: DescriptorSet, position: [f32; 2], size: [f32; 2]) -> Quad {
+        Quad {
+            texture,
+            layer: 0,
+            position,
+            size,
+            rotation: 0.0,
+            uv_min: [0.0, 0.0],
+            uv_max: [1.0, 1.0],
+            tint: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn layer(mut self, layer: i32) -> Quad {
+        self.layer = layer;
+        self
+    }
+
+    pub fn rotation(mut self, radians: f32) -> Quad {
+        self.rotation = radians;
+        self
+    }
+
+    pub fn uv(mut self, uv_min: [f32; 2], uv_max: [f32; 2]) -> Quad {
+        self.uv_min = uv_min;
+        self.uv_max = uv_max;
+        self
+    }
+
+    pub fn tint(mut self, tint: [f32; 4]) -> Quad {
+        self.tint = tint;
+        self
+    }
+
+    fn corners(&self) -> [SpriteVertex; VERTICES_PER_QUAD] {
+        let half_width = self.size[0] / 2.0;
+        let half_height = self.size[1] / 2.0;
+        let (sin, cos) = self.rotation.sin_cos();
+
+        // Upper left, upper right, lower right, lower left - clockwise on screen, which matches
+        // the FrontFace::CLOCKWISE the rest of the renderer uses.
+        let offsets = [
+            (-half_width, -half_height),
+            (half_width, -half_height),
+            (half_width, half_height),
+            (-half_width, half_height),
+        ];
+        let uvs = [
+            [self.uv_min[0], self.uv_min[1]],
+            [self.uv_max[0], self.uv_min[1]],
+            [self.uv_max[0], self.uv_max[1]],
+            [self.uv_min[0], self.uv_max[1]],
+        ];
+
+        let mut corners = [SpriteVertex {
+            position: [0.0, 0.0],
+            uv: [0.0, 0.0],
+            color: self.tint,
+        }; VERTICES_PER_QUAD];
+
+        for (index, (x, y)) in offsets.iter().enumerate() {
+            corners[index].position = [
+                self.position[0] + x * cos - y * sin,
+                self.position[1] + x * sin + y * cos,
+            ];
+            corners[index].uv = uvs[index];
+        }
+
+        corners
+    }
+}
+
+// A run of consecutive indices that share a texture, and so can be issued as one indexed draw.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawBatch {
+    pub texture: DescriptorSet,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+struct FrameBuffers {
+    vertices: DustBuffer,
+    indices: DustBuffer,
+    quad_capacity: usize,
+}
+
+// *** SpriteBatch
+//
+// Collects quads over the course of a frame, then sorts them by layer and texture and writes them
+// into that frame's vertex and index buffers.  Each frame in flight gets its own pair of host
+// mapped buffers, so the CPU can fill frame N+1 while the GPU is still reading frame N.
+pub struct SpriteBatch {
+    quads: Vec<Quad>,
+    frames: Vec<Option<FrameBuffers>>,
+    batches: Vec<DrawBatch>,
+}
+
+pub fn new(frames_in_flight: usize) -> SpriteBatch {
+    let mut frames = Vec::with_capacity(frames_in_flight);
+    frames.resize_with(frames_in_flight, || None);
+
+    SpriteBatch {
+        quads: Vec::new(),
+        frames,
+        batches: Vec::new(),
+    }
+}
+
+impl SpriteBatch {
+    pub fn push(&mut self, quad: Quad) {
+        self.quads.push(quad);
+    }
+
+    // Drops the queued quads without drawing them.
+    pub fn clear(&mut self) {
+        self.quads.clear();
+    }
+
+    // *** prepare(&mut self, ctxt: &VkContext, frame: usize) -> Result<&[DrawBatch], DustError>
+    //
+    // Sorts the queued quads, writes them into the buffers belonging to the given frame and
+    // empties the queue.  The frame's buffers must no longer be in use by the GPU - i.e. the
+    // fence for the last submission that used this frame index must have signalled.
+    //
+    pub fn prepare(&mut self, ctxt: &VkContext, frame: usize) -> Result<&[DrawBatch], DustError> {
+        let (vertices, indices) = build_batches(&mut self.quads, &mut self.batches);
+
+        if self.quads.is_empty() {
+            return Ok(&self.batches);
+        }
+
+        let quad_count = self.quads.len();
+        let frame_buffers = &mut self.frames[frame];
+
+        let needs_growth = match frame_buffers {
+            Some(buffers) => buffers.quad_capacity < quad_count,
+            None => true,
+        };
+
+        if needs_growth {
+            let quad_capacity =
+                std::cmp::max(INITIAL_QUAD_CAPACITY, quad_count.next_power_of_two());
+            debug!(
+                "Growing sprite batch buffers for frame {} to {} quads.",
+                frame, quad_capacity
+            );
+            *frame_buffers = Some(make_frame_buffers(ctxt, quad_capacity)?);
+        }
+
+        let buffers = frame_buffers.as_ref().unwrap();
+        buffers.vertices.write(0, &vertices)?;
+        buffers.indices.write(0, &indices)?;
+
+        self.quads.clear();
+
+        Ok(&self.batches)
+    }
+
+    // *** record(&self, ctxt: &VkContext, command_buffer: CommandBuffer, pipeline_layout: PipelineLayout, frame: usize, target_extent: Extent2D)
+    //
+    // Records the draws produced by the last call to prepare for this frame.  The sprite pipeline
+    // must already be bound, and the command buffer must be inside a render pass.
+    //
+    pub fn record(
+        &self,
+        ctxt: &VkContext,
+        command_buffer: CommandBuffer,
+        pipeline_layout: PipelineLayout,
+        frame: usize,
+        target_extent: Extent2D,
+    ) {
+        let buffers = match (&self.frames[frame], self.batches.is_empty()) {
+            (Some(buffers), false) => buffers,
+            _ => {
+                return;
+            }
+        };
+
+        let screen_size = [target_extent.width as f32, target_extent.height as f32];
+        let push_constants: Vec<u8> = screen_size
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+
+        unsafe {
+            ctxt.logical_device.cmd_push_constants(
+                command_buffer,
+                pipeline_layout,
+                ShaderStageFlags::VERTEX,
+                0,
+                &push_constants,
+            );
+            ctxt.logical_device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[buffers.vertices.buffer],
+                &[0],
+            );
+            ctxt.logical_device.cmd_bind_index_buffer(
+                command_buffer,
+                buffers.indices.buffer,
+                0,
+                IndexType::UINT32,
+            );
+
+            for batch in &self.batches {
+                ctxt.logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    0,
+                    &[batch.texture],
+                    &[],
+                );
+                ctxt.logical_device.cmd_draw_indexed(
+                    command_buffer,
+                    batch.index_count,
+                    1,
+                    batch.first_index,
+                    0,
+                    0,
+                );
+            }
+        }
+    }
+}
+
+// Sorts quads by layer and then texture, lays them out as vertices and indices, and fills batches
+// with one DrawBatch per run of quads sharing a texture.  Needs no device, unlike the rest of
+// prepare.
+fn build_batches(
+    quads: &mut [Quad],
+    batches: &mut Vec<DrawBatch>,
+) -> (Vec<SpriteVertex>, Vec<u32>) {
+    batches.clear();
+
+    // Stable sort, so that quads on the same layer with the same texture keep their submission
+    // order.
+    quads.sort_by_key(|quad| (quad.layer, quad.texture.as_raw()));
+
+    let mut vertices = Vec::with_capacity(quads.len() * VERTICES_PER_QUAD);
+    let mut indices = Vec::with_capacity(quads.len() * INDICES_PER_QUAD);
+
+    for quad in quads.iter() {
+        let base = vertices.len() as u32;
+        vertices.extend_from_slice(&quad.corners());
+        indices.extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 3, base]);
+
+        match batches.last_mut() {
+            Some(batch) if batch.texture == quad.texture => {
+                batch.index_count += INDICES_PER_QUAD as u32;
+            }
+            _ => {
+                batches.push(DrawBatch {
+                    texture: quad.texture,
+                    first_index: (indices.len() - INDICES_PER_QUAD) as u32,
+                    index_count: INDICES_PER_QUAD as u32,
+                });
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+fn make_frame_buffers(ctxt: &VkContext, quad_capacity: usize) -> Result<FrameBuffers, DustError> {
+    let vertex_bytes = (quad_capacity * VERTICES_PER_QUAD * size_of::<SpriteVertex>()) as u64;
+    let index_bytes = (quad_capacity * INDICES_PER_QUAD * size_of::<u32>()) as u64;
+
+    Ok(FrameBuffers {
+        vertices: transfer::make_mapped_buffer(
+            ctxt,
+            vertex_bytes,
+            BufferUsageFlags::VERTEX_BUFFER,
+        )?,
+        indices: transfer::make_mapped_buffer(ctxt, index_bytes, BufferUsageFlags::INDEX_BUFFER)?,
+        quad_capacity,
+    })
+}
+
+pub fn vertex_binding_descriptions() -> [VertexInputBindingDescription; 1] {
+    [VertexInputBindingDescription::default()
+        .binding(0)
+        .stride(size_of::<SpriteVertex>() as u32)
+        .input_rate(VertexInputRate::VERTEX)]
+}
+
+pub fn vertex_attribute_descriptions() -> [VertexInputAttributeDescription; 3] {
+    [
+        VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(0)
+            .format(Format::R32G32_SFLOAT)
+            .offset(offset_of!(SpriteVertex, position) as u32),
+        VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(1)
+            .format(Format::R32G32_SFLOAT)
+            .offset(offset_of!(SpriteVertex, uv) as u32),
+        VertexInputAttributeDescription::default()
+            .binding(0)
+            .location(2)
+            .format(Format::R32G32B32A32_SFLOAT)
+            .offset(offset_of!(SpriteVertex, color) as u32),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(raw: u64) -> DescriptorSet {
+        DescriptorSet::from_raw(raw)
+    }
+
+    fn quad(raw_texture: u64, layer: i32, x: f32) -> Quad {
+        Quad::new(texture(raw_texture), [x, 0.0], [2.0, 2.0]).layer(layer)
+    }
+
+    fn batch_summary(batches: &[DrawBatch]) -> Vec<(u64, u32, u32)> {
+        batches
+            .iter()
+            .map(|batch| (batch.texture.as_raw(), batch.first_index, batch.index_count))
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_layer_then_texture() {
+        let mut quads = vec![quad(2, 1, 0.0), quad(1, 1, 1.0), quad(3, 0, 2.0)];
+        let mut batches = Vec::new();
+        build_batches(&mut quads, &mut batches);
+
+        let order: Vec<(i32, u64)> = quads
+            .iter()
+            .map(|quad| (quad.layer, quad.texture.as_raw()))
+            .collect();
+        assert_eq!(order, vec![(0, 3), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn keeps_submission_order_within_a_layer_and_texture() {
+        let mut quads = vec![quad(1, 0, 0.0), quad(2, 0, 1.0), quad(1, 0, 2.0)];
+        let mut batches = Vec::new();
+        build_batches(&mut quads, &mut batches);
+
+        let positions: Vec<f32> = quads.iter().map(|quad| quad.position[0]).collect();
+        assert_eq!(positions, vec![0.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn merges_runs_that_share_a_texture() {
+        let mut quads = vec![
+            quad(1, 0, 0.0),
+            quad(1, 0, 1.0),
+            quad(2, 0, 2.0),
+            // Same texture on the next layer up follows straight on, so it joins the run.
+            quad(2, 1, 3.0),
+            quad(3, 1, 4.0),
+        ];
+        let mut batches = Vec::new();
+        let (vertices, indices) = build_batches(&mut quads, &mut batches);
+
+        assert_eq!(vertices.len(), 5 * VERTICES_PER_QUAD);
+        assert_eq!(indices.len(), 5 * INDICES_PER_QUAD);
+        assert_eq!(
+            batch_summary(&batches),
+            vec![(1, 0, 12), (2, 12, 12), (3, 24, 6)]
+        );
+    }
+
+    #[test]
+    fn a_higher_layer_splits_a_texture_run() {
+        let mut quads = vec![quad(1, 0, 0.0), quad(2, 1, 1.0), quad(1, 2, 2.0)];
+        let mut batches = Vec::new();
+        build_batches(&mut quads, &mut batches);
+
+        assert_eq!(
+            batch_summary(&batches),
+            vec![(1, 0, 6), (2, 6, 6), (1, 12, 6)]
+        );
+    }
+
+    #[test]
+    fn indexes_each_quad_from_its_own_vertices() {
+        let mut quads = vec![quad(1, 0, 0.0), quad(1, 0, 1.0)];
+        let mut batches = Vec::new();
+        let (_, indices) = build_batches(&mut quads, &mut batches);
+
+        assert_eq!(indices, vec![0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4]);
+    }
+
+    #[test]
+    fn starts_over_on_each_build() {
+        let mut batches = Vec::new();
+        build_batches(&mut [quad(1, 0, 0.0)], &mut batches);
+        let (vertices, indices) = build_batches(&mut [], &mut batches);
+
+        assert!(vertices.is_empty());
+        assert!(indices.is_empty());
+        assert!(batches.is_empty());
+    }
+}
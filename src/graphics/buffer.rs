@@ -0,0 +1,87 @@
+use std::{ffi::c_void, sync::Arc};
+
+use ash::{
+    vk::{Buffer, DeviceMemory, DeviceSize},
+    Device,
+};
+
+use crate::dust_errors::DustError;
+
+// *** DustBuffer
+//
+// Counterpart to DustImage for buffers.  Owns the buffer, its backing memory and - for buffers
+// created in host visible memory - the persistent mapping of that memory.  Everything is
+// released on drop, so the caller must make sure the GPU is done with the buffer first.
+pub struct DustBuffer {
+    pub buffer: Buffer,
+    pub size: DeviceSize,
+    memory: DeviceMemory,
+    mapped: Option<*mut c_void>,
+    logical_device: Arc<Device>,
+}
+
+pub fn new(
+    buffer: Buffer,
+    size: DeviceSize,
+    memory: DeviceMemory,
+    mapped: Option<*mut c_void>,
+    logical_device: Arc<Device>,
+) -> DustBuffer {
+    DustBuffer {
+        buffer,
+        size,
+        memory,
+        mapped,
+        logical_device,
+    }
+}
+
+// The mapping is just an address in this process; nothing ties it to the thread that made it.
+// Writes go through &self, so it is not Sync.
+unsafe impl Send for DustBuffer {}
+
+impl DustBuffer {
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    // Copies data into the mapped memory of the buffer, starting offset_in_bytes into the
+    // buffer.  Fails, writing nothing, if the buffer is not host visible or if the write would
+    // run off the end of the buffer.
+    pub fn write<T>(&self, offset_in_bytes: DeviceSize, data: &[T]) -> Result<(), DustError>
+    where
+        T: Sized + Copy + Clone,
+    {
+        let size_in_bytes = std::mem::size_of_val(data) as DeviceSize;
+
+        if offset_in_bytes + size_in_bytes > self.size {
+            return Err(DustError::BufferWrite(format!(
+                "{} bytes at offset {} would run off the end of a {} byte buffer",
+                size_in_bytes, offset_in_bytes, self.size
+            )));
+        }
+
+        match self.mapped {
+            Some(ptr) => unsafe {
+                let dst = (ptr as *mut u8).add(offset_in_bytes as usize) as *mut T;
+                std::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+                Ok(())
+            },
+            None => Err(DustError::BufferWrite(String::from(
+                "the buffer is not host mapped",
+            ))),
+        }
+    }
+}
+
+impl Drop for DustBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            if self.mapped.is_some() {
+                self.logical_device.unmap_memory(self.memory);
+            }
+            self.logical_device.destroy_buffer(self.buffer, None);
+            self.logical_device.free_memory(self.memory, None);
+        }
+    }
+}
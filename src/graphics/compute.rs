@@ -0,0 +1,581 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use ash::{
+    vk::{
+        AccessFlags2, CommandBuffer, ComputePipelineCreateInfo, DependencyFlags, DependencyInfo,
+        DescriptorBufferInfo, DescriptorImageInfo, DescriptorSet, DescriptorSetLayout,
+        DescriptorType, ImageAspectFlags, ImageLayout, ImageMemoryBarrier2, ImageSubresourceRange,
+        MemoryBarrier2, Pipeline, PipelineBindPoint, PipelineCreateFlags, PipelineLayout,
+        PipelineStageFlags2, ShaderStageFlags, WriteDescriptorSet, QUEUE_FAMILY_IGNORED,
+    },
+    Device,
+};
+use log::{debug, error};
+
+use crate::{
+    dust_errors::DustError,
+    setup::{debug, instance::VkContext},
+};
+
+use super::{
+    buffer::DustBuffer,
+    descriptors,
+    image::DustImage,
+    pipeline_cache, pipelines,
+    shaders::{self, ShaderType},
+};
+
+// *** ComputePipeline
+//
+// A compute pipeline, the layout reflected from its shader, and the workgroup size the shader
+// declares.  The descriptor set layouts come from the descriptor layout cache; the pipeline and
+// its layout belong to the compute registry.  None of it needs destroying by the caller.
+#[derive(Clone, Debug)]
+pub struct ComputePipeline {
+    pub name: String,
+    pub pipeline: Pipeline,
+    pub layout: PipelineLayout,
+    pub local_size: [u32; 3],
+    set_layouts: Vec<DescriptorSetLayout>,
+    shader_bindings: Vec<(u32, u32, DescriptorType)>,
+    push_constant_size: u32,
+}
+
+impl ComputePipeline {
+    // The number of workgroups needed to cover width x height x depth invocations, rounding up.
+    // Shaders dispatched this way must ignore the invocations that fall off the edge.
+    pub fn groups_for(&self, width: u32, height: u32, depth: u32) -> [u32; 3] {
+        [
+            width.div_ceil(self.local_size[0]),
+            height.div_ceil(self.local_size[1]),
+            depth.div_ceil(self.local_size[2]),
+        ]
+    }
+}
+
+// What a dispatch binds to each descriptor its shader declares.  Storage images are moved into
+// GENERAL for the dispatch; sampled images into SHADER_READ_ONLY_OPTIMAL, and must have been made
+// into textures so that they have a sampler.
+#[derive(Clone, Copy)]
+pub enum ComputeResource<'a> {
+    StorageImage(&'a DustImage),
+    SampledImage(&'a DustImage),
+    StorageBuffer(&'a DustBuffer),
+    UniformBuffer(&'a DustBuffer),
+}
+
+impl ComputeResource<'_> {
+    fn descriptor_type(&self) -> DescriptorType {
+        match self {
+            ComputeResource::StorageImage(_) => DescriptorType::STORAGE_IMAGE,
+            ComputeResource::SampledImage(_) => DescriptorType::COMBINED_IMAGE_SAMPLER,
+            ComputeResource::StorageBuffer(_) => DescriptorType::STORAGE_BUFFER,
+            ComputeResource::UniformBuffer(_) => DescriptorType::UNIFORM_BUFFER,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ComputeBinding<'a> {
+    pub set: u32,
+    pub binding: u32,
+    pub resource: ComputeResource<'a>,
+}
+
+impl<'a> ComputeBinding<'a> {
+    pub fn new(set: u32, binding: u32, resource: ComputeResource<'a>) -> ComputeBinding<'a> {
+        ComputeBinding {
+            set,
+            binding,
+            resource,
+        }
+    }
+}
+
+static COMPUTE: OnceLock<Mutex<ComputeState>> = OnceLock::new();
+
+struct ComputeState {
+    logical_device: Arc<Device>,
+    built: HashMap<String, ComputePipeline>,
+}
+
+// Called again with the new device when the device is rebuilt; destroy() has already emptied
+// the pipelines, and they are built again on first use.
+pub fn init(logical_device: Arc<Device>) {
+    if let Some(state) = COMPUTE.get() {
+        state.lock().unwrap().logical_device = logical_device;
+        return;
+    }
+
+    let state = ComputeState {
+        logical_device,
+        built: HashMap::new(),
+    };
+
+    if COMPUTE.set(Mutex::new(state)).is_err() {
+        panic!("Unable to set the compute pipeline static.");
+    }
+}
+
+pub fn destroy() {
+    match COMPUTE.get() {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            let device = state.logical_device.clone();
+
+            state
+                .built
+                .drain()
+                .for_each(|(_, built)| destroy_built(&device, &built));
+        }
+        None => {
+            error!("The compute pipelines were never initialized; nothing to destroy.");
+        }
+    }
+}
+
+fn with_state<R>(action: impl FnOnce(&mut ComputeState) -> R) -> Result<R, DustError> {
+    match COMPUTE.get() {
+        Some(state) => Ok(action(&mut state.lock().unwrap())),
+        None => Err(DustError::NotInitialized("the compute pipelines")),
+    }
+}
+
+// *** get(ctxt: &VkContext, shader_name: &str) -> Result<ComputePipeline, DustError>
+//
+// A compute pipeline is nothing more than its shader, so compute pipelines are looked up by
+// shader name and built on first use.  Asking for a shader that does not exist, or is not a
+// compute shader, is an error.
+//
+pub fn get(ctxt: &VkContext, shader_name: &str) -> Result<ComputePipeline, DustError> {
+    with_state(|state| {
+        if let Some(built) = state.built.get(shader_name) {
+            return Ok(built.clone());
+        }
+
+        let built = build_pipeline(ctxt, shader_name)?;
+
+        debug!("Built compute pipeline {}", shader_name);
+        state.built.insert(String::from(shader_name), built.clone());
+
+        Ok(built)
+    })?
+}
+
+// *** shaders_reloaded(ctxt: &VkContext, shader_names: &[String])
+//
+// Rebuilds any compute pipeline made from one of the named shaders, keeping the previous pipeline
+// if the rebuild fails.  Called by pipelines::apply_shader_reloads with the device already idle.
+//
+pub fn shaders_reloaded(ctxt: &VkContext, shader_names: &[String]) -> Result<(), DustError> {
+    with_state(|state| {
+        for shader_name in shader_names {
+            if !state.built.contains_key(shader_name) {
+                continue;
+            }
+
+            match build_pipeline(ctxt, shader_name) {
+                Ok(built) => {
+                    if let Some(previous) = state.built.insert(shader_name.clone(), built) {
+                        destroy_built(&state.logical_device, &previous);
+                    }
+                    debug!(
+                        "Rebuilt compute pipeline {} after a shader reload",
+                        shader_name
+                    );
+                }
+                Err(msg) => {
+                    error!(
+                        "Rebuilding the {} compute pipeline failed; keeping the previous one: {}",
+                        shader_name, msg
+                    );
+                }
+            }
+        }
+    })
+}
+
+// *** dispatch(ctxt, command_buffer, frame, pipeline, bindings, push_constants, groups) -> Result<(), DustError>
+//
+// Records a compute dispatch into command_buffer, along with the barriers that keep it in order
+// with the work around it:
+//
+// 1.  Every image bound is moved into the layout the dispatch needs, after any earlier graphics,
+//     compute or transfer work has finished writing it.  Buffers get the same treatment through a
+//     global memory barrier.
+// 2.  The descriptor sets are allocated for the given frame, written and bound; the push
+//     constants follow, and then the dispatch itself.
+// 3.  Storage images written by the dispatch are moved into SHADER_READ_ONLY_OPTIMAL, and the
+//     dispatch's writes are made visible to vertex input, indirect draws and every shader stage,
+//     so whatever is recorded next can consume the results without further ceremony.
+//
+// Every descriptor the shader declares must be given exactly once, with the matching type.
+//
+pub fn dispatch<T>(
+    ctxt: &VkContext,
+    command_buffer: CommandBuffer,
+    frame: usize,
+    pipeline: &ComputePipeline,
+    bindings: &[ComputeBinding],
+    push_constants: &[T],
+    groups: [u32; 3],
+) -> Result<(), DustError>
+where
+    T: Sized + Copy + Clone,
+{
+    check_bindings(pipeline, bindings)?;
+
+    let push_constant_bytes = unsafe {
+        std::slice::from_raw_parts(
+            push_constants.as_ptr() as *const u8,
+            std::mem::size_of_val(push_constants),
+        )
+    };
+    if push_constant_bytes.len() as u32 > pipeline.push_constant_size {
+        return Err(DustError::InvalidDispatch(format!(
+            "{} bytes of push constants were given to a shader that declares {}",
+            push_constant_bytes.len(),
+            pipeline.push_constant_size
+        )));
+    }
+
+    let descriptor_sets = write_descriptor_sets(ctxt, frame, pipeline, bindings)?;
+
+    let before = before_dispatch_barriers(bindings);
+    let after = after_dispatch_barriers(bindings);
+    let global_before = [MemoryBarrier2::default()
+        .src_stage_mask(PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+        .dst_access_mask(AccessFlags2::SHADER_READ | AccessFlags2::SHADER_WRITE)];
+    let global_after = [MemoryBarrier2::default()
+        .src_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+        .src_access_mask(AccessFlags2::SHADER_WRITE)
+        .dst_stage_mask(
+            PipelineStageFlags2::DRAW_INDIRECT
+                | PipelineStageFlags2::VERTEX_INPUT
+                | PipelineStageFlags2::ALL_GRAPHICS
+                | PipelineStageFlags2::COMPUTE_SHADER,
+        )
+        .dst_access_mask(
+            AccessFlags2::INDIRECT_COMMAND_READ
+                | AccessFlags2::VERTEX_ATTRIBUTE_READ
+                | AccessFlags2::INDEX_READ
+                | AccessFlags2::UNIFORM_READ
+                | AccessFlags2::SHADER_READ,
+        )];
+
+    debug::begin_label(command_buffer, &format!("dispatch {}", pipeline.name));
+    unsafe {
+        ctxt.logical_device.cmd_pipeline_barrier2(
+            command_buffer,
+            &DependencyInfo::default()
+                .memory_barriers(&global_before)
+                .image_memory_barriers(&before)
+                .dependency_flags(DependencyFlags::empty()),
+        );
+
+        ctxt.logical_device.cmd_bind_pipeline(
+            command_buffer,
+            PipelineBindPoint::COMPUTE,
+            pipeline.pipeline,
+        );
+        if !descriptor_sets.is_empty() {
+            ctxt.logical_device.cmd_bind_descriptor_sets(
+                command_buffer,
+                PipelineBindPoint::COMPUTE,
+                pipeline.layout,
+                0,
+                &descriptor_sets,
+                &[],
+            );
+        }
+        if !push_constant_bytes.is_empty() {
+            ctxt.logical_device.cmd_push_constants(
+                command_buffer,
+                pipeline.layout,
+                ShaderStageFlags::COMPUTE,
+                0,
+                push_constant_bytes,
+            );
+        }
+        ctxt.logical_device
+            .cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]);
+
+        ctxt.logical_device.cmd_pipeline_barrier2(
+            command_buffer,
+            &DependencyInfo::default()
+                .memory_barriers(&global_after)
+                .image_memory_barriers(&after)
+                .dependency_flags(DependencyFlags::empty()),
+        );
+    }
+    debug::end_label(command_buffer);
+
+    for binding in bindings {
+        match binding.resource {
+            ComputeResource::StorageImage(image) | ComputeResource::SampledImage(image) => {
+                image.set_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn build_pipeline(ctxt: &VkContext, shader_name: &str) -> Result<ComputePipeline, DustError> {
+    let shader = match shaders::shader_by_name(shader_name) {
+        Some(shader) => shader,
+        None => {
+            return Err(DustError::ShaderNotFound(String::from(shader_name)));
+        }
+    };
+
+    if shader.shader_type != ShaderType::Compute {
+        return Err(DustError::NotAComputeShader(String::from(shader_name)));
+    }
+
+    let local_size = match shader.reflection.local_size {
+        Some(local_size) => local_size,
+        None => {
+            return Err(DustError::NotAComputeShader(String::from(shader_name)));
+        }
+    };
+
+    let stages = [shader.clone()];
+    let set_layouts = shaders::descriptor_set_layouts(&stages)?;
+    let layout = pipelines::create_reflected_pipeline_layout(ctxt, &stages)?;
+
+    let stage_info = pipelines::fill_shader_stage_infos(&stages).remove(0);
+    let create_info = ComputePipelineCreateInfo::default()
+        .flags(PipelineCreateFlags::empty())
+        .stage(stage_info)
+        .layout(layout);
+
+    let pipeline = match unsafe {
+        ctxt.logical_device
+            .create_compute_pipelines(pipeline_cache::handle(), &[create_info], None)
+    } {
+        Ok(pipelines) => *pipelines.first().unwrap(),
+        Err((_, msg)) => {
+            unsafe { ctxt.logical_device.destroy_pipeline_layout(layout, None) };
+            return Err(DustError::CreatePipelineFailed(msg));
+        }
+    };
+
+    debug::name_object(pipeline, shader_name);
+
+    Ok(ComputePipeline {
+        name: String::from(shader_name),
+        pipeline,
+        layout,
+        local_size,
+        set_layouts,
+        shader_bindings: shader
+            .descriptor_bindings()
+            .iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type))
+            .collect(),
+        push_constant_size: shader
+            .push_constant_range()
+            .map(|range| range.offset + range.size)
+            .unwrap_or(0),
+    })
+}
+
+fn destroy_built(device: &Device, built: &ComputePipeline) {
+    unsafe {
+        device.destroy_pipeline(built.pipeline, None);
+        device.destroy_pipeline_layout(built.layout, None);
+    }
+}
+
+fn check_bindings(
+    pipeline: &ComputePipeline,
+    bindings: &[ComputeBinding],
+) -> Result<(), DustError> {
+    for (set, binding, descriptor_type) in &pipeline.shader_bindings {
+        let given: Vec<&ComputeBinding> = bindings
+            .iter()
+            .filter(|given| given.set == *set && given.binding == *binding)
+            .collect();
+
+        match given.as_slice() {
+            [given] if given.resource.descriptor_type() == *descriptor_type => {}
+            [given] => {
+                return Err(DustError::InvalidDispatch(format!(
+                    "set {}, binding {} is a {:?}, but a {:?} was bound to it",
+                    set,
+                    binding,
+                    descriptor_type,
+                    given.resource.descriptor_type()
+                )));
+            }
+            [] => {
+                return Err(DustError::InvalidDispatch(format!(
+                    "set {}, binding {} was left unbound",
+                    set, binding
+                )));
+            }
+            _ => {
+                return Err(DustError::InvalidDispatch(format!(
+                    "set {}, binding {} was bound more than once",
+                    set, binding
+                )));
+            }
+        }
+    }
+
+    if bindings.len() != pipeline.shader_bindings.len() {
+        return Err(DustError::InvalidDispatch(format!(
+            "{} resources were bound to a shader that declares {} descriptors",
+            bindings.len(),
+            pipeline.shader_bindings.len()
+        )));
+    }
+
+    Ok(())
+}
+
+fn write_descriptor_sets(
+    ctxt: &VkContext,
+    frame: usize,
+    pipeline: &ComputePipeline,
+    bindings: &[ComputeBinding],
+) -> Result<Vec<DescriptorSet>, DustError> {
+    let descriptor_sets = pipeline
+        .set_layouts
+        .iter()
+        .map(|layout| descriptors::allocate_for_frame(frame, *layout))
+        .collect::<Result<Vec<DescriptorSet>, DustError>>()?;
+
+    // The infos have to stay put while the writes point at them, so they are all gathered before
+    // any write is built.
+    let image_infos: Vec<DescriptorImageInfo> = bindings
+        .iter()
+        .map(|binding| match binding.resource {
+            ComputeResource::StorageImage(image) => Ok(DescriptorImageInfo::default()
+                .image_view(image.view)
+                .image_layout(ImageLayout::GENERAL)),
+            ComputeResource::SampledImage(image) => {
+                if !image.has_sampler() {
+                    return Err(DustError::InvalidDispatch(format!(
+                        "the sampled image at set {}, binding {} has not been made into a texture",
+                        binding.set, binding.binding
+                    )));
+                }
+                Ok(image.descriptor_image_info())
+            }
+            _ => Ok(DescriptorImageInfo::default()),
+        })
+        .collect::<Result<Vec<DescriptorImageInfo>, DustError>>()?;
+    let buffer_infos: Vec<DescriptorBufferInfo> = bindings
+        .iter()
+        .map(|binding| match binding.resource {
+            ComputeResource::StorageBuffer(buffer) | ComputeResource::UniformBuffer(buffer) => {
+                DescriptorBufferInfo::default()
+                    .buffer(buffer.buffer)
+                    .offset(0)
+                    .range(buffer.size)
+            }
+            _ => DescriptorBufferInfo::default(),
+        })
+        .collect();
+
+    let writes: Vec<WriteDescriptorSet> = bindings
+        .iter()
+        .enumerate()
+        .map(|(index, binding)| {
+            let write = WriteDescriptorSet::default()
+                .dst_set(descriptor_sets[binding.set as usize])
+                .dst_binding(binding.binding)
+                .dst_array_element(0)
+                .descriptor_type(binding.resource.descriptor_type());
+
+            match binding.resource {
+                ComputeResource::StorageImage(_) | ComputeResource::SampledImage(_) => {
+                    write.image_info(std::slice::from_ref(&image_infos[index]))
+                }
+                ComputeResource::StorageBuffer(_) | ComputeResource::UniformBuffer(_) => {
+                    write.buffer_info(std::slice::from_ref(&buffer_infos[index]))
+                }
+            }
+        })
+        .collect();
+
+    unsafe { ctxt.logical_device.update_descriptor_sets(&writes, &[]) };
+
+    Ok(descriptor_sets)
+}
+
+fn color_subresource_range() -> ImageSubresourceRange {
+    ImageSubresourceRange::default()
+        .aspect_mask(ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+}
+
+fn before_dispatch_barriers<'a>(bindings: &[ComputeBinding]) -> Vec<ImageMemoryBarrier2<'a>> {
+    bindings
+        .iter()
+        .filter_map(|binding| {
+            let (image, new_layout, dst_access) = match binding.resource {
+                ComputeResource::StorageImage(image) => (
+                    image,
+                    ImageLayout::GENERAL,
+                    AccessFlags2::SHADER_STORAGE_READ | AccessFlags2::SHADER_STORAGE_WRITE,
+                ),
+                ComputeResource::SampledImage(image) => (
+                    image,
+                    ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    AccessFlags2::SHADER_SAMPLED_READ,
+                ),
+                _ => return None,
+            };
+
+            Some(
+                ImageMemoryBarrier2::default()
+                    .src_stage_mask(PipelineStageFlags2::ALL_COMMANDS)
+                    .src_access_mask(AccessFlags2::MEMORY_WRITE)
+                    .dst_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                    .dst_access_mask(dst_access)
+                    .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .old_layout(image.layout())
+                    .new_layout(new_layout)
+                    .image(image.image)
+                    .subresource_range(color_subresource_range()),
+            )
+        })
+        .collect()
+}
+
+fn after_dispatch_barriers<'a>(bindings: &[ComputeBinding]) -> Vec<ImageMemoryBarrier2<'a>> {
+    bindings
+        .iter()
+        .filter_map(|binding| match binding.resource {
+            ComputeResource::StorageImage(image) => Some(
+                ImageMemoryBarrier2::default()
+                    .src_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                    .src_access_mask(AccessFlags2::SHADER_STORAGE_WRITE)
+                    .dst_stage_mask(
+                        PipelineStageFlags2::ALL_GRAPHICS | PipelineStageFlags2::COMPUTE_SHADER,
+                    )
+                    .dst_access_mask(AccessFlags2::SHADER_SAMPLED_READ)
+                    .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                    .old_layout(ImageLayout::GENERAL)
+                    .new_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image(image.image)
+                    .subresource_range(color_subresource_range()),
+            ),
+            _ => None,
+        })
+        .collect()
+}
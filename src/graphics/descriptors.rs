@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use ash::vk::{
+    DescriptorPool, DescriptorPoolCreateFlags, DescriptorPoolCreateInfo, DescriptorPoolResetFlags,
+    DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo, DescriptorSetLayout,
+    DescriptorSetLayoutBinding, DescriptorSetLayoutCreateFlags, DescriptorSetLayoutCreateInfo,
+    DescriptorType, Fence, ShaderStageFlags,
+};
+use ash::Device;
+use log::{debug, error};
+
+use crate::dust_errors::DustError;
+
+// Sets allocated out of the first pool each allocator creates.  Every pool after that is half as
+// big again as the last, up to MAX_SETS_PER_POOL.
+const INITIAL_SETS_PER_POOL: u32 = 16;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+// How many descriptors of each type a pool reserves, per set it can hold.  Textures dominate
+// everything we draw right now, so combined image samplers get the lion's share.
+const POOL_RATIOS: [(DescriptorType, f32); 7] = [
+    (DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (DescriptorType::UNIFORM_BUFFER, 2.0),
+    (DescriptorType::STORAGE_BUFFER, 1.0),
+    (DescriptorType::STORAGE_IMAGE, 1.0),
+    (DescriptorType::SAMPLED_IMAGE, 1.0),
+    (DescriptorType::SAMPLER, 1.0),
+    (DescriptorType::INPUT_ATTACHMENT, 1.0),
+];
+
+static DESCRIPTORS: OnceLock<Mutex<DescriptorState>> = OnceLock::new();
+
+struct DescriptorState {
+    logical_device: Arc<Device>,
+    persistent: DescriptorAllocator,
+    // The pool each persistent set came from, for free_persistent to hand it back to.
+    persistent_pools: HashMap<DescriptorSet, DescriptorPool>,
+    frames: Vec<DescriptorAllocator>,
+    layouts: HashMap<Vec<LayoutBindingKey>, DescriptorSetLayout>,
+}
+
+// DescriptorSetLayoutBinding carries a raw pointer for immutable samplers, so it cannot be hashed
+// directly.  This is the part of it that identifies a layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct LayoutBindingKey {
+    binding: u32,
+    descriptor_type: i32,
+    descriptor_count: u32,
+    stage_flags: u32,
+}
+
+impl From<&DescriptorSetLayoutBinding<'_>> for LayoutBindingKey {
+    fn from(binding: &DescriptorSetLayoutBinding) -> Self {
+        LayoutBindingKey {
+            binding: binding.binding,
+            descriptor_type: binding.descriptor_type.as_raw(),
+            descriptor_count: binding.descriptor_count,
+            stage_flags: binding.stage_flags.as_raw(),
+        }
+    }
+}
+
+// *** DescriptorAllocator
+//
+// A list of descriptor pools that grows on demand.  Allocation always goes to the newest pool;
+// when that pool reports ERROR_OUT_OF_POOL_MEMORY or ERROR_FRAGMENTED_POOL it is retired to the
+// full list and a fresh, larger pool takes its place.  Resetting returns every pool to service
+// and frees every set allocated from them in one go.  An allocator made with FREE_DESCRIPTOR_SET
+// can also free sets one at a time, which puts a retired pool back into service.
+struct DescriptorAllocator {
+    ready_pools: Vec<DescriptorPool>,
+    full_pools: Vec<DescriptorPool>,
+    sets_per_pool: u32,
+    pool_flags: DescriptorPoolCreateFlags,
+}
+
+fn new_allocator(pool_flags: DescriptorPoolCreateFlags) -> DescriptorAllocator {
+    DescriptorAllocator {
+        ready_pools: Vec::new(),
+        full_pools: Vec::new(),
+        sets_per_pool: INITIAL_SETS_PER_POOL,
+        pool_flags,
+    }
+}
+
+impl DescriptorAllocator {
+    // Returns the set along with the pool it came from.
+    fn allocate(
+        &mut self,
+        device: &Device,
+        layout: DescriptorSetLayout,
+    ) -> Result<(DescriptorSet, DescriptorPool), DustError> {
+        let layouts = [layout];
+
+        loop {
+            let (pool, fresh) = self.current_pool(device)?;
+            let allocate_info = DescriptorSetAllocateInfo::default()
+                .descriptor_pool(pool)
+                .set_layouts(&layouts);
+
+            match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+                Ok(mut sets) => {
+                    return Ok((sets.pop().unwrap(), pool));
+                }
+                // A brand new pool that cannot hold a single set means the layout asks for more
+                // descriptors than our ratios will ever reserve; growing again will not help.
+                Err(msg) if fresh => {
+                    return Err(DustError::vulkan(
+                        "allocating a descriptor set from a fresh pool",
+                        msg,
+                    ));
+                }
+                Err(ash::vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(ash::vk::Result::ERROR_FRAGMENTED_POOL) => {
+                    debug!("Descriptor pool exhausted; retiring it and growing the allocator.");
+                    self.full_pools.push(self.ready_pools.pop().unwrap());
+                }
+                Err(msg) => {
+                    return Err(DustError::vulkan("allocating a descriptor set", msg));
+                }
+            }
+        }
+    }
+
+    fn current_pool(&mut self, device: &Device) -> Result<(DescriptorPool, bool), DustError> {
+        if let Some(pool) = self.ready_pools.last() {
+            return Ok((*pool, false));
+        }
+
+        let pool = make_pool(device, self.sets_per_pool, self.pool_flags)?;
+        self.sets_per_pool = std::cmp::min(MAX_SETS_PER_POOL, self.sets_per_pool * 3 / 2);
+        self.ready_pools.push(pool);
+
+        Ok((pool, true))
+    }
+
+    fn reset(&mut self, device: &Device) -> Result<(), DustError> {
+        for pool in self.ready_pools.iter().chain(self.full_pools.iter()) {
+            if let Err(msg) =
+                unsafe { device.reset_descriptor_pool(*pool, DescriptorPoolResetFlags::empty()) }
+            {
+                return Err(DustError::vulkan("resetting a descriptor pool", msg));
+            }
+        }
+
+        self.ready_pools.append(&mut self.full_pools);
+
+        Ok(())
+    }
+
+    fn free(
+        &mut self,
+        device: &Device,
+        pool: DescriptorPool,
+        set: DescriptorSet,
+    ) -> Result<(), DustError> {
+        if let Err(msg) = unsafe { device.free_descriptor_sets(pool, &[set]) } {
+            return Err(DustError::vulkan("freeing a descriptor set", msg));
+        }
+
+        // The pool has room again.  Put it at the back of the queue, to be used once the pool
+        // currently being allocated from fills up.
+        if let Some(index) = self.full_pools.iter().position(|full| *full == pool) {
+            self.full_pools.remove(index);
+            self.ready_pools.insert(0, pool);
+        }
+
+        Ok(())
+    }
+
+    fn destroy(&mut self, device: &Device) {
+        for pool in self.ready_pools.drain(..).chain(self.full_pools.drain(..)) {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+fn make_pool(
+    device: &Device,
+    max_sets: u32,
+    flags: DescriptorPoolCreateFlags,
+) -> Result<DescriptorPool, DustError> {
+    let pool_sizes: Vec<DescriptorPoolSize> = POOL_RATIOS
+        .iter()
+        .map(|(descriptor_type, ratio)| {
+            DescriptorPoolSize::default()
+                .ty(*descriptor_type)
+                .descriptor_count((ratio * max_sets as f32).ceil() as u32)
+        })
+        .collect();
+
+    let pool_create_info = DescriptorPoolCreateInfo::default()
+        .flags(flags)
+        .max_sets(max_sets)
+        .pool_sizes(&pool_sizes);
+
+    match unsafe { device.create_descriptor_pool(&pool_create_info, None) } {
+        Ok(pool) => Ok(pool),
+        Err(msg) => Err(DustError::vulkan("creating a descriptor pool", msg)),
+    }
+}
+
+// Called again with the new device when the device is rebuilt, which starts the allocators and
+// layout cache over.
+pub fn init(logical_device: Arc<Device>, frames_in_flight: usize) {
+    let mut frames = Vec::with_capacity(frames_in_flight);
+    frames.resize_with(frames_in_flight, || {
+        new_allocator(DescriptorPoolCreateFlags::empty())
+    });
+
+    let state = DescriptorState {
+        logical_device,
+        persistent: new_allocator(DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET),
+        persistent_pools: HashMap::new(),
+        frames,
+        layouts: HashMap::new(),
+    };
+
+    if let Some(previous) = DESCRIPTORS.get() {
+        *previous.lock().unwrap() = state;
+        return;
+    }
+
+    if DESCRIPTORS.set(Mutex::new(state)).is_err() {
+        panic!("Unable to set the descriptor allocator static.");
+    }
+}
+
+pub fn destroy() {
+    match DESCRIPTORS.get() {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            let device = state.logical_device.clone();
+
+            state.persistent.destroy(&device);
+            state.persistent_pools.clear();
+            state
+                .frames
+                .iter_mut()
+                .for_each(|allocator| allocator.destroy(&device));
+            state.layouts.drain().for_each(|(_, layout)| unsafe {
+                device.destroy_descriptor_set_layout(layout, None)
+            });
+        }
+        None => {
+            error!("The descriptor allocator was never initialized; nothing to destroy.");
+        }
+    }
+}
+
+fn with_state<R>(action: impl FnOnce(&mut DescriptorState) -> R) -> Result<R, DustError> {
+    match DESCRIPTORS.get() {
+        Some(state) => Ok(action(&mut state.lock().unwrap())),
+        None => Err(DustError::NotInitialized("the descriptor allocator")),
+    }
+}
+
+// *** allocate_persistent(layout: DescriptorSetLayout) -> Result<DescriptorSet, DustError>
+//
+// For descriptor sets that live as long as the resource they describe - textures, mostly.  These
+// are never reset; each is handed back with free_persistent when its resource goes, or along
+// with everything else when the allocator is destroyed.
+//
+pub fn allocate_persistent(layout: DescriptorSetLayout) -> Result<DescriptorSet, DustError> {
+    with_state(|state| {
+        let device = state.logical_device.clone();
+        let (set, pool) = state.persistent.allocate(&device, layout)?;
+        state.persistent_pools.insert(set, pool);
+        Ok(set)
+    })?
+}
+
+// *** free_persistent(set: DescriptorSet) -> Result<(), DustError>
+//
+// Hands a set from allocate_persistent back to its pool.  Nothing still in flight may be using
+// it.  A set the allocator does not know - one made before the device was rebuilt, whose pool
+// has already gone - is left alone.
+//
+pub fn free_persistent(set: DescriptorSet) -> Result<(), DustError> {
+    with_state(|state| match state.persistent_pools.remove(&set) {
+        Some(pool) => {
+            let device = state.logical_device.clone();
+            state.persistent.free(&device, pool, set)
+        }
+        None => Ok(()),
+    })?
+}
+
+// *** allocate_for_frame(frame: usize, layout: DescriptorSetLayout) -> Result<DescriptorSet, DustError>
+//
+// For descriptor sets that only need to survive until the given frame is next begun.  They are
+// released in bulk by begin_frame, so there is no need (and no way) to free them one at a time.
+//
+pub fn allocate_for_frame(
+    frame: usize,
+    layout: DescriptorSetLayout,
+) -> Result<DescriptorSet, DustError> {
+    with_state(|state| {
+        let device = state.logical_device.clone();
+        state.frames[frame]
+            .allocate(&device, layout)
+            .map(|(set, _)| set)
+    })?
+}
+
+// *** begin_frame(frame: usize, frame_fence: Fence) -> Result<(), DustError>
+//
+// Waits for the fence guarding the previous use of this frame slot, then resets every pool the
+// frame has allocated from.  A null fence means the slot is known to be idle - never submitted,
+// or already waited on - so there is nothing to wait on.  Must be called before anything is
+// allocated for the frame.
+//
+pub fn begin_frame(frame: usize, frame_fence: Fence) -> Result<(), DustError> {
+    with_state(|state| {
+        let device = state.logical_device.clone();
+
+        if frame_fence != Fence::null() {
+            if let Err(msg) = unsafe { device.wait_for_fences(&[frame_fence], true, u64::MAX) } {
+                return Err(DustError::vulkan(
+                    "waiting on a frame before resetting its descriptors",
+                    msg,
+                ));
+            }
+        }
+
+        state.frames[frame].reset(&device)
+    })?
+}
+
+// *** layout_for(bindings: &[DescriptorSetLayoutBinding]) -> Result<DescriptorSetLayout, DustError>
+//
+// Returns a layout for the given bindings, creating it the first time it is asked for.  Layouts
+// are owned by the cache and destroyed along with it - callers must not destroy them.  Bindings
+// with immutable samplers are not supported, as the samplers take no part in the cache key.
+//
+pub fn layout_for(
+    bindings: &[DescriptorSetLayoutBinding],
+) -> Result<DescriptorSetLayout, DustError> {
+    let mut key: Vec<LayoutBindingKey> = bindings.iter().map(LayoutBindingKey::from).collect();
+    key.sort_by_key(|binding| binding.binding);
+
+    with_state(|state| {
+        if let Some(layout) = state.layouts.get(&key) {
+            return Ok(*layout);
+        }
+
+        let create_info = DescriptorSetLayoutCreateInfo::default()
+            .flags(DescriptorSetLayoutCreateFlags::empty())
+            .bindings(bindings);
+
+        let layout = match unsafe {
+            state
+                .logical_device
+                .create_descriptor_set_layout(&create_info, None)
+        } {
+            Ok(layout) => layout,
+            Err(msg) => {
+                return Err(DustError::vulkan("creating a descriptor set layout", msg));
+            }
+        };
+
+        debug!("Caching new descriptor set layout for bindings {:?}", key);
+        state.layouts.insert(key, layout);
+
+        Ok(layout)
+    })?
+}
+
+// Convenience for the common single binding case.
+pub fn single_binding_layout(
+    descriptor_type: DescriptorType,
+    stage_flags: ShaderStageFlags,
+) -> Result<DescriptorSetLayout, DustError> {
+    layout_for(&[DescriptorSetLayoutBinding::default()
+        .binding(0)
+        .descriptor_type(descriptor_type)
+        .descriptor_count(1)
+        .stage_flags(stage_flags)])
+}
@@ -0,0 +1,178 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+// thread::sleep routinely overshoots by a millisecond or more, which at a few hundred frames a
+// second is most of a frame.  The last stretch before a deadline is spun out instead.
+const SPIN_THRESHOLD: Duration = Duration::from_micros(1500);
+
+static LIMITER: OnceLock<Mutex<FrameLimiter>> = OnceLock::new();
+
+// *** FrameLimiter
+//
+// Holds frames to a maximum rate on the CPU, whatever the present mode.  Deadlines advance by a
+// fixed interval rather than from when the last frame happened to finish, so the average rate is
+// the cap even though individual frames jitter.
+//
+#[derive(Clone, Debug)]
+pub struct FrameLimiter {
+    cap: Option<u32>,
+    interval: Option<Duration>,
+    next_frame: Option<Instant>,
+}
+
+impl FrameLimiter {
+    // cap is in frames per second; None, or 0, means no cap.
+    pub fn new(cap: Option<u32>) -> FrameLimiter {
+        let mut limiter = FrameLimiter {
+            cap: None,
+            interval: None,
+            next_frame: None,
+        };
+        limiter.set_cap(cap);
+        limiter
+    }
+
+    pub fn set_cap(&mut self, cap: Option<u32>) {
+        let cap = cap.filter(|fps| *fps > 0);
+        if cap != self.cap {
+            self.cap = cap;
+            self.interval = cap.map(|fps| Duration::from_secs(1) / fps);
+            self.next_frame = None;
+        }
+    }
+
+    pub fn cap(&self) -> Option<u32> {
+        self.cap
+    }
+
+    // *** wait(&mut self)
+    //
+    // Blocks until the next frame is due.  Returns at once with no cap, and on the first frame
+    // after the cap changes.  A frame that finishes more than a whole interval late starts the
+    // schedule over rather than letting the frames after it rush to catch up.
+    //
+    pub fn wait(&mut self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+
+        if let Some(deadline) = self.next_frame {
+            sleep_until(deadline);
+        }
+        self.schedule(interval, Instant::now());
+    }
+
+    // Sets the deadline for the frame after the one starting at now.
+    fn schedule(&mut self, interval: Duration, now: Instant) {
+        self.next_frame = match self.next_frame {
+            Some(deadline) if now <= deadline + interval => Some(deadline + interval),
+            _ => Some(now + interval),
+        };
+    }
+}
+
+// *** sleep_until(deadline: Instant)
+//
+// Sleeps most of the way to deadline, then spins for the rest, so it returns within a few
+// microseconds of it rather than whenever the scheduler gets round to it.
+//
+pub fn sleep_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+fn limiter() -> &'static Mutex<FrameLimiter> {
+    LIMITER.get_or_init(|| Mutex::new(FrameLimiter::new(None)))
+}
+
+// The cap render applies to every frame it draws; None turns it off.  Can be changed at any time.
+pub fn set_frame_cap(cap: Option<u32>) {
+    limiter().lock().unwrap().set_cap(cap);
+}
+
+pub fn frame_cap() -> Option<u32> {
+    limiter().lock().unwrap().cap()
+}
+
+// Called by render at the start of every frame.
+pub fn wait_for_next_frame() {
+    limiter().lock().unwrap().wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn no_cap_means_no_schedule() {
+        for cap in [None, Some(0)] {
+            let limiter = FrameLimiter::new(cap);
+            assert_eq!(limiter.cap(), None);
+            assert_eq!(limiter.interval, None);
+        }
+        assert_eq!(FrameLimiter::new(Some(100)).interval, Some(INTERVAL));
+    }
+
+    #[test]
+    fn deadlines_advance_by_the_interval() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+
+        limiter.schedule(INTERVAL, start);
+        assert_eq!(limiter.next_frame, Some(start + INTERVAL));
+
+        // A frame that woke a little late does not push the ones after it back.
+        limiter.schedule(INTERVAL, start + INTERVAL + Duration::from_millis(3));
+        assert_eq!(limiter.next_frame, Some(start + INTERVAL * 2));
+    }
+
+    #[test]
+    fn a_frame_a_whole_interval_late_starts_over() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+        limiter.schedule(INTERVAL, start);
+
+        let late = start + INTERVAL * 5;
+        limiter.schedule(INTERVAL, late);
+        assert_eq!(limiter.next_frame, Some(late + INTERVAL));
+    }
+
+    #[test]
+    fn changing_the_cap_drops_the_schedule() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+        limiter.schedule(INTERVAL, start);
+
+        limiter.set_cap(Some(100));
+        assert_eq!(limiter.next_frame, Some(start + INTERVAL));
+
+        limiter.set_cap(Some(50));
+        assert_eq!(limiter.cap(), Some(50));
+        assert_eq!(limiter.interval, Some(INTERVAL * 2));
+        assert_eq!(limiter.next_frame, None);
+    }
+
+    #[test]
+    fn sleep_until_does_not_return_early() {
+        let deadline = Instant::now() + Duration::from_millis(3);
+        sleep_until(deadline);
+        assert!(Instant::now() >= deadline);
+    }
+}
@@ -1,17 +1,40 @@
-use std::sync::Arc;
+use std::{cell::Cell, sync::Arc};
 
 use ash::{
     vk::{
-        ComponentMapping, ComponentSwizzle, DeviceMemory, Format, Image, ImageAspectFlags,
-        ImageSubresourceRange, ImageView, ImageViewCreateFlags, ImageViewCreateInfo, ImageViewType,
+        BorderColor, CompareOp, ComponentMapping, ComponentSwizzle, DescriptorImageInfo,
+        DescriptorSet, DescriptorSetLayout, DescriptorType, DeviceMemory, Extent2D, Filter, Format,
+        Image, ImageAspectFlags, ImageLayout, ImageSubresourceRange, ImageView,
+        ImageViewCreateFlags, ImageViewCreateInfo, ImageViewType, Sampler, SamplerAddressMode,
+        SamplerCreateFlags, SamplerCreateInfo, SamplerMipmapMode, WriteDescriptorSet,
     },
     Device,
 };
 
+use log::error;
+
+use crate::{dust_errors::DustError, setup::debug};
+
+use super::{batch::Quad, descriptors};
+
+// Nearest keeps hard pixel edges, which is what pixel art wants when it is scaled up.  Linear is
+// for everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
 pub struct DustImage {
     pub image: Image,
     pub view: ImageView,
     pub format: Format,
+    pub extent: Extent2D,
+    // The layout the image will be in once everything recorded against it so far has run.  Kept
+    // up to date by whoever records a transition, so the next barrier knows where to start from.
+    layout: Cell<ImageLayout>,
+    sampler: Option<Sampler>,
+    descriptor_set: Option<DescriptorSet>,
     memory: DeviceMemory,
     logical_device: Arc<Device>,
 }
@@ -19,9 +42,11 @@ pub struct DustImage {
 pub fn new(
     image: Image,
     format: Format,
+    extent: Extent2D,
+    layout: ImageLayout,
     memory: DeviceMemory,
     logical_device: Arc<Device>,
-) -> DustImage {
+) -> Result<DustImage, DustError> {
     let view = match unsafe {
         logical_device.create_image_view(
             &ImageViewCreateInfo::default()
@@ -36,7 +61,7 @@ pub fn new(
                 )
                 .subresource_range(
                     ImageSubresourceRange::default()
-                        .aspect_mask(ImageAspectFlags::COLOR)
+                        .aspect_mask(aspect_mask(format))
                         .level_count(1)
                         .base_array_layer(0)
                         .layer_count(1)
@@ -49,24 +74,181 @@ pub fn new(
     } {
         Ok(view) => view,
         Err(msg) => {
-            panic!("Unable to construct view for image: {:?}", msg);
+            // The image and memory were handed over to be owned by the DustImage, so they go too.
+            unsafe {
+                logical_device.destroy_image(image, None);
+                logical_device.free_memory(memory, None);
+            }
+            return Err(DustError::vulkan("creating an image view", msg));
         }
     };
-    DustImage {
+    Ok(DustImage {
         image,
         format,
+        extent,
         view,
+        layout: Cell::new(layout),
+        sampler: None,
+        descriptor_set: None,
         memory,
         logical_device,
+    })
+}
+
+// Which aspects a view of an image in the given format covers: depth and stencil for the depth
+// formats, colour for everything else.
+pub fn aspect_mask(format: Format) -> ImageAspectFlags {
+    match format {
+        Format::D16_UNORM | Format::X8_D24_UNORM_PACK32 | Format::D32_SFLOAT => {
+            ImageAspectFlags::DEPTH
+        }
+        Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT => {
+            ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL
+        }
+        Format::S8_UINT => ImageAspectFlags::STENCIL,
+        _ => ImageAspectFlags::COLOR,
+    }
+}
+
+impl DustImage {
+    // *** make_texture(&mut self, layout, filter) -> Result<DescriptorSet, DustError>
+    //
+    // Turns the image into something a shader can sample: builds a sampler with the requested
+    // filtering, allocates a descriptor set matching layout (which must have a single
+    // COMBINED_IMAGE_SAMPLER at binding 0) and points it at this image.  The image must already
+    // be in SHADER_READ_ONLY_OPTIMAL by the time anything samples from it.
+    //
+    pub fn make_texture(
+        &mut self,
+        layout: DescriptorSetLayout,
+        filter: TextureFilter,
+    ) -> Result<DescriptorSet, DustError> {
+        if let Some(descriptor_set) = self.descriptor_set {
+            return Ok(descriptor_set);
+        }
+
+        let sampler = make_sampler(&self.logical_device, filter)?;
+        self.sampler = Some(sampler);
+
+        let descriptor_set = descriptors::allocate_persistent(layout)?;
+
+        let image_info = [self.descriptor_image_info()];
+        let write_descriptors = [WriteDescriptorSet::default()
+            .dst_binding(0)
+            .descriptor_count(1)
+            .descriptor_type(DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .dst_set(descriptor_set)
+            .dst_array_element(0)
+            .image_info(&image_info)];
+
+        unsafe {
+            self.logical_device
+                .update_descriptor_sets(&write_descriptors, &[])
+        };
+
+        self.descriptor_set = Some(descriptor_set);
+
+        Ok(descriptor_set)
+    }
+
+    pub fn layout(&self) -> ImageLayout {
+        self.layout.get()
+    }
+
+    pub fn set_layout(&self, layout: ImageLayout) {
+        self.layout.set(layout);
+    }
+
+    // Names the image and everything made from it, for validation messages to refer to.
+    pub fn set_name(&self, name: &str) {
+        debug::name_object(self.image, name);
+        debug::name_object(self.view, &format!("{}_view", name));
+        debug::name_object(self.memory, &format!("{}_memory", name));
+        if let Some(sampler) = self.sampler {
+            debug::name_object(sampler, &format!("{}_sampler", name));
+        }
+        if let Some(descriptor_set) = self.descriptor_set {
+            debug::name_object(descriptor_set, &format!("{}_texture", name));
+        }
+    }
+
+    pub fn has_sampler(&self) -> bool {
+        self.sampler.is_some()
+    }
+
+    pub fn descriptor_image_info(&self) -> DescriptorImageInfo {
+        DescriptorImageInfo::default()
+            .sampler(self.sampler.unwrap_or_default())
+            .image_view(self.view)
+            .image_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    // *** quad(&self, position: [f32; 2], scale: f32) -> Result<Quad, DustError>
+    //
+    // A quad that draws the whole texture with its centre at position, scaled uniformly from its
+    // size in texels.  Further adjustments (layer, tint, rotation) go through the Quad builder.
+    // The image must have been through make_texture.
+    //
+    pub fn quad(&self, position: [f32; 2], scale: f32) -> Result<Quad, DustError> {
+        let texture = match self.descriptor_set {
+            Some(descriptor_set) => descriptor_set,
+            None => return Err(DustError::NotATexture),
+        };
+
+        Ok(Quad::new(
+            texture,
+            position,
+            [
+                self.extent.width as f32 * scale,
+                self.extent.height as f32 * scale,
+            ],
+        ))
+    }
+}
+
+fn make_sampler(device: &Device, filter: TextureFilter) -> Result<Sampler, DustError> {
+    let (vk_filter, mipmap_mode) = match filter {
+        TextureFilter::Nearest => (Filter::NEAREST, SamplerMipmapMode::NEAREST),
+        TextureFilter::Linear => (Filter::LINEAR, SamplerMipmapMode::LINEAR),
+    };
+
+    let sampler_info = SamplerCreateInfo::default()
+        .flags(SamplerCreateFlags::empty())
+        .mag_filter(vk_filter)
+        .min_filter(vk_filter)
+        .mipmap_mode(mipmap_mode)
+        .address_mode_u(SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(SamplerAddressMode::CLAMP_TO_EDGE)
+        .mip_lod_bias(0.0)
+        .anisotropy_enable(false)
+        .compare_enable(false)
+        .compare_op(CompareOp::ALWAYS)
+        .min_lod(0.0)
+        .max_lod(0.0)
+        .border_color(BorderColor::FLOAT_TRANSPARENT_BLACK)
+        .unnormalized_coordinates(false);
+
+    match unsafe { device.create_sampler(&sampler_info, None) } {
+        Ok(sampler) => Ok(sampler),
+        Err(msg) => Err(DustError::vulkan("creating a sampler", msg)),
     }
 }
 
 impl Drop for DustImage {
     fn drop(&mut self) {
+        if let Some(descriptor_set) = self.descriptor_set {
+            if let Err(msg) = descriptors::free_persistent(descriptor_set) {
+                error!("Unable to free a texture's descriptor set: {}", msg);
+            }
+        }
         unsafe {
+            if let Some(sampler) = self.sampler {
+                self.logical_device.destroy_sampler(sampler, None);
+            }
+            self.logical_device.destroy_image_view(self.view, None);
             self.logical_device.destroy_image(self.image, None);
             self.logical_device.free_memory(self.memory, None);
-            self.logical_device.destroy_image_view(self.view, None);
         }
     }
 }
@@ -1,8 +1,23 @@
+pub mod batch;
 pub mod bitmap;
+pub mod buffer;
+pub mod compute;
+pub mod descriptors;
+pub mod frame_limiter;
 pub mod image;
+pub mod palette;
+pub mod pipeline_cache;
+pub mod pipelines;
 pub mod pools;
+pub mod postprocess;
 pub mod render;
+pub mod resident;
+pub mod scaling;
+#[cfg(all(feature = "shader-hot-reload", target_os = "linux"))]
+mod shader_watch;
 pub mod shaders;
+pub mod spirv;
 pub mod swapchain;
+pub mod targets;
 pub mod transfer;
 pub mod util;
@@ -0,0 +1,328 @@
+use ash::vk::{
+    AccessFlags2, BufferImageCopy, BufferUsageFlags, CommandBuffer, DependencyInfo,
+    DescriptorSetLayout, Extent2D, Extent3D, Format, ImageAspectFlags, ImageCreateFlags,
+    ImageCreateInfo, ImageLayout, ImageMemoryBarrier2, ImageSubresourceLayers,
+    ImageSubresourceRange, ImageTiling, ImageType, ImageUsageFlags, PipelineStageFlags2,
+    SampleCountFlags, Semaphore, SharingMode, QUEUE_FAMILY_IGNORED,
+};
+
+use crate::{dust_errors::DustError, setup::instance::VkContext};
+
+use super::{
+    buffer::DustBuffer,
+    compute::{self, ComputeBinding, ComputeResource},
+    image::{DustImage, TextureFilter},
+    pools, render, transfer,
+};
+
+// Doom's lumps: PLAYPAL is a run of 256 entry RGB palettes, COLORMAP a run of 256 entry tables
+// mapping each palette index to the index that stands in for it at a given light level.
+pub const PALETTE_ENTRIES: usize = 256;
+const PLAYPAL_ROW_BYTES: usize = PALETTE_ENTRIES * 3;
+const COLORMAP_ROW_BYTES: usize = PALETTE_ENTRIES;
+
+const RESOLVE_SHADER: &str = "palette_resolve";
+
+// *** PaletteFramebuffer
+//
+// An 8-bit, palette indexed framebuffer in the style of the Doom engine, resolved to colour on
+// the GPU.  The renderer writes palette indices; resolve() runs each one through the COLORMAP row
+// for the current light level and then the PLAYPAL row for the current palette, and writes the
+// result into output.  Damage flashes, pickup tints and the like are just a different palette
+// row.
+//
+// output holds linear colour, ready to be scaled to the screen by render::composite_palette.
+pub struct PaletteFramebuffer {
+    pub extent: Extent2D,
+    pub output: DustImage,
+    // The latest indices, written into the frame slot's staging buffer by the next resolve.
+    pending: Vec<u8>,
+    // Host visible, one per frame slot, so a resolve never overwrites indices a frame still in
+    // flight is copying from.  Each resolve copies its slot's buffer into indices.
+    staging: Vec<DustBuffer>,
+    indices: DustImage,
+    palettes: DustImage,
+    colormaps: DustImage,
+    palette_count: u32,
+    light_levels: u32,
+    palette_row: u32,
+    light_level: u32,
+}
+
+// *** new(ctxt, extent, playpal, colormap, texture_layout, filter) -> Result<(PaletteFramebuffer, Vec<Semaphore>), DustError>
+//
+// Uploads the palettes and colormaps and creates the index and output images.  playpal and
+// colormap are the raw lump contents.  The output is made into a texture against texture_layout
+// with the given filtering, which should match the scale filter it will be presented with.  The returned
+// semaphores are signalled once the uploads have landed and must be waited on before the first
+// resolve.  Lumps that are empty or hold a partial row are refused.
+//
+pub fn new(
+    ctxt: &VkContext,
+    extent: Extent2D,
+    playpal: &[u8],
+    colormap: &[u8],
+    texture_layout: DescriptorSetLayout,
+    filter: TextureFilter,
+) -> Result<(PaletteFramebuffer, Vec<Semaphore>), DustError> {
+    if playpal.is_empty() || !playpal.len().is_multiple_of(PLAYPAL_ROW_BYTES) {
+        return Err(DustError::InvalidPalette(format!(
+            "PLAYPAL must hold whole {} byte palettes; got {} bytes",
+            PLAYPAL_ROW_BYTES,
+            playpal.len()
+        )));
+    }
+    if colormap.is_empty() || !colormap.len().is_multiple_of(COLORMAP_ROW_BYTES) {
+        return Err(DustError::InvalidPalette(format!(
+            "COLORMAP must hold whole {} byte light levels; got {} bytes",
+            COLORMAP_ROW_BYTES,
+            colormap.len()
+        )));
+    }
+
+    let palette_count = (playpal.len() / PLAYPAL_ROW_BYTES) as u32;
+    let light_levels = (colormap.len() / COLORMAP_ROW_BYTES) as u32;
+
+    // Storage images have no three channel formats worth relying on, so the palettes go up as
+    // RGBA with an opaque alpha.
+    let palette_rgba: Vec<u8> = playpal
+        .chunks_exact(3)
+        .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+        .collect();
+
+    let (palettes, palettes_ready) = transfer::copy_to_image(
+        &palette_rgba,
+        ctxt,
+        &storage_image_info(
+            Format::R8G8B8A8_UNORM,
+            PALETTE_ENTRIES as u32,
+            palette_count,
+            ImageUsageFlags::TRANSFER_DST,
+        ),
+        ImageLayout::GENERAL,
+        pools::get_graphics_queue_family()?,
+    )?;
+
+    let (colormaps, colormaps_ready) = transfer::copy_to_image(
+        colormap,
+        ctxt,
+        &storage_image_info(
+            Format::R8_UINT,
+            PALETTE_ENTRIES as u32,
+            light_levels,
+            ImageUsageFlags::TRANSFER_DST,
+        ),
+        ImageLayout::GENERAL,
+        pools::get_graphics_queue_family()?,
+    )?;
+
+    let pixels = (extent.width * extent.height) as usize;
+    let staging = (0..render::FRAMES_IN_FLIGHT)
+        .map(|_| transfer::make_mapped_buffer(ctxt, pixels as u64, BufferUsageFlags::TRANSFER_SRC))
+        .collect::<Result<Vec<DustBuffer>, DustError>>()?;
+    let indices = transfer::make_image(
+        ctxt,
+        &storage_image_info(
+            Format::R8_UINT,
+            extent.width,
+            extent.height,
+            ImageUsageFlags::TRANSFER_DST,
+        ),
+    )?;
+
+    let mut output = transfer::make_image(
+        ctxt,
+        &storage_image_info(
+            Format::R16G16B16A16_SFLOAT,
+            extent.width,
+            extent.height,
+            ImageUsageFlags::SAMPLED,
+        ),
+    )?;
+    output.make_texture(texture_layout, filter)?;
+
+    palettes.set_name("playpal");
+    colormaps.set_name("colormap");
+    indices.set_name("palette_indices");
+    output.set_name("palette_output");
+
+    Ok((
+        PaletteFramebuffer {
+            extent,
+            output,
+            pending: vec![0u8; pixels],
+            staging,
+            indices,
+            palettes,
+            colormaps,
+            palette_count,
+            light_levels,
+            palette_row: 0,
+            light_level: 0,
+        },
+        vec![palettes_ready, colormaps_ready],
+    ))
+}
+
+impl PaletteFramebuffer {
+    // *** upload_indices(&mut self, indices: &[u8]) -> Result<(), DustError>
+    //
+    // Replaces the frame's palette indices, one byte per pixel in rows from the top.  They are
+    // kept until the next resolve, which writes them to its frame slot's staging buffer and
+    // copies them into the index image; the image itself is made once and kept.
+    //
+    pub fn upload_indices(&mut self, indices: &[u8]) -> Result<(), DustError> {
+        if indices.len() != (self.extent.width * self.extent.height) as usize {
+            return Err(DustError::InvalidPalette(format!(
+                "{} palette indices were given for a {}x{} framebuffer",
+                indices.len(),
+                self.extent.width,
+                self.extent.height
+            )));
+        }
+
+        self.pending.copy_from_slice(indices);
+        Ok(())
+    }
+
+    // Selects the PLAYPAL row to resolve through: 0 is the normal palette, the rest are the
+    // damage, pickup and radiation suit tints.
+    pub fn set_palette(&mut self, palette_row: u32) -> Result<(), DustError> {
+        if palette_row >= self.palette_count {
+            return Err(DustError::InvalidPalette(format!(
+                "palette {} requested, but only {} were loaded",
+                palette_row, self.palette_count
+            )));
+        }
+        self.palette_row = palette_row;
+        Ok(())
+    }
+
+    // Selects the COLORMAP row: 0 is full brightness, higher rows are darker.
+    pub fn set_light_level(&mut self, light_level: u32) -> Result<(), DustError> {
+        if light_level >= self.light_levels {
+            return Err(DustError::InvalidPalette(format!(
+                "light level {} requested, but only {} were loaded",
+                light_level, self.light_levels
+            )));
+        }
+        self.light_level = light_level;
+        Ok(())
+    }
+
+    // *** resolve(&self, ctxt: &VkContext, command_buffer: CommandBuffer, frame: usize) -> Result<(), DustError>
+    //
+    // Records the copy of the latest indices and the palette resolve into command_buffer.
+    // compute::dispatch takes care of the barriers either side, so output can be sampled by
+    // anything recorded afterwards.
+    //
+    pub fn resolve(
+        &self,
+        ctxt: &VkContext,
+        command_buffer: CommandBuffer,
+        frame: usize,
+    ) -> Result<(), DustError> {
+        // The slot's last frame has finished by now, so its staging buffer is free to rewrite.
+        let staging = &self.staging[frame];
+        staging.write(0, &self.pending)?;
+        self.record_index_copy(ctxt, command_buffer, staging);
+
+        let pipeline = compute::get(ctxt, RESOLVE_SHADER)?;
+        let groups = pipeline.groups_for(self.extent.width, self.extent.height, 1);
+
+        compute::dispatch(
+            ctxt,
+            command_buffer,
+            frame,
+            &pipeline,
+            &[
+                ComputeBinding::new(0, 0, ComputeResource::StorageImage(&self.indices)),
+                ComputeBinding::new(0, 1, ComputeResource::StorageImage(&self.palettes)),
+                ComputeBinding::new(0, 2, ComputeResource::StorageImage(&self.colormaps)),
+                ComputeBinding::new(0, 3, ComputeResource::StorageImage(&self.output)),
+            ],
+            &[self.palette_row, self.light_level],
+            groups,
+        )
+    }
+
+    // Moves the index image out of whatever the last resolve left it in and copies the staging
+    // buffer over it.  The dispatch's own barrier then waits for the copy.
+    fn record_index_copy(
+        &self,
+        ctxt: &VkContext,
+        command_buffer: CommandBuffer,
+        staging: &DustBuffer,
+    ) {
+        let to_transfer_dst = [ImageMemoryBarrier2::default()
+            .src_stage_mask(PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(PipelineStageFlags2::COPY)
+            .dst_access_mask(AccessFlags2::TRANSFER_WRITE)
+            .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+            .old_layout(self.indices.layout())
+            .new_layout(ImageLayout::TRANSFER_DST_OPTIMAL)
+            .image(self.indices.image)
+            .subresource_range(
+                ImageSubresourceRange::default()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .base_mip_level(0)
+                    .level_count(1)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )];
+        let region = BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(self.extent.width)
+            .buffer_image_height(self.extent.height)
+            .image_subresource(
+                ImageSubresourceLayers::default()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(
+                Extent3D::default()
+                    .width(self.extent.width)
+                    .height(self.extent.height)
+                    .depth(1),
+            );
+
+        unsafe {
+            ctxt.logical_device.cmd_pipeline_barrier2(
+                command_buffer,
+                &DependencyInfo::default().image_memory_barriers(&to_transfer_dst),
+            );
+            ctxt.logical_device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging.buffer,
+                self.indices.image,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+        }
+        self.indices.set_layout(ImageLayout::TRANSFER_DST_OPTIMAL);
+    }
+}
+
+fn storage_image_info<'a>(
+    format: Format,
+    width: u32,
+    height: u32,
+    extra_usage: ImageUsageFlags,
+) -> ImageCreateInfo<'a> {
+    ImageCreateInfo::default()
+        .initial_layout(ImageLayout::UNDEFINED)
+        .sharing_mode(SharingMode::EXCLUSIVE)
+        .image_type(ImageType::TYPE_2D)
+        .array_layers(1)
+        .format(format)
+        .extent(Extent3D::default().width(width).height(height).depth(1))
+        .mip_levels(1)
+        .samples(SampleCountFlags::TYPE_1)
+        .flags(ImageCreateFlags::empty())
+        .usage(ImageUsageFlags::STORAGE | extra_usage)
+        .tiling(ImageTiling::OPTIMAL)
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use ash::{
+    vk::{
+        PhysicalDeviceProperties, PipelineCache, PipelineCacheCreateFlags, PipelineCacheCreateInfo,
+        PipelineCacheHeaderVersion, UUID_SIZE,
+    },
+    Device,
+};
+use log::{debug, error, warn};
+
+// VkPipelineCacheHeaderVersionOne: header length, header version, vendor ID, device ID, then the
+// pipeline cache UUID.  Unlike the rest of the API these are always little endian.
+const HEADER_LENGTH: usize = 16 + UUID_SIZE;
+const CACHE_DIRECTORY: &str = "dust";
+const CACHE_FILE: &str = "pipeline_cache.bin";
+
+// None until the Vulkan context creates the cache, and again once it is destroyed.
+static PIPELINE_CACHE: OnceLock<Mutex<Option<CacheState>>> = OnceLock::new();
+
+struct CacheState {
+    logical_device: Arc<Device>,
+    cache: PipelineCache,
+    path: Option<PathBuf>,
+}
+
+// *** init(logical_device: Arc<Device>, properties: &PhysicalDeviceProperties)
+//
+// Creates the pipeline cache every pipeline in the engine is built through, seeded from the blob
+// saved by the last run.  A blob written by a different driver, device or driver version is
+// thrown away rather than handed to Vulkan; so is one that cannot be read.  Either way we start
+// with an empty cache and nothing worse than slower pipeline creation.  If the driver will not
+// make a cache at all, pipelines are built without one.  Called again when the device is rebuilt,
+// after destroy() has saved the old cache.
+//
+pub fn init(logical_device: Arc<Device>, properties: &PhysicalDeviceProperties) {
+    let path = cache_path();
+
+    let initial_data = match &path {
+        Some(path) => match fs::read(path) {
+            Ok(blob) if header_matches(&blob, properties) => {
+                debug!("Loaded {} byte pipeline cache from {:?}", blob.len(), path);
+                blob
+            }
+            Ok(_) => Vec::new(),
+            Err(msg) => {
+                debug!("No usable pipeline cache at {:?}: {:?}", path, msg);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let create_info = PipelineCacheCreateInfo::default()
+        .flags(PipelineCacheCreateFlags::empty())
+        .initial_data(&initial_data);
+
+    let cache = match unsafe { logical_device.create_pipeline_cache(&create_info, None) } {
+        Ok(cache) => cache,
+        Err(msg) => {
+            error!(
+                "Unable to create the pipeline cache; building pipelines without one: {:?}",
+                msg
+            );
+            PipelineCache::null()
+        }
+    };
+
+    let state = CacheState {
+        logical_device,
+        cache,
+        path,
+    };
+
+    *PIPELINE_CACHE
+        .get_or_init(|| Mutex::new(None))
+        .lock()
+        .unwrap() = Some(state);
+}
+
+// *** destroy()
+//
+// Writes the cache back to disk and destroys it.  Must run after the last pipeline has been
+// created and before the device goes away.
+//
+pub fn destroy() {
+    match PIPELINE_CACHE
+        .get()
+        .and_then(|state| state.lock().unwrap().take())
+    {
+        Some(state) if state.cache == PipelineCache::null() => {}
+        Some(state) => {
+            if let Some(path) = &state.path {
+                save(&state, path);
+            }
+            unsafe {
+                state
+                    .logical_device
+                    .destroy_pipeline_cache(state.cache, None)
+            };
+        }
+        None => {
+            error!("The pipeline cache was never initialized; nothing to destroy.");
+        }
+    }
+}
+
+// Pipeline caches are internally synchronized, so the one handle can be used for pipeline
+// creation from any thread.  A null handle, when there is no cache, builds pipelines uncached.
+pub fn handle() -> PipelineCache {
+    match PIPELINE_CACHE.get() {
+        Some(state) => match state.lock().unwrap().as_ref() {
+            Some(state) => state.cache,
+            None => PipelineCache::null(),
+        },
+        None => PipelineCache::null(),
+    }
+}
+
+fn header_matches(blob: &[u8], properties: &PhysicalDeviceProperties) -> bool {
+    if blob.len() < HEADER_LENGTH {
+        warn!("Discarding pipeline cache: too short to hold a header.");
+        return false;
+    }
+
+    let word = |index: usize| {
+        let offset = index * 4;
+        u32::from_le_bytes(blob[offset..offset + 4].try_into().unwrap())
+    };
+
+    let header_length = word(0);
+    let header_version = word(1);
+    let vendor_id = word(2);
+    let device_id = word(3);
+    let cache_uuid = &blob[16..HEADER_LENGTH];
+
+    if (header_length as usize) < HEADER_LENGTH
+        || header_version != PipelineCacheHeaderVersion::ONE.as_raw() as u32
+    {
+        warn!(
+            "Discarding pipeline cache: unrecognised header (length {}, version {}).",
+            header_length, header_version
+        );
+        return false;
+    }
+
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        warn!(
+            "Discarding pipeline cache: written for vendor {:#x} device {:#x}, running on vendor {:#x} device {:#x}.",
+            vendor_id, device_id, properties.vendor_id, properties.device_id
+        );
+        return false;
+    }
+
+    if cache_uuid != properties.pipeline_cache_uuid {
+        warn!("Discarding pipeline cache: written by a different driver version.");
+        return false;
+    }
+
+    true
+}
+
+// Written to a temporary file beside the real one and renamed into place, so a crash part way
+// through leaves the previous cache intact instead of a truncated one.
+fn save(state: &CacheState, path: &PathBuf) {
+    let blob = match unsafe { state.logical_device.get_pipeline_cache_data(state.cache) } {
+        Ok(blob) => blob,
+        Err(msg) => {
+            error!("Unable to read back the pipeline cache: {:?}", msg);
+            return;
+        }
+    };
+
+    if let Some(directory) = path.parent() {
+        if let Err(msg) = fs::create_dir_all(directory) {
+            error!(
+                "Unable to create the pipeline cache directory {:?}: {:?}",
+                directory, msg
+            );
+            return;
+        }
+    }
+
+    let temporary_path = path.with_extension("tmp");
+
+    if let Err(msg) = fs::write(&temporary_path, &blob) {
+        error!(
+            "Unable to write the pipeline cache to {:?}: {:?}",
+            temporary_path, msg
+        );
+        return;
+    }
+
+    match fs::rename(&temporary_path, path) {
+        Ok(_) => debug!("Saved {} byte pipeline cache to {:?}", blob.len(), path),
+        Err(msg) => {
+            error!(
+                "Unable to move the pipeline cache into place at {:?}: {:?}",
+                path, msg
+            );
+            let _ = fs::remove_file(&temporary_path);
+        }
+    }
+}
+
+// $XDG_CACHE_HOME/dust, falling back to ~/.cache/dust as the XDG spec says to.  On Windows the
+// equivalent is the local application data directory.
+fn cache_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+
+    #[cfg(not(target_os = "windows"))]
+    let base = match std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+        Some(xdg_cache) if xdg_cache.is_absolute() => Some(xdg_cache),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")),
+    };
+
+    match base {
+        Some(base) => Some(base.join(CACHE_DIRECTORY).join(CACHE_FILE)),
+        None => {
+            warn!("No cache directory could be found; the pipeline cache will not persist.");
+            None
+        }
+    }
+}
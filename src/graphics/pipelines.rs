@@ -0,0 +1,719 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use ash::{
+    vk::{
+        AttachmentLoadOp, BlendFactor, BlendOp, ColorComponentFlags, CommandBuffer, CompareOp,
+        CullModeFlags, DynamicState, Extent2D, Format, FrontFace, GraphicsPipelineCreateInfo,
+        ImageLayout, Offset2D, Pipeline, PipelineColorBlendAttachmentState,
+        PipelineColorBlendStateCreateInfo, PipelineCreateFlags,
+        PipelineDepthStencilStateCreateInfo, PipelineDynamicStateCreateInfo,
+        PipelineInputAssemblyStateCreateFlags, PipelineInputAssemblyStateCreateInfo,
+        PipelineLayout, PipelineLayoutCreateFlags, PipelineLayoutCreateInfo,
+        PipelineMultisampleStateCreateFlags, PipelineMultisampleStateCreateInfo,
+        PipelineRasterizationStateCreateFlags, PipelineRasterizationStateCreateInfo,
+        PipelineShaderStageCreateFlags, PipelineShaderStageCreateInfo,
+        PipelineVertexInputStateCreateFlags, PipelineVertexInputStateCreateInfo,
+        PipelineViewportStateCreateFlags, PipelineViewportStateCreateInfo, PolygonMode,
+        PrimitiveTopology, Rect2D, RenderPass, SampleCountFlags, VertexInputAttributeDescription,
+        VertexInputBindingDescription, Viewport,
+    },
+    Device,
+};
+use log::{debug, error};
+
+use crate::{dust_errors::DustError, setup::instance::VkContext};
+
+use super::{
+    batch, compute, pipeline_cache,
+    shaders::{self, ShaderWrapper},
+    swapchain,
+    targets::{self, AttachmentSetup},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    PremultipliedAlpha,
+    Additive,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthState {
+    pub format: Format,
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+impl DepthState {
+    // Nearer fragments win, and write their depth for the ones that follow.
+    pub fn new(format: Format) -> DepthState {
+        DepthState {
+            format,
+            test: true,
+            write: true,
+            compare_op: CompareOp::LESS,
+        }
+    }
+}
+
+// *** PipelineDescription
+//
+// Everything needed to build a graphics pipeline, short of the things the shaders already say
+// about themselves: the pipeline layout is reflected from the named shaders, and the vertex
+// layout given here is checked against the inputs the vertex shader declares.  Viewport and
+// scissor are always dynamic, so one pipeline serves any size of render target; further dynamic
+// states can be added on top.  Pipelines are built against the swapchain format, single sampled,
+// unless told otherwise; get_for() builds variants for other attachment setups on demand.
+#[derive(Clone, Debug)]
+pub struct PipelineDescription {
+    pub vertex_shader: String,
+    pub fragment_shader: String,
+    pub vertex_bindings: Vec<VertexInputBindingDescription>,
+    pub vertex_attributes: Vec<VertexInputAttributeDescription>,
+    pub blend: BlendMode,
+    pub cull_mode: CullModeFlags,
+    pub front_face: FrontFace,
+    pub topology: PrimitiveTopology,
+    pub depth: Option<DepthState>,
+    pub dynamic_states: Vec<DynamicState>,
+    pub color_format: Option<Format>,
+    pub samples: SampleCountFlags,
+}
+
+impl PipelineDescription {
+    pub fn new(vertex_shader: &str, fragment_shader: &str) -> PipelineDescription {
+        PipelineDescription {
+            vertex_shader: String::from(vertex_shader),
+            fragment_shader: String::from(fragment_shader),
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            blend: BlendMode::Opaque,
+            cull_mode: CullModeFlags::BACK,
+            front_face: FrontFace::CLOCKWISE,
+            topology: PrimitiveTopology::TRIANGLE_LIST,
+            depth: None,
+            dynamic_states: Vec::new(),
+            color_format: None,
+            samples: SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn vertex_layout(
+        mut self,
+        bindings: &[VertexInputBindingDescription],
+        attributes: &[VertexInputAttributeDescription],
+    ) -> PipelineDescription {
+        self.vertex_bindings = bindings.to_vec();
+        self.vertex_attributes = attributes.to_vec();
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendMode) -> PipelineDescription {
+        self.blend = blend;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: CullModeFlags) -> PipelineDescription {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: FrontFace) -> PipelineDescription {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn topology(mut self, topology: PrimitiveTopology) -> PipelineDescription {
+        self.topology = topology;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthState) -> PipelineDescription {
+        self.depth = Some(depth);
+        self
+    }
+
+    pub fn dynamic_state(mut self, dynamic_state: DynamicState) -> PipelineDescription {
+        if !self.dynamic_states.contains(&dynamic_state) {
+            self.dynamic_states.push(dynamic_state);
+        }
+        self
+    }
+
+    pub fn color_format(mut self, color_format: Format) -> PipelineDescription {
+        self.color_format = Some(color_format);
+        self
+    }
+
+    pub fn samples(mut self, samples: SampleCountFlags) -> PipelineDescription {
+        self.samples = samples;
+        self
+    }
+
+    // The attachments the pipeline is built against when nobody asks for anything else.
+    fn default_setup(&self) -> Result<AttachmentSetup, DustError> {
+        let color_format = match self.color_format {
+            Some(color_format) => color_format,
+            None => swapchain::get_swapchain_format()?.format,
+        };
+        let mut setup = AttachmentSetup::new(color_format).samples(self.samples);
+        if let Some(depth) = self.depth {
+            setup = setup.depth(depth.format);
+        }
+        Ok(setup)
+    }
+
+    fn uses_shader(&self, shader_name: &str) -> bool {
+        self.vertex_shader == shader_name || self.fragment_shader == shader_name
+    }
+}
+
+// A pipeline and the layout it was built with.  Both belong to the registry.
+#[derive(Clone, Copy, Debug)]
+pub struct BuiltPipeline {
+    pub pipeline: Pipeline,
+    pub layout: PipelineLayout,
+}
+
+static PIPELINES: OnceLock<Mutex<Registry>> = OnceLock::new();
+
+struct Registry {
+    logical_device: Arc<Device>,
+    descriptions: HashMap<String, PipelineDescription>,
+    // One pipeline per name and attachment setup it has been asked for.
+    built: HashMap<(String, AttachmentSetup), BuiltPipeline>,
+    // Pipelines only need a render pass that is compatible with the one they will be used in -
+    // same attachment formats and sample counts - so the registry keeps one per setup rather
+    // than asking every caller for theirs.
+    render_passes: HashMap<AttachmentSetup, RenderPass>,
+}
+
+// Called again with the new device when the device is rebuilt.  Everything registered so far is
+// kept, and built again for the new device on first use.
+pub fn init(logical_device: Arc<Device>) -> Result<(), DustError> {
+    if let Some(registry) = PIPELINES.get() {
+        registry.lock().unwrap().logical_device = logical_device;
+        return Ok(());
+    }
+
+    let registry = Registry {
+        logical_device,
+        descriptions: HashMap::new(),
+        built: HashMap::new(),
+        render_passes: HashMap::new(),
+    };
+
+    if PIPELINES.set(Mutex::new(registry)).is_err() {
+        panic!("Unable to set the pipeline registry static.");
+    }
+
+    register_builtin_pipelines()
+}
+
+// The pipelines the engine itself draws with.
+fn register_builtin_pipelines() -> Result<(), DustError> {
+    register(
+        "sprite",
+        PipelineDescription::new("sprite", "textured")
+            .vertex_layout(
+                &batch::vertex_binding_descriptions(),
+                &batch::vertex_attribute_descriptions(),
+            )
+            .blend(BlendMode::Alpha)
+            // Quads flipped with a negative size wind the other way, and should still draw.
+            .cull_mode(CullModeFlags::NONE),
+    )?;
+    register(
+        "sprite_sharp_bilinear",
+        PipelineDescription::new("sprite", "sharp_bilinear")
+            .vertex_layout(
+                &batch::vertex_binding_descriptions(),
+                &batch::vertex_attribute_descriptions(),
+            )
+            .blend(BlendMode::Alpha)
+            .cull_mode(CullModeFlags::NONE),
+    )?;
+    register(
+        "compositor",
+        PipelineDescription::new("passthrough", "compositor"),
+    )
+}
+
+pub fn destroy() {
+    match PIPELINES.get() {
+        Some(registry) => {
+            let mut registry = registry.lock().unwrap();
+            let device = registry.logical_device.clone();
+
+            registry
+                .built
+                .drain()
+                .for_each(|(_, built)| destroy_built(&device, built));
+            registry
+                .render_passes
+                .drain()
+                .for_each(|(_, render_pass)| unsafe {
+                    device.destroy_render_pass(render_pass, None)
+                });
+        }
+        None => {
+            error!("The pipeline registry was never initialized; nothing to destroy.");
+        }
+    }
+}
+
+fn with_registry<R>(action: impl FnOnce(&mut Registry) -> R) -> Result<R, DustError> {
+    match PIPELINES.get() {
+        Some(registry) => Ok(action(&mut registry.lock().unwrap())),
+        None => Err(DustError::NotInitialized("the pipeline registry")),
+    }
+}
+
+// *** register(name: &str, description: PipelineDescription) -> Result<(), DustError>
+//
+// Adds a pipeline under the given name, or replaces the description of an existing one.  Nothing
+// is built until the pipeline is first asked for.  Replacing a pipeline that has already been
+// built waits for the device to go idle before destroying the old one.
+//
+pub fn register(name: &str, description: PipelineDescription) -> Result<(), DustError> {
+    with_registry(|registry| {
+        let replaced: Vec<(String, AttachmentSetup)> = registry
+            .built
+            .keys()
+            .filter(|(built_name, _)| built_name == name)
+            .cloned()
+            .collect();
+        // A device that will not go idle is as good as lost; the old pipelines are leaked rather
+        // than destroyed while the GPU may still be using them.
+        let idle = match replaced.is_empty() {
+            true => true,
+            false => match wait_idle(&registry.logical_device) {
+                Ok(()) => true,
+                Err(msg) => {
+                    error!("Leaking the replaced {} pipelines: {}", name, msg);
+                    false
+                }
+            },
+        };
+        for key in replaced {
+            if let Some(built) = registry.built.remove(&key) {
+                if idle {
+                    destroy_built(&registry.logical_device, built);
+                }
+            }
+        }
+
+        debug!("Registered pipeline {}: {:?}", name, description);
+        registry
+            .descriptions
+            .insert(String::from(name), description);
+    })
+}
+
+// *** get(ctxt: &VkContext, name: &str) -> Result<BuiltPipeline, DustError>
+//
+// Looks a pipeline up by name, building it on first use.  Asking for a pipeline that was never
+// registered, or one that cannot be built, returns why.
+//
+pub fn get(ctxt: &VkContext, name: &str) -> Result<BuiltPipeline, DustError> {
+    let setup = with_registry(|registry| description_of(registry, name))??.default_setup()?;
+    get_for(ctxt, name, &setup)
+}
+
+// *** get_for(ctxt: &VkContext, name: &str, setup: &AttachmentSetup) -> Result<BuiltPipeline, DustError>
+//
+// As get(), but for use in a render pass with the given attachments rather than the ones the
+// description names.  The colour format and sample count come from setup.  So does the depth
+// format: a description with depth state tests against it, one without leaves it alone.
+//
+pub fn get_for(
+    ctxt: &VkContext,
+    name: &str,
+    setup: &AttachmentSetup,
+) -> Result<BuiltPipeline, DustError> {
+    with_registry(|registry| {
+        let key = (String::from(name), *setup);
+        if let Some(built) = registry.built.get(&key) {
+            return Ok(*built);
+        }
+
+        let description = description_of(registry, name)?;
+        let built = match build_pipeline(ctxt, registry, &description, setup) {
+            Ok(built) => built,
+            Err(msg) => {
+                error!("Unable to build the {} pipeline: {}", name, msg);
+                return Err(msg);
+            }
+        };
+
+        debug!("Built pipeline {} for {:?}", name, setup);
+        registry.built.insert(key, built);
+
+        Ok(built)
+    })?
+}
+
+fn description_of(registry: &Registry, name: &str) -> Result<PipelineDescription, DustError> {
+    match registry.descriptions.get(name) {
+        Some(description) => Ok(description.clone()),
+        None => Err(DustError::PipelineNotFound(String::from(name))),
+    }
+}
+
+// *** apply_shader_reloads(ctxt: &VkContext) -> Result<(), DustError>
+//
+// The frame boundary half of shader hot reloading.  Picks up whatever shaders have changed on
+// disk, then rebuilds every graphics and compute pipeline built from one of them.  A pipeline
+// that fails to rebuild keeps running on its previous version, with the reason logged; the
+// device failing to drain first is returned.  Only does anything with the shader-hot-reload
+// feature enabled.
+//
+pub fn apply_shader_reloads(ctxt: &VkContext) -> Result<(), DustError> {
+    let reloaded = shaders::apply_pending_reloads()?;
+    if reloaded.is_empty() {
+        return Ok(());
+    }
+
+    // Hot reload is a development convenience; draining the GPU once is cheaper than tracking
+    // which frame last used each pipeline.
+    with_registry(|registry| wait_idle(&registry.logical_device))??;
+
+    compute::shaders_reloaded(ctxt, &reloaded)?;
+
+    with_registry(|registry| {
+        let stale: Vec<((String, AttachmentSetup), PipelineDescription)> = registry
+            .built
+            .keys()
+            .filter_map(|key| {
+                let description = &registry.descriptions[&key.0];
+                reloaded
+                    .iter()
+                    .any(|shader_name| description.uses_shader(shader_name))
+                    .then(|| (key.clone(), description.clone()))
+            })
+            .collect();
+
+        for (key, description) in stale {
+            let name = key.0.clone();
+            match build_pipeline(ctxt, registry, &description, &key.1) {
+                Ok(built) => {
+                    if let Some(previous) = registry.built.insert(key, built) {
+                        destroy_built(&registry.logical_device, previous);
+                    }
+                    debug!("Rebuilt pipeline {} after a shader reload", name);
+                }
+                Err(msg) => {
+                    error!(
+                        "Rebuilding the {} pipeline failed; keeping the previous one: {}",
+                        name, msg
+                    );
+                }
+            }
+        }
+    })?;
+
+    Ok(())
+}
+
+// Sets the dynamic viewport and scissor every registry pipeline expects to cover the whole of a
+// render target.
+pub fn set_viewport(ctxt: &VkContext, command_buffer: CommandBuffer, extent: Extent2D) {
+    let viewport = Viewport::default()
+        .x(0.0)
+        .y(0.0)
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .min_depth(0.0)
+        .max_depth(1.0);
+    let scissor = Rect2D::default()
+        .offset(Offset2D::default().x(0).y(0))
+        .extent(extent);
+
+    unsafe {
+        ctxt.logical_device
+            .cmd_set_viewport(command_buffer, 0, &[viewport]);
+        ctxt.logical_device
+            .cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+}
+
+fn wait_idle(device: &Device) -> Result<(), DustError> {
+    match unsafe { device.device_wait_idle() } {
+        Ok(()) => Ok(()),
+        Err(msg) => Err(DustError::vulkan(
+            "waiting for the device to go idle before rebuilding pipelines",
+            msg,
+        )),
+    }
+}
+
+fn destroy_built(device: &Device, built: BuiltPipeline) {
+    unsafe {
+        device.destroy_pipeline(built.pipeline, None);
+        device.destroy_pipeline_layout(built.layout, None);
+    }
+}
+
+fn build_pipeline(
+    ctxt: &VkContext,
+    registry: &mut Registry,
+    description: &PipelineDescription,
+    setup: &AttachmentSetup,
+) -> Result<BuiltPipeline, DustError> {
+    let stages = [
+        find_shader(&description.vertex_shader)?,
+        find_shader(&description.fragment_shader)?,
+    ];
+
+    check_vertex_layout(&stages[0], description)?;
+
+    let render_pass = registry.compatible_render_pass(setup)?;
+
+    let layout = create_reflected_pipeline_layout(ctxt, &stages)?;
+
+    let shader_stage_infos = fill_shader_stage_infos(&stages);
+    let vertex_input_state = PipelineVertexInputStateCreateInfo::default()
+        .flags(PipelineVertexInputStateCreateFlags::empty())
+        .vertex_binding_descriptions(&description.vertex_bindings)
+        .vertex_attribute_descriptions(&description.vertex_attributes);
+    let input_assembly_state = PipelineInputAssemblyStateCreateInfo::default()
+        .flags(PipelineInputAssemblyStateCreateFlags::empty())
+        .topology(description.topology)
+        .primitive_restart_enable(false);
+    let rasterization_state = create_rasterization_state(description);
+    let viewport_state = PipelineViewportStateCreateInfo::default()
+        .flags(PipelineViewportStateCreateFlags::empty())
+        .viewport_count(1)
+        .scissor_count(1);
+    let multisample_state = create_multisample_state(setup.samples);
+    // Depth state only means something with a depth attachment to test against.
+    let depth = match setup.depth_format {
+        Some(depth_format) => description.depth.map(|depth| DepthState {
+            format: depth_format,
+            ..depth
+        }),
+        None => None,
+    };
+    let depth_stencil_state = create_depth_stencil_state(depth);
+
+    let attachment_blends = [create_attachment_blend_state(description.blend)];
+    let color_blend_state = PipelineColorBlendStateCreateInfo::default()
+        .logic_op_enable(false)
+        .attachments(&attachment_blends)
+        .blend_constants([0.0f32, 0.0f32, 0.0f32, 0.0f32]);
+
+    let mut dynamic_states = vec![DynamicState::VIEWPORT, DynamicState::SCISSOR];
+    for dynamic_state in &description.dynamic_states {
+        if !dynamic_states.contains(dynamic_state) {
+            dynamic_states.push(*dynamic_state);
+        }
+    }
+    let dynamic_state = PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+    let pipeline_create_info = GraphicsPipelineCreateInfo::default()
+        .flags(PipelineCreateFlags::empty())
+        .stages(&shader_stage_infos)
+        .layout(layout)
+        .subpass(0)
+        .render_pass(render_pass)
+        .dynamic_state(&dynamic_state)
+        .viewport_state(&viewport_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .vertex_input_state(&vertex_input_state)
+        .rasterization_state(&rasterization_state)
+        .input_assembly_state(&input_assembly_state);
+
+    match unsafe {
+        ctxt.logical_device.create_graphics_pipelines(
+            pipeline_cache::handle(),
+            &[pipeline_create_info],
+            None,
+        )
+    } {
+        Ok(pipelines) => Ok(BuiltPipeline {
+            pipeline: *pipelines.first().unwrap(),
+            layout,
+        }),
+        Err((_, msg)) => {
+            unsafe { ctxt.logical_device.destroy_pipeline_layout(layout, None) };
+            Err(DustError::CreatePipelineFailed(msg))
+        }
+    }
+}
+
+fn find_shader(name: &str) -> Result<Arc<ShaderWrapper>, DustError> {
+    match shaders::shader_by_name(name) {
+        Some(shader) => Ok(shader),
+        None => Err(DustError::ShaderNotFound(String::from(name))),
+    }
+}
+
+// Every input the vertex shader reads must be fed by an attribute of the same format.  Catching
+// a mismatch here beats the validation layers - or worse, the driver - catching it later.
+fn check_vertex_layout(
+    vertex_shader: &ShaderWrapper,
+    description: &PipelineDescription,
+) -> Result<(), DustError> {
+    for input in vertex_shader.vertex_inputs() {
+        let attribute = description
+            .vertex_attributes
+            .iter()
+            .find(|attribute| attribute.location == input.location);
+
+        match attribute {
+            Some(attribute) if attribute.format == input.format => {}
+            _ => {
+                return Err(DustError::VertexInputMismatch(input.location));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Registry {
+    fn compatible_render_pass(&mut self, setup: &AttachmentSetup) -> Result<RenderPass, DustError> {
+        if let Some(render_pass) = self.render_passes.get(setup) {
+            return Ok(*render_pass);
+        }
+
+        let render_pass = targets::make_render_pass(
+            &self.logical_device,
+            setup,
+            AttachmentLoadOp::DONT_CARE,
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )?;
+        self.render_passes.insert(*setup, render_pass);
+
+        Ok(render_pass)
+    }
+}
+
+// *** create_reflected_pipeline_layout(ctxt, stages) -> Result<PipelineLayout, DustError>
+//
+// Builds a pipeline layout straight from what the shader stages declare: their descriptor
+// bindings merged set by set, and one push constant range per stage that has a push constant
+// block.  The set layouts are owned by the descriptor layout cache, so only the pipeline layout
+// itself needs destroying.
+//
+pub fn create_reflected_pipeline_layout(
+    ctxt: &VkContext,
+    stages: &[Arc<ShaderWrapper>],
+) -> Result<PipelineLayout, DustError> {
+    let descriptor_layouts = shaders::descriptor_set_layouts(stages)?;
+    let push_constant_ranges = shaders::push_constant_ranges(stages);
+
+    debug!("Size of layouts being bound: {}", descriptor_layouts.len());
+
+    let create_info = PipelineLayoutCreateInfo::default()
+        .flags(PipelineLayoutCreateFlags::empty())
+        .set_layouts(&descriptor_layouts)
+        .push_constant_ranges(&push_constant_ranges);
+
+    match unsafe {
+        ctxt.logical_device
+            .create_pipeline_layout(&create_info, None)
+    } {
+        Ok(layout) => Ok(layout),
+        Err(msg) => Err(DustError::vulkan("creating a pipeline layout", msg)),
+    }
+}
+
+pub fn fill_shader_stage_infos(
+    stages: &[Arc<ShaderWrapper>],
+) -> Vec<PipelineShaderStageCreateInfo<'_>> {
+    stages
+        .iter()
+        .map(|shader| {
+            PipelineShaderStageCreateInfo::default()
+                .name(shader.name.as_c_str())
+                .flags(PipelineShaderStageCreateFlags::empty())
+                .stage(shader.stage_flags())
+                .module(shader.shader_module)
+        })
+        .collect()
+}
+
+fn create_rasterization_state<'a>(
+    description: &PipelineDescription,
+) -> PipelineRasterizationStateCreateInfo<'a> {
+    PipelineRasterizationStateCreateInfo::default()
+        .flags(PipelineRasterizationStateCreateFlags::empty())
+        .depth_clamp_enable(false)
+        .cull_mode(description.cull_mode)
+        .front_face(description.front_face)
+        .polygon_mode(PolygonMode::FILL)
+        .depth_bias_enable(false)
+        .depth_bias_constant_factor(0.0f32)
+        .depth_bias_clamp(0.0f32)
+        .depth_bias_slope_factor(0.0f32)
+        .rasterizer_discard_enable(false)
+        .line_width(1.0f32)
+}
+
+fn create_multisample_state<'a>(
+    samples: SampleCountFlags,
+) -> PipelineMultisampleStateCreateInfo<'a> {
+    PipelineMultisampleStateCreateInfo::default()
+        .flags(PipelineMultisampleStateCreateFlags::empty())
+        .sample_shading_enable(false)
+        .rasterization_samples(samples)
+        .alpha_to_one_enable(false)
+        .alpha_to_coverage_enable(false)
+        .min_sample_shading(1.0)
+}
+
+fn create_depth_stencil_state<'a>(
+    depth: Option<DepthState>,
+) -> PipelineDepthStencilStateCreateInfo<'a> {
+    match depth {
+        Some(depth) => PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(depth.test)
+            .depth_write_enable(depth.write)
+            .depth_compare_op(depth.compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false),
+        None => PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false)
+            .depth_compare_op(CompareOp::ALWAYS)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false),
+    }
+}
+
+fn create_attachment_blend_state(blend: BlendMode) -> PipelineColorBlendAttachmentState {
+    let state = PipelineColorBlendAttachmentState::default()
+        .color_write_mask(ColorComponentFlags::RGBA)
+        .color_blend_op(BlendOp::ADD)
+        .alpha_blend_op(BlendOp::ADD);
+
+    match blend {
+        BlendMode::Opaque => state.blend_enable(false),
+        BlendMode::Alpha => state
+            .blend_enable(true)
+            .src_color_blend_factor(BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .src_alpha_blend_factor(BlendFactor::ONE)
+            .dst_alpha_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA),
+        BlendMode::PremultipliedAlpha => state
+            .blend_enable(true)
+            .src_color_blend_factor(BlendFactor::ONE)
+            .dst_color_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .src_alpha_blend_factor(BlendFactor::ONE)
+            .dst_alpha_blend_factor(BlendFactor::ONE_MINUS_SRC_ALPHA),
+        BlendMode::Additive => state
+            .blend_enable(true)
+            .src_color_blend_factor(BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(BlendFactor::ONE)
+            .src_alpha_blend_factor(BlendFactor::ZERO)
+            .dst_alpha_blend_factor(BlendFactor::ONE),
+    }
+}
@@ -0,0 +1,397 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use ash::{
+    vk::{
+        AttachmentLoadOp, ClearColorValue, ClearValue, CommandBuffer, CullModeFlags, DescriptorSet,
+        Extent2D, Extent3D, Format, Framebuffer, FramebufferCreateInfo, ImageCreateFlags,
+        ImageCreateInfo, ImageLayout, ImageTiling, ImageType, ImageUsageFlags, ImageView, Offset2D,
+        PipelineBindPoint, Rect2D, RenderPass, RenderPassBeginInfo, SampleCountFlags,
+        ShaderStageFlags, SharingMode, SubpassContents,
+    },
+    Device,
+};
+use log::{debug, error};
+
+use crate::{
+    dust_errors::DustError,
+    setup::{debug, instance::VkContext},
+};
+
+use super::{
+    image::{DustImage, TextureFilter},
+    pipelines::{self, PipelineDescription},
+    render, swapchain,
+    targets::{self, AttachmentSetup},
+    transfer,
+};
+
+// *** PostEffect
+//
+// One fullscreen pass of the post-processing chain, with its parameters.  Each effect reads the
+// output of the one before it, so order matters: grading before the CRT effects, for example,
+// grades the picture rather than the scanlines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostEffect {
+    // Barrel distortion of the picture plus darkening towards the corners.  A curvature of
+    // around 0.03 to 0.1 and a vignette of 0 to 1 look like a tube.
+    Crt { curvature: f32, vignette: f32 },
+    // Darkens every other line.  period is the height of one line and its gap, in screen
+    // pixels; intensity runs from 0 (off) to 1 (black gaps).
+    Scanlines { intensity: f32, period: f32 },
+    // Brightness scales the colour, then gamma is applied on top; 1.0 and 1.0 change nothing.
+    Gamma { gamma: f32, brightness: f32 },
+    // Looks the colour up in a size^3 LUT laid out as a (size * size) x size strip, made into a
+    // texture with linear filtering.  strength blends between the original (0) and graded (1).
+    ColorGrade { lut: DescriptorSet, strength: f32 },
+}
+
+impl PostEffect {
+    fn pipeline_name(&self) -> &'static str {
+        match self {
+            PostEffect::Crt { .. } => "post_crt",
+            PostEffect::Scanlines { .. } => "post_scanlines",
+            PostEffect::Gamma { .. } => "post_gamma",
+            PostEffect::ColorGrade { .. } => "post_color_grade",
+        }
+    }
+
+    // Laid out to match the push constant block of the effect's fragment shader.
+    fn push_constants(&self) -> Vec<u8> {
+        let values = match self {
+            PostEffect::Crt {
+                curvature,
+                vignette,
+            } => vec![*curvature, *vignette],
+            PostEffect::Scanlines { intensity, period } => vec![*intensity, *period],
+            PostEffect::Gamma { gamma, brightness } => vec![*gamma, *brightness],
+            PostEffect::ColorGrade { strength, .. } => vec![*strength],
+        };
+
+        values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+
+    fn extra_descriptor_sets(&self) -> Vec<DescriptorSet> {
+        match self {
+            PostEffect::ColorGrade { lut, .. } => vec![*lut],
+            _ => Vec::new(),
+        }
+    }
+}
+
+static POST: OnceLock<Mutex<PostState>> = OnceLock::new();
+
+struct PostState {
+    logical_device: Arc<Device>,
+    effects: Vec<PostEffect>,
+    targets: Option<Targets>,
+}
+
+// The two images the chain ping-pongs between, and what it takes to draw into them.  They match
+// the swapchain in size and format, so the same pipelines draw into either them or the swapchain.
+struct Targets {
+    extent: Extent2D,
+    format: Format,
+    images: [DustImage; 2],
+    textures: [DescriptorSet; 2],
+    framebuffers: [Framebuffer; 2],
+    // Overwrites every pixel, so does not bother to load or clear.
+    effect_pass: RenderPass,
+}
+
+// Called again with the new device when the device is rebuilt; the chain of effects is kept, and
+// its targets made again at the next frame.
+pub fn init(logical_device: Arc<Device>) -> Result<(), DustError> {
+    if let Some(state) = POST.get() {
+        state.lock().unwrap().logical_device = logical_device;
+        return Ok(());
+    }
+
+    let state = PostState {
+        logical_device,
+        effects: Vec::new(),
+        targets: None,
+    };
+
+    if POST.set(Mutex::new(state)).is_err() {
+        panic!("Unable to set the post-processing static.");
+    }
+
+    register_effect_pipelines()
+}
+
+fn register_effect_pipelines() -> Result<(), DustError> {
+    for (name, fragment_shader) in [
+        ("post_crt", "crt"),
+        ("post_scanlines", "scanlines"),
+        ("post_gamma", "gamma"),
+        ("post_color_grade", "color_grade"),
+    ] {
+        pipelines::register(
+            name,
+            PipelineDescription::new("fullscreen", fragment_shader).cull_mode(CullModeFlags::NONE),
+        )?;
+    }
+    Ok(())
+}
+
+pub fn destroy() {
+    match POST.get() {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            let device = state.logical_device.clone();
+
+            if let Some(targets) = state.targets.take() {
+                destroy_targets(&device, targets);
+            }
+        }
+        None => {
+            error!("Post-processing was never initialized; nothing to destroy.");
+        }
+    }
+}
+
+fn with_state<R>(action: impl FnOnce(&mut PostState) -> R) -> Result<R, DustError> {
+    match POST.get() {
+        Some(state) => Ok(action(&mut state.lock().unwrap())),
+        None => Err(DustError::NotInitialized("post-processing")),
+    }
+}
+
+// *** set_effects(effects: Vec<PostEffect>) -> Result<(), DustError>
+//
+// Replaces the post-processing chain; the next frame runs the new one.  An empty chain switches
+// post-processing off, and the scene goes straight to the swapchain again.
+//
+pub fn set_effects(effects: Vec<PostEffect>) -> Result<(), DustError> {
+    debug!("Post-processing chain is now {:?}", effects);
+    with_state(|state| state.effects = effects)
+}
+
+pub fn effects() -> Result<Vec<PostEffect>, DustError> {
+    with_state(|state| state.effects.clone())
+}
+
+// *** scene_target(ctxt: &VkContext, extent: Extent2D) -> Result<Option<ImageView>, DustError>
+//
+// Where the scene should be drawn this frame: None while the chain is empty, in which case it is
+// drawn straight to the swapchain; otherwise the first ping-pong target, which the scene pass
+// must leave in SHADER_READ_ONLY_OPTIMAL.  The targets are (re)created to match extent as needed.
+//
+pub fn scene_target(ctxt: &VkContext, extent: Extent2D) -> Result<Option<ImageView>, DustError> {
+    with_state(|state| {
+        if state.effects.is_empty() {
+            return Ok(None);
+        }
+
+        let format = swapchain::get_swapchain_format()?.format;
+        let stale = match &state.targets {
+            Some(targets) => targets.extent != extent || targets.format != format,
+            None => true,
+        };
+
+        if stale {
+            if let Some(targets) = state.targets.take() {
+                if let Err(msg) = unsafe { state.logical_device.device_wait_idle() } {
+                    return Err(DustError::vulkan("waiting for the device to go idle", msg));
+                }
+                destroy_targets(&state.logical_device, targets);
+            }
+            state.targets = Some(make_targets(ctxt, extent, format)?);
+        }
+
+        let targets = state.targets.as_ref().unwrap();
+        Ok(Some(targets.images[0].view))
+    })?
+}
+
+// *** record(ctxt, command_buffer, final_pass, final_framebuffer)
+//
+// Records the chain, after the scene has been drawn into the target scene_target handed out.
+// Each effect samples the previous result and draws into the other target, except the last,
+// which draws through final_pass into final_framebuffer - the swapchain image.
+//
+pub fn record(
+    ctxt: &VkContext,
+    command_buffer: CommandBuffer,
+    final_pass: RenderPass,
+    final_framebuffer: Framebuffer,
+) -> Result<(), DustError> {
+    with_state(|state| {
+        let targets = match &state.targets {
+            Some(targets) => targets,
+            None => {
+                return Err(DustError::NotInitialized(
+                    "the post-processing scene target",
+                ))
+            }
+        };
+
+        let last = state.effects.len() - 1;
+
+        for (index, effect) in state.effects.iter().enumerate() {
+            let source = index % 2;
+            let (render_pass, framebuffer) = if index == last {
+                (final_pass, final_framebuffer)
+            } else {
+                (targets.effect_pass, targets.framebuffers[1 - source])
+            };
+
+            let pipeline = pipelines::get(ctxt, effect.pipeline_name())?;
+
+            let mut descriptor_sets = vec![targets.textures[source]];
+            descriptor_sets.extend(effect.extra_descriptor_sets());
+
+            // Clearing costs next to nothing, and the final pass shares its render pass with the
+            // scene, which clears.
+            let clear_values = [ClearValue {
+                color: ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            }];
+
+            debug::begin_label(command_buffer, effect.pipeline_name());
+            unsafe {
+                ctxt.logical_device.cmd_begin_render_pass(
+                    command_buffer,
+                    &RenderPassBeginInfo::default()
+                        .render_pass(render_pass)
+                        .framebuffer(framebuffer)
+                        .clear_values(&clear_values)
+                        .render_area(
+                            Rect2D::default()
+                                .offset(Offset2D::default().x(0).y(0))
+                                .extent(targets.extent),
+                        ),
+                    SubpassContents::INLINE,
+                );
+                ctxt.logical_device.cmd_bind_pipeline(
+                    command_buffer,
+                    PipelineBindPoint::GRAPHICS,
+                    pipeline.pipeline,
+                );
+                pipelines::set_viewport(ctxt, command_buffer, targets.extent);
+                ctxt.logical_device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    PipelineBindPoint::GRAPHICS,
+                    pipeline.layout,
+                    0,
+                    &descriptor_sets,
+                    &[],
+                );
+                ctxt.logical_device.cmd_push_constants(
+                    command_buffer,
+                    pipeline.layout,
+                    ShaderStageFlags::FRAGMENT,
+                    0,
+                    &effect.push_constants(),
+                );
+                ctxt.logical_device.cmd_draw(command_buffer, 3, 1, 0, 0);
+                ctxt.logical_device.cmd_end_render_pass(command_buffer);
+            }
+            debug::end_label(command_buffer);
+        }
+
+        Ok(())
+    })?
+}
+
+fn make_targets(ctxt: &VkContext, extent: Extent2D, format: Format) -> Result<Targets, DustError> {
+    debug!(
+        "Creating {}x{} post-processing targets",
+        extent.width, extent.height
+    );
+
+    let texture_layout = render::create_texture_descriptor_set_layout()?;
+    let make_image = || {
+        let mut image = transfer::make_image(
+            ctxt,
+            &ImageCreateInfo::default()
+                .initial_layout(ImageLayout::UNDEFINED)
+                .sharing_mode(SharingMode::EXCLUSIVE)
+                .image_type(ImageType::TYPE_2D)
+                .array_layers(1)
+                .format(format)
+                .extent(
+                    Extent3D::default()
+                        .width(extent.width)
+                        .height(extent.height)
+                        .depth(1),
+                )
+                .mip_levels(1)
+                .samples(SampleCountFlags::TYPE_1)
+                .flags(ImageCreateFlags::empty())
+                .usage(ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::SAMPLED)
+                .tiling(ImageTiling::OPTIMAL),
+        )?;
+        // Effects like the CRT curve sample between pixels.
+        let texture = image.make_texture(texture_layout, TextureFilter::Linear)?;
+        Ok::<_, DustError>((image, texture))
+    };
+    let (first, first_texture) = make_image()?;
+    let (second, second_texture) = make_image()?;
+    first.set_name("post_target_0");
+    second.set_name("post_target_1");
+    let images = [first, second];
+
+    let effect_pass = targets::make_render_pass(
+        &ctxt.logical_device,
+        &AttachmentSetup::new(format),
+        AttachmentLoadOp::DONT_CARE,
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    )?;
+
+    let framebuffers = [
+        make_framebuffer(ctxt, effect_pass, &images[0], extent)?,
+        make_framebuffer(ctxt, effect_pass, &images[1], extent)?,
+    ];
+
+    Ok(Targets {
+        extent,
+        format,
+        images,
+        textures: [first_texture, second_texture],
+        framebuffers,
+        effect_pass,
+    })
+}
+
+fn destroy_targets(device: &Device, targets: Targets) {
+    unsafe {
+        for framebuffer in targets.framebuffers {
+            device.destroy_framebuffer(framebuffer, None);
+        }
+        device.destroy_render_pass(targets.effect_pass, None);
+    }
+    // Only once nothing refers to them any more.
+    drop(targets.images);
+}
+
+fn make_framebuffer(
+    ctxt: &VkContext,
+    render_pass: RenderPass,
+    image: &DustImage,
+    extent: Extent2D,
+) -> Result<Framebuffer, DustError> {
+    let attachments = [image.view];
+
+    match unsafe {
+        ctxt.logical_device.create_framebuffer(
+            &FramebufferCreateInfo::default()
+                .render_pass(render_pass)
+                .attachments(&attachments)
+                .width(extent.width)
+                .height(extent.height)
+                .layers(1),
+            None,
+        )
+    } {
+        Ok(framebuffer) => Ok(framebuffer),
+        Err(msg) => Err(DustError::vulkan(
+            "creating a post-processing framebuffer",
+            msg,
+        )),
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+};
+
+use ash::vk::{
+    Extent3D, Format, ImageCreateFlags, ImageCreateInfo, ImageLayout, ImageTiling, ImageType,
+    ImageUsageFlags, SampleCountFlags, Semaphore, SharingMode,
+};
+use log::{debug, info};
+
+use crate::{dust_errors::DustError, setup::instance::VkContext};
+
+use super::{
+    image::{DustImage, TextureFilter},
+    pools, render, transfer,
+};
+
+// Textures that outlive the device.  Each is kept alongside the pixels it was made from, so that
+// when the device is lost and rebuilt it can be uploaded again and the game carries on holding
+// the same TextureId.  Images made straight through transfer are not kept, and die with the
+// device.
+static RESIDENT: OnceLock<Mutex<Resident>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextureId(u32);
+
+struct Resident {
+    next_id: u32,
+    textures: BTreeMap<TextureId, Texture>,
+}
+
+struct Texture {
+    source: TextureSource,
+    // None between the device going and the textures being restored.
+    image: Option<DustImage>,
+}
+
+// What a texture is made from: tightly packed pixels in format, width by height.
+pub struct TextureSource {
+    pub name: String,
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub filter: TextureFilter,
+}
+
+impl TextureSource {
+    pub fn new(name: &str, pixels: Vec<u8>, width: u32, height: u32) -> TextureSource {
+        TextureSource {
+            name: String::from(name),
+            pixels,
+            width,
+            height,
+            format: Format::R8G8B8A8_SRGB,
+            filter: TextureFilter::Nearest,
+        }
+    }
+
+    pub fn format(mut self, format: Format) -> TextureSource {
+        self.format = format;
+        self
+    }
+
+    pub fn filter(mut self, filter: TextureFilter) -> TextureSource {
+        self.filter = filter;
+        self
+    }
+}
+
+fn resident() -> &'static Mutex<Resident> {
+    RESIDENT.get_or_init(|| {
+        Mutex::new(Resident {
+            next_id: 0,
+            textures: BTreeMap::new(),
+        })
+    })
+}
+
+// *** upload(ctxt: &VkContext, source: TextureSource) -> Result<(TextureId, Semaphore), DustError>
+//
+// Makes a sampled texture from source and keeps both.  The semaphore signals once the upload has
+// finished, and goes in the images_ready of the first frame that draws the texture.
+//
+pub fn upload(
+    ctxt: &VkContext,
+    source: TextureSource,
+) -> Result<(TextureId, Semaphore), DustError> {
+    let (image, ready) = make_image(ctxt, &source)?;
+
+    let mut resident = resident().lock().unwrap();
+    let id = TextureId(resident.next_id);
+    resident.next_id += 1;
+    resident.textures.insert(
+        id,
+        Texture {
+            source,
+            image: Some(image),
+        },
+    );
+
+    Ok((id, ready))
+}
+
+// *** with_texture<R>(id: TextureId, action: impl FnOnce(&DustImage) -> R) -> Result<R, DustError>
+//
+// Lends out the current image for id - for quad(), say.  The image changes when the device is
+// rebuilt, so hang on to the id rather than anything taken from the image.  An id that has been
+// forgotten, or whose image has not been restored since the device was lost, is an error.
+//
+pub fn with_texture<R>(
+    id: TextureId,
+    action: impl FnOnce(&DustImage) -> R,
+) -> Result<R, DustError> {
+    let resident = resident().lock().unwrap();
+    match resident
+        .textures
+        .get(&id)
+        .and_then(|texture| texture.image.as_ref())
+    {
+        Some(image) => Ok(action(image)),
+        None => Err(DustError::TextureNotResident(id.0)),
+    }
+}
+
+// Drops a texture and its pixels.  Nothing still in flight may be drawing with it; call
+// render::finish_frames first if a recent frame did.
+pub fn forget(id: TextureId) {
+    resident().lock().unwrap().textures.remove(&id);
+}
+
+// *** release()
+//
+// Destroys the GPU side of every resident texture, keeping their sources.  Runs as the device is
+// torn down, before anything the images depend on goes.
+//
+pub fn release() {
+    let mut resident = resident().lock().unwrap();
+    for texture in resident.textures.values_mut() {
+        texture.image = None;
+    }
+}
+
+// *** restore(ctxt: &VkContext) -> Result<Vec<Semaphore>, DustError>
+//
+// Uploads every resident texture again, on a device that has just been built.  The semaphores
+// signal as the uploads finish; they go in the images_ready of the next frame.  If an upload
+// fails, the semaphores of those that worked are destroyed before the error is returned.
+//
+pub fn restore(ctxt: &VkContext) -> Result<Vec<Semaphore>, DustError> {
+    let mut resident = resident().lock().unwrap();
+    let mut ready = Vec::with_capacity(resident.textures.len());
+
+    for texture in resident.textures.values_mut() {
+        match make_image(ctxt, &texture.source) {
+            Ok((image, uploaded)) => {
+                texture.image = Some(image);
+                ready.push(uploaded);
+            }
+            Err(msg) => {
+                // copy_to_image waited for each upload, so nothing is left to signal these.
+                for semaphore in ready {
+                    unsafe { ctxt.logical_device.destroy_semaphore(semaphore, None) };
+                }
+                return Err(msg);
+            }
+        }
+    }
+
+    info!("Restored {} resident textures", ready.len());
+    Ok(ready)
+}
+
+fn make_image(
+    ctxt: &VkContext,
+    source: &TextureSource,
+) -> Result<(DustImage, Semaphore), DustError> {
+    debug!(
+        "Uploading {} ({} x {}, {:?})",
+        source.name, source.width, source.height, source.format
+    );
+
+    let (mut image, ready) = transfer::copy_to_image(
+        &source.pixels,
+        ctxt,
+        &ImageCreateInfo::default()
+            .format(source.format)
+            .flags(ImageCreateFlags::empty())
+            .extent(
+                Extent3D::default()
+                    .depth(1)
+                    .width(source.width)
+                    .height(source.height),
+            )
+            .usage(ImageUsageFlags::SAMPLED | ImageUsageFlags::TRANSFER_DST)
+            .tiling(ImageTiling::OPTIMAL)
+            .samples(SampleCountFlags::TYPE_1)
+            .mip_levels(1)
+            .sharing_mode(SharingMode::EXCLUSIVE)
+            .array_layers(1)
+            .image_type(ImageType::TYPE_2D)
+            .initial_layout(ImageLayout::UNDEFINED),
+        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        pools::get_graphics_queue_family()?,
+    )?;
+
+    image.make_texture(
+        render::create_texture_descriptor_set_layout()?,
+        source.filter,
+    )?;
+    image.set_name(&source.name);
+
+    Ok((image, ready))
+}
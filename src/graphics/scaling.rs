@@ -0,0 +1,280 @@
+use ash::vk::Extent2D;
+
+use crate::dust_errors::DustError;
+
+use super::{
+    batch::Quad,
+    image::{DustImage, TextureFilter},
+};
+
+// How a logical resolution is fitted to the swapchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleMode {
+    // The largest whole multiple that fits, so every logical pixel covers the same number of
+    // screen pixels.  Whatever is left over is border.
+    Integer,
+    // As large as fits while keeping the logical pixels square, letterboxed or pillarboxed.
+    Letterbox,
+    // As large as fits while showing the logical image at a 4:3 aspect ratio, the way 320x200
+    // was shown on the CRTs it was drawn for.  Logical pixels come out taller than they are wide.
+    Aspect4By3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleFilter {
+    Nearest,
+    // Nearest up to the largest whole scale, bilinear for the fraction left over.  Keeps hard
+    // pixel edges without the uneven pixel widths nearest gives at non-integer scales.
+    SharpBilinear,
+}
+
+impl ScaleFilter {
+    // The sampler filtering the scaled image has to be made into a texture with.
+    pub fn texture_filter(&self) -> TextureFilter {
+        match self {
+            ScaleFilter::Nearest => TextureFilter::Nearest,
+            ScaleFilter::SharpBilinear => TextureFilter::Linear,
+        }
+    }
+
+    // The registered pipeline that draws with this filter.
+    pub fn pipeline_name(&self) -> &'static str {
+        match self {
+            ScaleFilter::Nearest => "sprite",
+            ScaleFilter::SharpBilinear => "sprite_sharp_bilinear",
+        }
+    }
+}
+
+// *** VirtualResolution
+//
+// A logical resolution to render at, and how to get it onto a swapchain of whatever size the
+// monitor turned out to be.  Everything drawn in logical coordinates goes through placement(),
+// so nothing else needs to know the size of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtualResolution {
+    pub extent: Extent2D,
+    pub mode: ScaleMode,
+    pub filter: ScaleFilter,
+}
+
+// Where the logical image lands on the target: centre and size in target pixels, and the scale
+// from logical to target pixels along each axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub scale: [f32; 2],
+}
+
+impl VirtualResolution {
+    // *** new(width: u32, height: u32) -> Option<VirtualResolution>
+    //
+    // Letterboxed with nearest filtering until told otherwise.  None if either side is zero,
+    // since there would be nothing to scale and placement would divide by it.
+    //
+    pub fn new(width: u32, height: u32) -> Option<VirtualResolution> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        Some(VirtualResolution {
+            extent: Extent2D::default().width(width).height(height),
+            mode: ScaleMode::Letterbox,
+            filter: ScaleFilter::Nearest,
+        })
+    }
+
+    pub fn mode(mut self, mode: ScaleMode) -> VirtualResolution {
+        self.mode = mode;
+        self
+    }
+
+    pub fn filter(mut self, filter: ScaleFilter) -> VirtualResolution {
+        self.filter = filter;
+        self
+    }
+
+    // *** placement(&self, target: Extent2D) -> Placement
+    //
+    // Fits the logical resolution to target according to the scale mode.  The result is
+    // centred, and its edges fall on whole target pixels so the border is crisp.  A target
+    // smaller than the logical resolution still gets a scale of 1 in Integer mode; the image is
+    // simply cropped.
+    //
+    pub fn placement(&self, target: Extent2D) -> Placement {
+        let logical_width = self.extent.width as f32;
+        let logical_height = self.extent.height as f32;
+        let target_width = target.width as f32;
+        let target_height = target.height as f32;
+
+        let scale = match self.mode {
+            ScaleMode::Integer => {
+                let whole = std::cmp::min(
+                    target.width / self.extent.width,
+                    target.height / self.extent.height,
+                );
+                let whole = std::cmp::max(whole, 1) as f32;
+                [whole, whole]
+            }
+            ScaleMode::Letterbox => {
+                let fit = f32::min(target_width / logical_width, target_height / logical_height);
+                [fit, fit]
+            }
+            ScaleMode::Aspect4By3 => {
+                let display_height = logical_width * 3.0 / 4.0;
+                let fit = f32::min(target_width / logical_width, target_height / display_height);
+                [fit, fit * display_height / logical_height]
+            }
+        };
+
+        let size = [
+            (logical_width * scale[0]).round(),
+            (logical_height * scale[1]).round(),
+        ];
+        let upper_left = [
+            ((target_width - size[0]) / 2.0).floor(),
+            ((target_height - size[1]) / 2.0).floor(),
+        ];
+
+        Placement {
+            position: [upper_left[0] + size[0] / 2.0, upper_left[1] + size[1] / 2.0],
+            size,
+            scale: [size[0] / logical_width, size[1] / logical_height],
+        }
+    }
+}
+
+impl Placement {
+    // A point in logical coordinates, in target pixels.
+    pub fn to_target(&self, logical: [f32; 2]) -> [f32; 2] {
+        [
+            self.position[0] - self.size[0] / 2.0 + logical[0] * self.scale[0],
+            self.position[1] - self.size[1] / 2.0 + logical[1] * self.scale[1],
+        ]
+    }
+
+    // A quad drawing the whole of image at its logical size, centred on a logical position.
+    pub fn quad(&self, image: &DustImage, logical_position: [f32; 2]) -> Result<Quad, DustError> {
+        let position = self.to_target(logical_position);
+        let size = [
+            image.extent.width as f32 * self.scale[0],
+            image.extent.height as f32 * self.scale[1],
+        ];
+
+        Ok(Quad {
+            position,
+            size,
+            ..image.quad(position, 1.0)?
+        })
+    }
+
+    // A quad filling the placement with image, which is expected to be the logical framebuffer.
+    pub fn fill(&self, image: &DustImage) -> Result<Quad, DustError> {
+        Ok(Quad {
+            size: self.size,
+            ..image.quad(self.position, 1.0)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(width: u32, height: u32) -> Extent2D {
+        Extent2D::default().width(width).height(height)
+    }
+
+    #[test]
+    fn integer_takes_the_largest_whole_scale_that_fits() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Integer)
+            .placement(target(1920, 1080));
+
+        assert_eq!(placement.scale, [5.0, 5.0]);
+        assert_eq!(placement.size, [1600.0, 1000.0]);
+        assert_eq!(placement.position, [960.0, 540.0]);
+    }
+
+    #[test]
+    fn integer_crops_rather_than_shrinks() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Integer)
+            .placement(target(300, 180));
+
+        assert_eq!(placement.scale, [1.0, 1.0]);
+        assert_eq!(placement.size, [320.0, 200.0]);
+        assert_eq!(placement.to_target([0.0, 0.0]), [-10.0, -10.0]);
+    }
+
+    #[test]
+    fn letterbox_fills_the_narrower_side() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Letterbox)
+            .placement(target(1920, 1080));
+
+        assert_eq!(placement.scale, [5.4, 5.4]);
+        assert_eq!(placement.size, [1728.0, 1080.0]);
+        assert_eq!(placement.to_target([0.0, 0.0]), [96.0, 0.0]);
+    }
+
+    #[test]
+    fn letterbox_puts_the_border_top_and_bottom_on_a_tall_target() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Letterbox)
+            .placement(target(640, 800));
+
+        assert_eq!(placement.size, [640.0, 400.0]);
+        assert_eq!(placement.to_target([0.0, 0.0]), [0.0, 200.0]);
+        assert_eq!(placement.to_target([320.0, 200.0]), [640.0, 600.0]);
+    }
+
+    #[test]
+    fn aspect_4_by_3_makes_the_pixels_taller() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Aspect4By3)
+            .placement(target(1920, 1080));
+
+        assert_eq!(placement.size, [1440.0, 1080.0]);
+        assert_eq!(placement.scale, [4.5, 5.4]);
+        assert_eq!(placement.to_target([0.0, 0.0]), [240.0, 0.0]);
+    }
+
+    #[test]
+    fn edges_fall_on_whole_pixels() {
+        let placement = VirtualResolution::new(320, 200)
+            .unwrap()
+            .mode(ScaleMode::Integer)
+            .placement(target(1001, 601));
+
+        let upper_left = placement.to_target([0.0, 0.0]);
+        assert_eq!(upper_left, [20.0, 0.0]);
+        assert_eq!(upper_left.map(f32::fract), [0.0, 0.0]);
+        assert_eq!(placement.position, [500.0, 300.0]);
+    }
+
+    #[test]
+    fn refuses_an_empty_logical_resolution() {
+        assert_eq!(VirtualResolution::new(0, 200), None);
+        assert_eq!(VirtualResolution::new(320, 0), None);
+        assert!(VirtualResolution::new(1, 1).is_some());
+    }
+
+    #[test]
+    fn sharp_bilinear_samples_linearly() {
+        assert_eq!(
+            ScaleFilter::SharpBilinear.texture_filter(),
+            TextureFilter::Linear
+        );
+        assert_eq!(
+            ScaleFilter::Nearest.texture_filter(),
+            TextureFilter::Nearest
+        );
+    }
+}
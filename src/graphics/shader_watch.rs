@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use log::{debug, error};
+
+// .spv files that have been written since the renderer last asked.  A set, because compilers
+// and editors tend to touch a file several times in quick succession and one reload is plenty.
+static CHANGED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+// *** start(shader_root: &Path)
+//
+// Puts an inotify watch on the shader directory and every directory beneath it, then hands the
+// watches to a background thread that records which .spv files change.  Nothing is reloaded
+// here; the renderer picks the changes up through shaders::apply_pending_reloads at the next
+// frame boundary.  Any failure just leaves hot reload switched off.
+//
+pub fn start(shader_root: &Path) {
+    let inotify = match Inotify::init() {
+        Ok(inotify) => inotify,
+        Err(msg) => {
+            error!(
+                "Unable to initialize inotify; shader hot reload is disabled: {:?}",
+                msg
+            );
+            return;
+        }
+    };
+
+    let mut directories = HashMap::new();
+    watch_directory(&inotify, shader_root, &mut directories);
+
+    if let Err(msg) = thread::Builder::new()
+        .name(String::from("shader-watch"))
+        .spawn(move || watch(inotify, directories))
+    {
+        error!(
+            "Unable to start the shader watcher thread; shader hot reload is disabled: {:?}",
+            msg
+        );
+    }
+}
+
+pub fn take_changed() -> Vec<PathBuf> {
+    std::mem::take(&mut *CHANGED.lock().unwrap())
+        .into_iter()
+        .collect()
+}
+
+// inotify watches are not recursive, so every subdirectory needs its own.
+fn watch_directory(
+    inotify: &Inotify,
+    path: &Path,
+    directories: &mut HashMap<WatchDescriptor, PathBuf>,
+) {
+    // CLOSE_WRITE catches compilers writing in place, MOVED_TO catches the write-then-rename
+    // most editors and build tools use.
+    match inotify
+        .watches()
+        .add(path, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)
+    {
+        Ok(descriptor) => {
+            debug!("Watching {:?} for shader changes", path);
+            directories.insert(descriptor, path.to_path_buf());
+        }
+        Err(msg) => {
+            error!("Unable to watch {:?} for shader changes: {:?}", path, msg);
+            return;
+        }
+    }
+
+    if let Ok(dir_contents) = read_dir(path) {
+        for entry in dir_contents.flatten() {
+            if entry.path().is_dir() {
+                watch_directory(inotify, &entry.path(), directories);
+            }
+        }
+    }
+}
+
+fn watch(mut inotify: Inotify, directories: HashMap<WatchDescriptor, PathBuf>) {
+    let mut buffer = [0; 4096];
+
+    loop {
+        let events = match inotify.read_events_blocking(&mut buffer) {
+            Ok(events) => events,
+            Err(msg) => {
+                error!(
+                    "Reading shader directory events failed; shader hot reload has stopped: {:?}",
+                    msg
+                );
+                return;
+            }
+        };
+
+        for event in events {
+            let path = match (directories.get(&event.wd), event.name) {
+                (Some(directory), Some(name)) => directory.join(name),
+                _ => continue,
+            };
+
+            if path.extension().and_then(|extension| extension.to_str()) == Some("spv") {
+                debug!("Shader changed on disk: {:?}", path);
+                CHANGED.lock().unwrap().insert(path);
+            }
+        }
+    }
+}
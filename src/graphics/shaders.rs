@@ -1,4 +1,11 @@
-use std::{collections::HashMap, ffi::CString, fs::read_dir, io::Read, path::Path, sync::OnceLock};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::CString,
+    fs::read_dir,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Once, OnceLock, RwLock},
+};
 #[cfg(all(target_os = "linux", not(target_os = "windows")))]
 use std::{
     fs::File,
@@ -8,7 +15,10 @@ use std::{
 
 use std::sync::Arc;
 
-use ash::vk::{ShaderModule, ShaderModuleCreateFlags, ShaderModuleCreateInfo};
+use ash::vk::{
+    DescriptorSetLayout, DescriptorSetLayoutBinding, PushConstantRange, ShaderModule,
+    ShaderModuleCreateFlags, ShaderModuleCreateInfo, ShaderStageFlags,
+};
 use ash::Device;
 use log::{debug, error};
 
@@ -21,38 +31,55 @@ use std::{
 
 use crate::{dust_errors::DustError, setup::instance::VkContext};
 
-static LOGICAL_DEVICE: OnceLock<Arc<Device>> = OnceLock::new();
-static mut SHADERS: OnceLock<HashMap<String, ShaderWrapper>> = OnceLock::new();
+#[cfg(all(feature = "shader-hot-reload", target_os = "linux"))]
+use super::shader_watch;
+use super::{
+    descriptors,
+    spirv::{self, DescriptorBinding, ExecutionModel, ShaderReflection, VertexInput},
+};
 
-pub fn init(device: Arc<Device>) {
-    match LOGICAL_DEVICE.set(device) {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("The logical device Arc could not be set in the shader module.");
-        }
-    };
+static LOGICAL_DEVICE: RwLock<Option<Arc<Device>>> = RwLock::new(None);
+// Shaders are handed out as Arcs so that a hot reload can swap an entry in the map without
+// pulling the module out from under anybody still building a pipeline from the old one.  The
+// module is destroyed when the last Arc goes.
+static SHADERS: OnceLock<RwLock<HashMap<String, Arc<ShaderWrapper>>>> = OnceLock::new();
 
-    let shaders = load_shaders();
+static WATCHING: Once = Once::new();
 
-    unsafe {
-        match SHADERS.set(shaders) {
-            Ok(_) => {}
-            Err(_) => {
-                panic!("The Shader map could not be set in the shader module.");
-            }
-        }
-    }
+// Set by set_shader_dir; None is the shaders directory beside the executable.
+static SHADER_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+// Called again with the new device when the device is rebuilt: the modules are loaded afresh for
+// it.  The watcher carries on from the first call.  Fails with ShaderDirectory if the shader
+// directory cannot be read.
+pub fn init(device: Arc<Device>) -> Result<(), DustError> {
+    *LOGICAL_DEVICE.write().unwrap() = Some(device.clone());
+
+    let shader_root = shader_root()?;
+    let shaders = load_shaders(&device, &shader_root)?;
+    *SHADERS
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .write()
+        .unwrap() = shaders;
+
+    WATCHING.call_once(|| {
+        #[cfg(all(feature = "shader-hot-reload", target_os = "linux"))]
+        shader_watch::start(&shader_root);
+    });
+
+    Ok(())
 }
 
 pub fn destroy(_ctxt: &VkContext) {
-    if let Some(shaders) = unsafe { SHADERS.take() } {
-        destroy_shaders(shaders);
+    if let Some(shaders) = SHADERS.get() {
+        shaders.write().unwrap().clear();
     }
+    *LOGICAL_DEVICE.write().unwrap() = None;
 }
 
-pub fn shader_by_name(name: &str) -> Option<&ShaderWrapper> {
-    match unsafe { SHADERS.get() } {
-        Some(map) => map.get(name),
+pub fn shader_by_name(name: &str) -> Option<Arc<ShaderWrapper>> {
+    match SHADERS.get() {
+        Some(map) => map.read().unwrap().get(name).cloned(),
         None => {
             error!("The shaders have not been loaded; Vulkan has not been properly initialized.");
             None
@@ -60,6 +87,34 @@ pub fn shader_by_name(name: &str) -> Option<&ShaderWrapper> {
     }
 }
 
+// *** apply_pending_reloads() -> Result<Vec<String>, DustError>
+//
+// Call once per frame, before any pipelines are built or bound.  Every .spv the watcher has seen
+// change since the last call is loaded and validated again; if that works the new module replaces
+// the old one and its name is returned, so whoever owns pipelines built from it knows to rebuild
+// them.  A module that fails to load, or that now declares a different stage, is logged and the
+// previous version stays in service.  Without the shader-hot-reload feature this does nothing.
+// NotInitialized before init().
+//
+pub fn apply_pending_reloads() -> Result<Vec<String>, DustError> {
+    #[cfg(all(feature = "shader-hot-reload", target_os = "linux"))]
+    {
+        let mut reloaded = Vec::new();
+        for path in shader_watch::take_changed() {
+            if let Some(name) = reload_shader_file(&path)? {
+                reloaded.push(name);
+            }
+        }
+        Ok(reloaded)
+    }
+
+    #[cfg(not(all(feature = "shader-hot-reload", target_os = "linux")))]
+    {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
@@ -69,10 +124,136 @@ pub enum ShaderType {
     Compute,
 }
 
+impl ShaderType {
+    fn from_execution_model(model: ExecutionModel) -> Option<ShaderType> {
+        match model {
+            ExecutionModel::Vertex => Some(ShaderType::Vertex),
+            ExecutionModel::Fragment => Some(ShaderType::Fragment),
+            ExecutionModel::TessellationControl => Some(ShaderType::TesselationControl),
+            ExecutionModel::TessellationEvaluation => Some(ShaderType::TesselationEval),
+            ExecutionModel::Geometry => Some(ShaderType::Geometry),
+            ExecutionModel::GLCompute => Some(ShaderType::Compute),
+            ExecutionModel::Unsupported(_) => None,
+        }
+    }
+
+    pub fn stage_flags(&self) -> ShaderStageFlags {
+        match self {
+            ShaderType::Vertex => ShaderStageFlags::VERTEX,
+            ShaderType::Fragment => ShaderStageFlags::FRAGMENT,
+            ShaderType::TesselationControl => ShaderStageFlags::TESSELLATION_CONTROL,
+            ShaderType::TesselationEval => ShaderStageFlags::TESSELLATION_EVALUATION,
+            ShaderType::Geometry => ShaderStageFlags::GEOMETRY,
+            ShaderType::Compute => ShaderStageFlags::COMPUTE,
+        }
+    }
+}
+
+// name is the entry point, ready to hand to PipelineShaderStageCreateInfo.  Everything else the
+// module declares about its interface is in reflection.
 pub struct ShaderWrapper {
     pub shader_module: ShaderModule,
     pub name: CString,
     pub shader_type: ShaderType,
+    pub reflection: ShaderReflection,
+    // The device the module was made on.  A shader still held when the device is rebuilt goes
+    // back to the one it came from, not the new one.
+    logical_device: Arc<Device>,
+}
+
+impl ShaderWrapper {
+    pub fn stage_flags(&self) -> ShaderStageFlags {
+        self.shader_type.stage_flags()
+    }
+
+    pub fn descriptor_bindings(&self) -> &[DescriptorBinding] {
+        &self.reflection.descriptor_bindings
+    }
+
+    pub fn push_constant_range(&self) -> Option<PushConstantRange> {
+        self.reflection.push_constant_range
+    }
+
+    pub fn vertex_inputs(&self) -> &[VertexInput] {
+        &self.reflection.vertex_inputs
+    }
+}
+
+impl Drop for ShaderWrapper {
+    fn drop(&mut self) {
+        unsafe {
+            self.logical_device
+                .destroy_shader_module(self.shader_module, None)
+        };
+    }
+}
+
+// *** descriptor_set_layouts(stages: &[Arc<ShaderWrapper>]) -> Result<Vec<DescriptorSetLayout>, DustError>
+//
+// Merges the descriptor bindings of every stage in a pipeline into one layout per set, in set
+// order.  A binding used by several stages is visible to all of them; sets that no stage uses get
+// an empty layout so the indices still line up.  The layouts come from the descriptor layout
+// cache and must not be destroyed by the caller.  Fails with ConflictingDescriptorType if two
+// stages disagree on what a binding holds.
+//
+pub fn descriptor_set_layouts(
+    stages: &[Arc<ShaderWrapper>],
+) -> Result<Vec<DescriptorSetLayout>, DustError> {
+    let mut sets: BTreeMap<u32, BTreeMap<u32, DescriptorSetLayoutBinding>> = BTreeMap::new();
+
+    for stage in stages {
+        for binding in stage.descriptor_bindings() {
+            let set = sets.entry(binding.set).or_default();
+            match set.get_mut(&binding.binding) {
+                Some(existing) if existing.descriptor_type == binding.descriptor_type => {
+                    existing.stage_flags |= stage.stage_flags();
+                    existing.descriptor_count =
+                        std::cmp::max(existing.descriptor_count, binding.count);
+                }
+                Some(existing) => {
+                    return Err(DustError::ConflictingDescriptorType {
+                        set: binding.set,
+                        binding: binding.binding,
+                        first: existing.descriptor_type,
+                        second: binding.descriptor_type,
+                    });
+                }
+                None => {
+                    set.insert(
+                        binding.binding,
+                        DescriptorSetLayoutBinding::default()
+                            .binding(binding.binding)
+                            .descriptor_type(binding.descriptor_type)
+                            .descriptor_count(binding.count)
+                            .stage_flags(stage.stage_flags()),
+                    );
+                }
+            }
+        }
+    }
+
+    let set_count = match sets.keys().next_back() {
+        Some(last) => last + 1,
+        None => 0,
+    };
+
+    (0..set_count)
+        .map(|set| {
+            let bindings: Vec<DescriptorSetLayoutBinding> = sets
+                .get(&set)
+                .map(|bindings| bindings.values().copied().collect())
+                .unwrap_or_default();
+            descriptors::layout_for(&bindings)
+        })
+        .collect()
+}
+
+// One range per stage that declares a push constant block.
+pub fn push_constant_ranges(stages: &[Arc<ShaderWrapper>]) -> Vec<PushConstantRange> {
+    stages
+        .iter()
+        .filter_map(|stage| stage.push_constant_range())
+        .collect()
 }
 
 // *** load_shader(file_name: &mut File) -> Result<Vec<u32>, Error>
@@ -114,11 +295,25 @@ fn load_shader(file_name: &mut File) -> Result<Vec<u32>, Error> {
     }
 }
 
-fn load_shaders() -> HashMap<String, ShaderWrapper> {
+// Where the compiled shaders are loaded from, in place of the shaders directory beside the
+// executable.  Read when the Vulkan context is created.
+pub fn set_shader_dir(directory: Option<PathBuf>) {
+    *SHADER_DIR.write().unwrap() = directory;
+}
+
+fn shader_root() -> Result<PathBuf, DustError> {
+    if let Some(directory) = SHADER_DIR.read().unwrap().clone() {
+        debug!("Shader root path: {:?}", directory);
+        return Ok(directory);
+    }
+
     let mut current_path = match std::env::current_exe() {
         Ok(path) => path,
         Err(msg) => {
-            panic!("There appears to be no current directory? {:?}", msg);
+            return Err(DustError::ShaderDirectory(format!(
+                "the executable it sits beside cannot be found ({})",
+                msg
+            )));
         }
     };
 
@@ -127,37 +322,64 @@ fn load_shaders() -> HashMap<String, ShaderWrapper> {
     current_path.push("shaders");
     debug!("Shader root path: {:?}", current_path);
 
+    Ok(current_path)
+}
+
+// *** load_shaders(device: &Arc<Device>, shader_root: &Path) -> Result<HashMap<String, Arc<ShaderWrapper>>, DustError>
+//
+// With the embedded-shaders feature, the modules baked into the executable are registered first
+// and the shader directory becomes optional.  Whatever is found on disk is loaded over the top,
+// replacing embedded modules of the same name - which is how mods override the shipped shaders.
+//
+fn load_shaders(
+    device: &Arc<Device>,
+    shader_root: &Path,
+) -> Result<HashMap<String, Arc<ShaderWrapper>>, DustError> {
     let mut storage = HashMap::new();
 
-    process_shader_directory(&current_path, &mut storage);
+    #[cfg(feature = "embedded-shaders")]
+    {
+        load_embedded_shaders(device, &mut storage);
 
-    // for (name, shader_type) in storage {
-    //     match shader_type {
-    //         ShaderType::Vertex(raw_code) => {
-    //             ShaderModuleCreateInfo::default()
-    //                 .flags(ShaderModuleCreateFlags::empty())
-    //                 .code(&raw_code);
-    //         }
-    //         _ => {}
-    //     }
-    // }
+        if !shader_root.is_dir() {
+            debug!(
+                "No shader directory at {:?}; running on embedded shaders alone.",
+                shader_root
+            );
+            return Ok(storage);
+        }
+    }
 
-    storage
+    process_shader_directory(device, shader_root, &mut storage)?;
+
+    Ok(storage)
 }
 
-fn destroy_shaders(mut shader_map: HashMap<String, ShaderWrapper>) {
-    match LOGICAL_DEVICE.get() {
-        Some(device) => shader_map.drain().for_each(|(_name, shader_type)| unsafe {
-            device.destroy_shader_module(shader_type.shader_module, None)
-        }),
+#[cfg(feature = "embedded-shaders")]
+mod embedded {
+    // Generated by build.rs: EMBEDDED_SHADERS, a list of (name, SPIR-V bytes) pairs.
+    include!(concat!(env!("OUT_DIR"), "/embedded_shaders.rs"));
+}
 
-        None => {
-            panic!("The Vulkan environment has not been initialized.");
+#[cfg(feature = "embedded-shaders")]
+fn load_embedded_shaders(device: &Arc<Device>, storage: &mut HashMap<String, Arc<ShaderWrapper>>) {
+    for (name, bytes) in embedded::EMBEDDED_SHADERS {
+        // include_bytes! makes no promises about alignment, so the words are copied out rather
+        // than reinterpreted in place.
+        let words: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        if let Some(shader) = make_shader(device, name, &words) {
+            storage.insert(String::from(*name), Arc::new(shader));
         }
-    };
+    }
+
+    debug!("Registered {} embedded shaders", storage.len());
 }
 
-fn make_shader_module(bytecode: &[u32]) -> Result<ShaderModule, DustError> {
+fn make_shader_module(device: &Device, bytecode: &[u32]) -> Result<ShaderModule, DustError> {
     debug!("Bytecode input size: {}", bytecode.len());
     let create_info = ShaderModuleCreateInfo::default()
         .flags(ShaderModuleCreateFlags::empty())
@@ -165,108 +387,202 @@ fn make_shader_module(bytecode: &[u32]) -> Result<ShaderModule, DustError> {
 
     debug!("Code size: {}", create_info.code_size);
 
-    match LOGICAL_DEVICE.get() {
-        Some(device) => match unsafe { device.create_shader_module(&create_info, None) } {
-            Ok(module) => Ok(module),
-            Err(msg) => Err(DustError::CreateShaderModuleFailed(msg)),
-        },
-        None => {
-            panic!("The Vulkan environment has not been initialized.  We cannot continue.");
-        }
+    match unsafe { device.create_shader_module(&create_info, None) } {
+        Ok(module) => Ok(module),
+        Err(msg) => Err(DustError::CreateShaderModuleFailed(msg)),
     }
 }
 
-fn process_shader_directory(path: &Path, storage: &mut HashMap<String, ShaderWrapper>) {
+fn process_shader_directory(
+    device: &Arc<Device>,
+    path: &Path,
+    storage: &mut HashMap<String, Arc<ShaderWrapper>>,
+) -> Result<(), DustError> {
     let dir_contents = match read_dir(path) {
         Ok(dir) => dir,
         Err(msg) => {
-            panic!(
-                "Unable to read the shaders subdirectory of the current directory {:?}: {:?}",
-                path, msg
-            );
+            return Err(DustError::ShaderDirectory(format!(
+                "{}: {}",
+                path.display(),
+                msg
+            )));
         }
     };
 
     for entry in dir_contents.flatten() {
         if entry.path().is_dir() {
-            process_shader_directory(&entry.path(), storage);
+            process_shader_directory(device, &entry.path(), storage)?;
         } else if entry.path().is_file() {
-            process_shader_file(&entry.path(), storage);
+            process_shader_file(device, &entry.path(), storage);
         }
     }
+
+    Ok(())
 }
 
-fn process_shader_file(path: &Path, storage: &mut HashMap<String, ShaderWrapper>) {
+fn process_shader_file(
+    device: &Arc<Device>,
+    path: &Path,
+    storage: &mut HashMap<String, Arc<ShaderWrapper>>,
+) {
+    if let Some((name, shader)) = read_shader_file(device, path) {
+        if storage.insert(name.clone(), Arc::new(shader)).is_some() {
+            debug!("{:?} overrides the shader {} loaded earlier", path, name);
+        }
+    }
+}
+
+// *** read_shader_file(device: &Arc<Device>, path: &Path) -> Option<(String, ShaderWrapper)>
+//
+// Loads a single compiled shader.  Only .spv files are considered; anything else sharing the
+// directory is logged and left alone.  The stage and entry point come from the module itself,
+// so the directory a shader sits in no longer matters - but the file stem is still the name it
+// is looked up by, and must be unique.
+//
+fn read_shader_file(device: &Arc<Device>, path: &Path) -> Option<(String, ShaderWrapper)> {
+    if path.extension().and_then(|extension| extension.to_str()) != Some("spv") {
+        debug!(
+            "Skipping non SPIR-V file in the shader directory: {:?}",
+            path
+        );
+        return None;
+    }
+
     debug!("Shader file being processed: {:?}", path);
-    if let Ok(mut file) = File::open(path) {
-        let shader_contents = match load_shader(&mut file) {
-            Ok(vec) => vec,
-            Err(msg) => {
-                debug!(
-                    "Attempting to load the shader resulted in an IO or file failure: {:?}",
-                    msg
-                );
-                return;
-            }
-        };
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(msg) => {
+            error!("Unable to open the shader file {:?}: {:?}", path, msg);
+            return None;
+        }
+    };
 
-        debug!("Shader contents size: {}", shader_contents.len());
+    let shader_contents = match load_shader(&mut file) {
+        Ok(vec) => vec,
+        Err(msg) => {
+            debug!(
+                "Attempting to load the shader resulted in an IO or file failure: {:?}",
+                msg
+            );
+            return None;
+        }
+    };
 
-        let module = match make_shader_module(&shader_contents) {
-            Ok(module) => module,
-            Err(msg) => {
-                error!("Shader load operation failed: {:?}", msg);
-                return;
-            }
-        };
+    debug!("Shader contents size: {}", shader_contents.len());
 
-        let shader_type = match path.to_str() {
-            Some(path_str) if path_str.contains("fragment") => {
-                ShaderType::Fragment
-            }
+    let shader_name = match path.file_stem().and_then(|file_stem| file_stem.to_str()) {
+        Some(name) => String::from(name),
+        None => {
+            error!(
+                "A shader name could not be converted from the OS-specific string {:?}",
+                path
+            );
+            return None;
+        }
+    };
 
-            Some(path_str) if path_str.contains("vertex") => ShaderType::Vertex,
-            Some(path_str) if path_str.contains("geometry") => {
-                ShaderType::Geometry
-            }
-            Some(path_str) if path_str.contains("tess_ctrl") => {
-                ShaderType::TesselationControl
-            }
-            Some(path_str) if path_str.contains("tess_eval") => {
-                ShaderType::TesselationEval
-            }
-            Some(path_str) if path_str.contains("compute") => ShaderType::Compute,
+    make_shader(device, &shader_name, &shader_contents).map(|shader| (shader_name, shader))
+}
 
-            Some(_) => unreachable!("Shaders must be one of fragment, vertex, geometry, tesselation control, \
-                tesselation evaluation, or compute, and must be in an appropriately named subdirectory."),
-            None => {
-                unreachable!("There should be no path that has allowed me to open a file and read it that then has no path.");
-            }
-        };
+// *** make_shader(logical_device: &Arc<Device>, shader_name: &str, shader_contents: &[u32]) -> Option<ShaderWrapper>
+//
+// Reflects and validates a SPIR-V module, then hands it to the driver.  Shared by shaders read
+// from disk and shaders embedded in the executable.
+//
+fn make_shader(
+    logical_device: &Arc<Device>,
+    shader_name: &str,
+    shader_contents: &[u32],
+) -> Option<ShaderWrapper> {
+    let reflection = match spirv::reflect(shader_contents) {
+        Ok(reflection) => reflection,
+        Err(msg) => {
+            error!(
+                "Unable to reflect the SPIR-V module {}: {}",
+                shader_name, msg
+            );
+            return None;
+        }
+    };
 
-        let shader_name = path
-            .file_stem()
-            .map(|file_name_os_str| file_name_os_str.to_str())
-            .map(|file_name_str| String::from(file_name_str.unwrap()));
-
-        match shader_name {
-            Some(name) => {
-                storage.insert(
-                    name.clone(),
-                    ShaderWrapper {
-                        shader_type,
-                        shader_module: module,
-                        // TODO: Come up with real error handling here, fucko
-                        name: CString::new(String::from("main")).unwrap(),
-                    },
-                );
-            }
-            None => {
-                error!(
-                    "A shader name could not be converted from the OS-specific string {:?}",
-                    path
-                );
-            }
-        };
+    let shader_type = match ShaderType::from_execution_model(reflection.execution_model) {
+        Some(shader_type) => shader_type,
+        None => {
+            error!(
+                "The shader {} uses an execution model we cannot build pipelines for: {:?}",
+                shader_name, reflection.execution_model
+            );
+            return None;
+        }
+    };
+
+    let entry_point = match CString::new(reflection.entry_point.clone()) {
+        Ok(entry_point) => entry_point,
+        Err(msg) => {
+            error!(
+                "The entry point name in {} cannot be passed to Vulkan: {:?}",
+                shader_name, msg
+            );
+            return None;
+        }
+    };
+
+    let module = match make_shader_module(logical_device, shader_contents) {
+        Ok(module) => module,
+        Err(msg) => {
+            error!("Shader load operation failed: {:?}", msg);
+            return None;
+        }
+    };
+
+    debug!(
+        "Loaded shader {} as {:?} with {} descriptor bindings",
+        shader_name,
+        shader_type,
+        reflection.descriptor_bindings.len()
+    );
+
+    Some(ShaderWrapper {
+        shader_type,
+        shader_module: module,
+        name: entry_point,
+        reflection,
+        logical_device: logical_device.clone(),
+    })
+}
+
+#[cfg(all(feature = "shader-hot-reload", target_os = "linux"))]
+fn reload_shader_file(path: &Path) -> Result<Option<String>, DustError> {
+    let (logical_device, shaders) = match (LOGICAL_DEVICE.read().unwrap().clone(), SHADERS.get()) {
+        (Some(logical_device), Some(shaders)) => (logical_device, shaders),
+        _ => return Err(DustError::NotInitialized("the shaders")),
+    };
+
+    let (name, shader) = match read_shader_file(&logical_device, path) {
+        Some(loaded) => loaded,
+        None => {
+            error!(
+                "Reloading {:?} failed; the previous version of the shader stays in use.",
+                path
+            );
+            return Ok(None);
+        }
+    };
+
+    let mut shaders = shaders.write().unwrap();
+
+    if let Some(previous) = shaders.get(&name) {
+        if previous.shader_type != shader.shader_type {
+            error!(
+                "Reloading {} would change it from a {:?} shader to a {:?} shader; keeping the previous version.",
+                name, previous.shader_type, shader.shader_type
+            );
+            return Ok(None);
+        }
     }
+
+    debug!("Hot reloaded shader {}", name);
+    shaders.insert(name.clone(), Arc::new(shader));
+
+    Ok(Some(name))
 }
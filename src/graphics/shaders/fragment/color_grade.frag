@@ -0,0 +1,44 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 0) uniform sampler sourceSampler;
+
+// A size^3 colour cube laid out as a strip of size slices, each size x size: red runs across a
+// slice, green down it, and blue picks the slice.
+layout(set = 1, binding = 0) uniform texture2D lut;
+layout(set = 1, binding = 0) uniform sampler lutSampler;
+
+layout(push_constant) uniform Grade {
+  float strength;
+} grade;
+
+vec3 lookUp(vec2 lutExtent, float size, vec2 redGreen, float slice) {
+  vec2 texel = vec2(slice * size + redGreen.x, redGreen.y);
+  return texture(sampler2D(lut, lutSampler), texel / lutExtent).rgb;
+}
+
+void main() {
+  vec3 color = clamp(texture(sampler2D(source, sourceSampler), fragUV).rgb, 0.0, 1.0);
+
+  vec2 lutExtent = vec2(textureSize(sampler2D(lut, lutSampler), 0));
+  float size = lutExtent.y;
+
+  // Red and green are filtered by the sampler within a slice; blue has to be blended between
+  // the two nearest slices by hand.
+  vec2 redGreen = color.rg * (size - 1.0) + 0.5;
+  float blue = color.b * (size - 1.0);
+  float lowerSlice = floor(blue);
+  float upperSlice = min(lowerSlice + 1.0, size - 1.0);
+
+  vec3 graded = mix(
+    lookUp(lutExtent, size, redGreen, lowerSlice),
+    lookUp(lutExtent, size, redGreen, upperSlice),
+    blue - lowerSlice
+  );
+
+  outColor = vec4(mix(color, graded, grade.strength), 1.0);
+}
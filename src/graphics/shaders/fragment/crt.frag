@@ -0,0 +1,34 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 0) uniform sampler sourceSampler;
+
+layout(push_constant) uniform Crt {
+  float curvature;
+  float vignette;
+} crt;
+
+void main() {
+  // Barrel distortion: push each point outwards by the square of its distance along the other
+  // axis, which bows the edges of the picture the way a curved tube does.
+  vec2 centred = fragUV * 2.0 - 1.0;
+  vec2 bulge = centred.yx * centred.yx * crt.curvature;
+  vec2 uv = (centred + centred * bulge) * 0.5 + 0.5;
+
+  if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+    outColor = vec4(0.0, 0.0, 0.0, 1.0);
+    return;
+  }
+
+  vec3 color = texture(sampler2D(source, sourceSampler), uv).rgb;
+
+  // Darken towards the corners of the tube.
+  float edge = uv.x * uv.y * (1.0 - uv.x) * (1.0 - uv.y) * 16.0;
+  float shade = mix(1.0, clamp(pow(edge, 0.25), 0.0, 1.0), crt.vignette);
+
+  outColor = vec4(color * shade, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 0) uniform sampler sourceSampler;
+
+layout(push_constant) uniform Gamma {
+  float gamma;
+  float brightness;
+} adjust;
+
+void main() {
+  vec3 color = texture(sampler2D(source, sourceSampler), fragUV).rgb * adjust.brightness;
+  outColor = vec4(pow(max(color, vec3(0.0)), vec3(1.0 / adjust.gamma)), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 0) uniform sampler sourceSampler;
+
+layout(push_constant) uniform Scanlines {
+  float intensity;
+  // Height of one scanline, dark gap included, in target pixels.
+  float period;
+} scanlines;
+
+void main() {
+  vec3 color = texture(sampler2D(source, sourceSampler), fragUV).rgb;
+
+  float phase = gl_FragCoord.y / max(scanlines.period, 1.0) * 6.28318530718;
+  float line = 0.5 + 0.5 * cos(phase);
+
+  outColor = vec4(color * mix(1.0, line, scanlines.intensity), 1.0);
+}
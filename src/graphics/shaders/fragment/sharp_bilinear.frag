@@ -0,0 +1,25 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D spriteTexture;
+layout(set = 0, binding = 0) uniform sampler spriteSampler;
+
+// Sharp bilinear: scale each texel up by the largest whole factor that fits with nearest
+// filtering, then let the bilinear sampler blend only the thin band left over at the texel
+// edges.  Pixels stay crisp at non-integer scales without the uneven widths of plain nearest.
+void main() {
+  vec2 textureExtent = vec2(textureSize(sampler2D(spriteTexture, spriteSampler), 0));
+  vec2 texel = fragUV * textureExtent;
+  vec2 prescale = max(floor(1.0 / fwidth(texel)), vec2(1.0));
+
+  vec2 centreDistance = fract(texel) - 0.5;
+  vec2 regionRange = 0.5 - 0.5 / prescale;
+  vec2 blend = (centreDistance - clamp(centreDistance, -regionRange, regionRange)) * prescale + 0.5;
+
+  vec2 sharpUV = (floor(texel) + blend) / textureExtent;
+  outColor = texture(sampler2D(spriteTexture, spriteSampler), sharpUV) * fragColor;
+}
@@ -0,0 +1,15 @@
+#version 460
+
+layout(location = 0) in vec2 fragUV;
+layout(location = 1) in vec4 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+// The image and sampler share a binding, which Vulkan backs with a single combined image sampler
+// descriptor.  They are declared separately because the shader compiler does not accept sampler2D.
+layout(set = 0, binding = 0) uniform texture2D spriteTexture;
+layout(set = 0, binding = 0) uniform sampler spriteSampler;
+
+void main() {
+  outColor = texture(sampler2D(spriteTexture, spriteSampler), fragUV) * fragColor;
+}
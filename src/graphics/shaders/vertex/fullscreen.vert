@@ -0,0 +1,12 @@
+#version 460
+
+layout(location = 0) out vec2 fragUV;
+
+void main() {
+  // A single triangle big enough to cover the whole target, drawn without a vertex buffer:
+  // vertices 0, 1 and 2 land at (0, 0), (2, 0) and (0, 2) in UV space.  Whatever falls outside
+  // clip space is clipped away.
+  vec2 uv = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+  fragUV = uv;
+  gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
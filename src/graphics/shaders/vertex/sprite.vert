@@ -0,0 +1,21 @@
+#version 460
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec2 inUV;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec2 fragUV;
+layout(location = 1) out vec4 fragColor;
+
+layout(push_constant) uniform Target {
+  vec2 screenSize;
+} target;
+
+void main() {
+  // Quads arrive in pixel coordinates with the origin in the upper left, which lines up with
+  // Vulkan's clip space once scaled into [-1, 1].
+  vec2 clipPosition = (inPosition / target.screenSize) * 2.0 - 1.0;
+  gl_Position = vec4(clipPosition, 0.0, 1.0);
+  fragUV = inUV;
+  fragColor = inColor;
+}
@@ -0,0 +1,987 @@
+use std::{collections::HashMap, fmt};
+
+use ash::vk::{DescriptorType, Format, PushConstantRange, ShaderStageFlags};
+use log::{debug, warn};
+
+// Reflection over compiled SPIR-V modules.  Everything below is decoded straight out of the
+// instruction stream as laid out in the SPIR-V specification
+// (https://registry.khronos.org/SPIR-V/specs/unified1/SPIRV.html); only the handful of
+// instructions needed to describe a shader's interface to Vulkan are looked at, everything else
+// is skipped over by word count.
+
+const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+// Opcodes
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+// Image dimensionalities that change the descriptor type
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+
+// Arrays and structs nest far less deeply than this in any real shader.  A module that goes
+// deeper is malformed - most likely a type that contains itself.
+const MAX_TYPE_DEPTH: u32 = 64;
+
+// The offsets are word offsets of the offending instruction into the module, the ids those of
+// the offending type.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SpirvError {
+    TooShort,
+    BadMagic(u32),
+    TruncatedInstruction(usize),
+    MissingOperands(usize),
+    UnterminatedString(usize),
+    NoEntryPoint,
+    TypeTooDeep(u32),
+    TypeTooLarge(u32),
+}
+
+impl fmt::Display for SpirvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpirvError::TooShort => write!(f, "the module is shorter than its header"),
+            SpirvError::BadMagic(magic) => {
+                write!(f, "{:#010x} is not the SPIR-V magic number", magic)
+            }
+            SpirvError::TruncatedInstruction(offset) => {
+                write!(
+                    f,
+                    "the instruction at word {} runs off the end of the module",
+                    offset
+                )
+            }
+            SpirvError::MissingOperands(offset) => {
+                write!(f, "the instruction at word {} is missing operands", offset)
+            }
+            SpirvError::UnterminatedString(offset) => {
+                write!(
+                    f,
+                    "the string in the instruction at word {} is not terminated",
+                    offset
+                )
+            }
+            SpirvError::NoEntryPoint => write!(f, "the module has no entry point"),
+            SpirvError::TypeTooDeep(id) => write!(
+                f,
+                "type {} nests more than {} levels deep, or contains itself",
+                id, MAX_TYPE_DEPTH
+            ),
+            SpirvError::TypeTooLarge(id) => {
+                write!(f, "the size or element count of type {} overflows", id)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionModel {
+    Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
+    Fragment,
+    GLCompute,
+    Unsupported(u32),
+}
+
+impl ExecutionModel {
+    fn from_word(word: u32) -> ExecutionModel {
+        match word {
+            0 => ExecutionModel::Vertex,
+            1 => ExecutionModel::TessellationControl,
+            2 => ExecutionModel::TessellationEvaluation,
+            3 => ExecutionModel::Geometry,
+            4 => ExecutionModel::Fragment,
+            5 => ExecutionModel::GLCompute,
+            other => ExecutionModel::Unsupported(other),
+        }
+    }
+
+    pub fn stage_flags(&self) -> ShaderStageFlags {
+        match self {
+            ExecutionModel::Vertex => ShaderStageFlags::VERTEX,
+            ExecutionModel::TessellationControl => ShaderStageFlags::TESSELLATION_CONTROL,
+            ExecutionModel::TessellationEvaluation => ShaderStageFlags::TESSELLATION_EVALUATION,
+            ExecutionModel::Geometry => ShaderStageFlags::GEOMETRY,
+            ExecutionModel::Fragment => ShaderStageFlags::FRAGMENT,
+            ExecutionModel::GLCompute => ShaderStageFlags::COMPUTE,
+            ExecutionModel::Unsupported(_) => ShaderStageFlags::empty(),
+        }
+    }
+}
+
+// A single descriptor binding as the shader declares it.  count is the array length, or 0 for a
+// runtime sized array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: DescriptorType,
+    pub count: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub format: Format,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub execution_model: ExecutionModel,
+    pub entry_point: String,
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub push_constant_range: Option<PushConstantRange>,
+    pub vertex_inputs: Vec<VertexInput>,
+    pub local_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone, Debug)]
+enum SpirvType {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length_id: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default, Debug)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default, Debug)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+struct Variable {
+    id: u32,
+    pointer_type: u32,
+    storage_class: u32,
+}
+
+#[derive(Default)]
+struct Module {
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), MemberDecorations>,
+    variables: Vec<Variable>,
+}
+
+// *** reflect(words: &[u32]) -> Result<ShaderReflection, SpirvError>
+//
+// Walks the module once, collecting entry points, types, constants, decorations and global
+// variables, then resolves the variables into descriptor bindings, a push constant range and - for
+// vertex shaders - vertex inputs.  Only the first entry point is reported; every shader we ship
+// has exactly one.
+//
+pub fn reflect(words: &[u32]) -> Result<ShaderReflection, SpirvError> {
+    if words.len() < HEADER_WORDS {
+        return Err(SpirvError::TooShort);
+    }
+
+    if words[0] != MAGIC {
+        return Err(SpirvError::BadMagic(words[0]));
+    }
+
+    let mut entry_point: Option<(ExecutionModel, u32, String)> = None;
+    let mut local_sizes: HashMap<u32, [u32; 3]> = HashMap::new();
+    let mut module = Module::default();
+
+    let mut offset = HEADER_WORDS;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        let opcode = words[offset] & 0xffff;
+
+        if word_count == 0 || offset + word_count > words.len() {
+            return Err(SpirvError::TruncatedInstruction(offset));
+        }
+
+        let operands = &words[offset + 1..offset + word_count];
+        if operands.len() < operands_read(opcode, operands) {
+            return Err(SpirvError::MissingOperands(offset));
+        }
+
+        match opcode {
+            OP_ENTRY_POINT if entry_point.is_none() => {
+                let (name, _) = decode_string(&operands[2..], offset)?;
+                entry_point = Some((ExecutionModel::from_word(operands[0]), operands[1], name));
+            }
+            OP_EXECUTION_MODE if operands[1] == EXECUTION_MODE_LOCAL_SIZE => {
+                local_sizes.insert(operands[0], [operands[2], operands[3], operands[4]]);
+            }
+            OP_TYPE_INT => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Int {
+                        width: operands[1],
+                        signed: operands[2] == 1,
+                    },
+                );
+            }
+            OP_TYPE_FLOAT => {
+                module
+                    .types
+                    .insert(operands[0], SpirvType::Float { width: operands[1] });
+            }
+            OP_TYPE_VECTOR => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Vector {
+                        component: operands[1],
+                        count: operands[2],
+                    },
+                );
+            }
+            OP_TYPE_MATRIX => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Matrix {
+                        column: operands[1],
+                        count: operands[2],
+                    },
+                );
+            }
+            OP_TYPE_IMAGE => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Image {
+                        dim: operands[2],
+                        sampled: operands[6],
+                    },
+                );
+            }
+            OP_TYPE_SAMPLER => {
+                module.types.insert(operands[0], SpirvType::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                module.types.insert(operands[0], SpirvType::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Array {
+                        element: operands[1],
+                        length_id: operands[2],
+                    },
+                );
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::RuntimeArray {
+                        element: operands[1],
+                    },
+                );
+            }
+            OP_TYPE_STRUCT => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Struct {
+                        members: operands[1..].to_vec(),
+                    },
+                );
+            }
+            OP_TYPE_POINTER => {
+                module.types.insert(
+                    operands[0],
+                    SpirvType::Pointer {
+                        pointee: operands[2],
+                    },
+                );
+            }
+            OP_CONSTANT => {
+                // 64 bit constants carry a second word; array lengths never need it.
+                module.constants.insert(operands[1], operands[2]);
+            }
+            OP_VARIABLE => {
+                module.variables.push(Variable {
+                    pointer_type: operands[0],
+                    id: operands[1],
+                    storage_class: operands[2],
+                });
+            }
+            OP_DECORATE => {
+                let decorations = module.decorations.entry(operands[0]).or_default();
+                match operands[1] {
+                    DECORATION_DESCRIPTOR_SET => decorations.set = Some(operands[2]),
+                    DECORATION_BINDING => decorations.binding = Some(operands[2]),
+                    DECORATION_LOCATION => decorations.location = Some(operands[2]),
+                    DECORATION_BUILT_IN => decorations.built_in = true,
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operands[2]),
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE => {
+                let decorations = module
+                    .member_decorations
+                    .entry((operands[0], operands[1]))
+                    .or_default();
+                match operands[2] {
+                    DECORATION_OFFSET => decorations.offset = Some(operands[3]),
+                    DECORATION_MATRIX_STRIDE => decorations.matrix_stride = Some(operands[3]),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        offset += word_count;
+    }
+
+    let (execution_model, entry_id, entry_name) = match entry_point {
+        Some(entry_point) => entry_point,
+        None => {
+            return Err(SpirvError::NoEntryPoint);
+        }
+    };
+
+    debug!(
+        "Reflected entry point {} with execution model {:?}",
+        entry_name, execution_model
+    );
+
+    let vertex_inputs = if execution_model == ExecutionModel::Vertex {
+        module.vertex_inputs()
+    } else {
+        Vec::new()
+    };
+
+    Ok(ShaderReflection {
+        execution_model,
+        entry_point: entry_name,
+        descriptor_bindings: module.descriptor_bindings()?,
+        push_constant_range: module.push_constant_range(execution_model.stage_flags())?,
+        vertex_inputs,
+        local_size: local_sizes.get(&entry_id).copied(),
+    })
+}
+
+// How many operands reflect() reads from an instruction, so that a malformed one is refused
+// rather than indexed past its end.
+fn operands_read(opcode: u32, operands: &[u32]) -> usize {
+    match opcode {
+        OP_ENTRY_POINT => 3,
+        OP_EXECUTION_MODE => match operands.get(1) {
+            Some(&EXECUTION_MODE_LOCAL_SIZE) => 5,
+            _ => 2,
+        },
+        OP_TYPE_SAMPLER | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_STRUCT => 1,
+        OP_TYPE_FLOAT | OP_TYPE_RUNTIME_ARRAY => 2,
+        OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER
+        | OP_CONSTANT | OP_VARIABLE => 3,
+        OP_TYPE_IMAGE => 7,
+        OP_DECORATE => match operands.get(1) {
+            Some(
+                &(DECORATION_DESCRIPTOR_SET
+                | DECORATION_BINDING
+                | DECORATION_LOCATION
+                | DECORATION_ARRAY_STRIDE),
+            ) => 3,
+            _ => 2,
+        },
+        OP_MEMBER_DECORATE => match operands.get(2) {
+            Some(&(DECORATION_OFFSET | DECORATION_MATRIX_STRIDE)) => 4,
+            _ => 3,
+        },
+        _ => 0,
+    }
+}
+
+// Literal strings are nul terminated UTF-8, packed four bytes to a word, low byte first.
+fn decode_string(words: &[u32], instruction_offset: usize) -> Result<(String, usize), SpirvError> {
+    let mut bytes = Vec::new();
+
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                return Ok((String::from_utf8_lossy(&bytes).into_owned(), index + 1));
+            }
+            bytes.push(byte);
+        }
+    }
+
+    Err(SpirvError::UnterminatedString(instruction_offset))
+}
+
+// A separate image and sampler declared on the same set and binding are how the build step
+// expresses a sampler2D; Vulkan backs the pair with one combined image sampler descriptor.
+fn combine_image_samplers(bindings: Vec<DescriptorBinding>) -> Vec<DescriptorBinding> {
+    let mut combined: Vec<DescriptorBinding> = Vec::with_capacity(bindings.len());
+
+    for binding in bindings {
+        match combined.last_mut() {
+            Some(previous)
+                if previous.set == binding.set
+                    && previous.binding == binding.binding
+                    && matches!(
+                        (previous.descriptor_type, binding.descriptor_type),
+                        (DescriptorType::SAMPLED_IMAGE, DescriptorType::SAMPLER)
+                            | (DescriptorType::SAMPLER, DescriptorType::SAMPLED_IMAGE)
+                    ) =>
+            {
+                previous.descriptor_type = DescriptorType::COMBINED_IMAGE_SAMPLER;
+            }
+            _ => combined.push(binding),
+        }
+    }
+
+    combined
+}
+
+impl Module {
+    fn pointee(&self, pointer_type: u32) -> Option<u32> {
+        match self.types.get(&pointer_type) {
+            Some(SpirvType::Pointer { pointee }) => Some(*pointee),
+            _ => None,
+        }
+    }
+
+    fn descriptor_bindings(&self) -> Result<Vec<DescriptorBinding>, SpirvError> {
+        let mut bindings = Vec::new();
+
+        for variable in &self.variables {
+            let (set, binding) = match self.decorations.get(&variable.id) {
+                Some(Decorations {
+                    set: Some(set),
+                    binding: Some(binding),
+                    ..
+                }) => (*set, *binding),
+                _ => continue,
+            };
+
+            let pointee = match self.pointee(variable.pointer_type) {
+                Some(pointee) => pointee,
+                None => continue,
+            };
+
+            let (element, count) = self.unwrap_arrays(pointee, 0)?;
+
+            match self.descriptor_type(variable.storage_class, element) {
+                Some(descriptor_type) => bindings.push(DescriptorBinding {
+                    set,
+                    binding,
+                    descriptor_type,
+                    count,
+                }),
+                None => {
+                    warn!(
+                        "Variable {} at set {}, binding {} has no recognisable descriptor type.",
+                        variable.id, set, binding
+                    );
+                }
+            }
+        }
+
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        Ok(combine_image_samplers(bindings))
+    }
+
+    // Strips array wrappers off a type, returning the element type and the total element count.
+    // depth is how many arrays have been stripped on the way here.
+    fn unwrap_arrays(&self, type_id: u32, depth: u32) -> Result<(u32, u32), SpirvError> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(SpirvError::TypeTooDeep(type_id));
+        }
+
+        match self.types.get(&type_id) {
+            Some(SpirvType::Array { element, length_id }) => {
+                let length = self.constants.get(length_id).copied().unwrap_or(1);
+                let (inner, inner_count) = self.unwrap_arrays(*element, depth + 1)?;
+                match length.checked_mul(inner_count) {
+                    Some(count) => Ok((inner, count)),
+                    None => Err(SpirvError::TypeTooLarge(type_id)),
+                }
+            }
+            Some(SpirvType::RuntimeArray { element }) => Ok((*element, 0)),
+            _ => Ok((type_id, 1)),
+        }
+    }
+
+    fn descriptor_type(&self, storage_class: u32, type_id: u32) -> Option<DescriptorType> {
+        match (storage_class, self.types.get(&type_id)) {
+            (STORAGE_UNIFORM_CONSTANT, Some(SpirvType::SampledImage)) => {
+                Some(DescriptorType::COMBINED_IMAGE_SAMPLER)
+            }
+            (STORAGE_UNIFORM_CONSTANT, Some(SpirvType::Sampler)) => Some(DescriptorType::SAMPLER),
+            (STORAGE_UNIFORM_CONSTANT, Some(SpirvType::Image { dim, sampled })) => {
+                match (*dim, *sampled) {
+                    (DIM_SUBPASS_DATA, _) => Some(DescriptorType::INPUT_ATTACHMENT),
+                    (DIM_BUFFER, 2) => Some(DescriptorType::STORAGE_TEXEL_BUFFER),
+                    (DIM_BUFFER, _) => Some(DescriptorType::UNIFORM_TEXEL_BUFFER),
+                    (_, 2) => Some(DescriptorType::STORAGE_IMAGE),
+                    _ => Some(DescriptorType::SAMPLED_IMAGE),
+                }
+            }
+            (STORAGE_UNIFORM, Some(SpirvType::Struct { .. })) => {
+                let buffer_block = self
+                    .decorations
+                    .get(&type_id)
+                    .map(|decorations| decorations.buffer_block)
+                    .unwrap_or(false);
+                if buffer_block {
+                    Some(DescriptorType::STORAGE_BUFFER)
+                } else {
+                    Some(DescriptorType::UNIFORM_BUFFER)
+                }
+            }
+            (STORAGE_STORAGE_BUFFER, Some(SpirvType::Struct { .. })) => {
+                Some(DescriptorType::STORAGE_BUFFER)
+            }
+            _ => None,
+        }
+    }
+
+    fn push_constant_range(
+        &self,
+        stage: ShaderStageFlags,
+    ) -> Result<Option<PushConstantRange>, SpirvError> {
+        let block = match self
+            .variables
+            .iter()
+            .find(|variable| variable.storage_class == STORAGE_PUSH_CONSTANT)
+            .and_then(|variable| self.pointee(variable.pointer_type))
+        {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        let members = match self.types.get(&block) {
+            Some(SpirvType::Struct { members }) => members,
+            _ => return Ok(None),
+        };
+
+        let start = (0..members.len() as u32)
+            .filter_map(|index| self.member_offset(block, index))
+            .min()
+            .unwrap_or(0);
+        let end = self.type_size(block, None, 0)?;
+
+        Ok(Some(
+            PushConstantRange::default()
+                .stage_flags(stage)
+                .offset(start)
+                .size(end.saturating_sub(start)),
+        ))
+    }
+
+    fn member_offset(&self, struct_id: u32, member: u32) -> Option<u32> {
+        self.member_decorations
+            .get(&(struct_id, member))
+            .and_then(|decorations| decorations.offset)
+    }
+
+    // Size in bytes of a type as laid out in a block.  matrix_stride is the MatrixStride of the
+    // struct member the type belongs to, when it is a matrix.  depth is how many types enclose
+    // this one.
+    fn type_size(
+        &self,
+        type_id: u32,
+        matrix_stride: Option<u32>,
+        depth: u32,
+    ) -> Result<u32, SpirvError> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(SpirvError::TypeTooDeep(type_id));
+        }
+        let too_large = SpirvError::TypeTooLarge(type_id);
+
+        match self.types.get(&type_id) {
+            Some(SpirvType::Int { width, .. }) | Some(SpirvType::Float { width }) => Ok(width / 8),
+            Some(SpirvType::Vector { component, count }) => count
+                .checked_mul(self.type_size(*component, None, depth + 1)?)
+                .ok_or(too_large),
+            Some(SpirvType::Matrix { column, count }) => {
+                let stride = match matrix_stride {
+                    Some(stride) => stride,
+                    None => self.type_size(*column, None, depth + 1)?,
+                };
+                count.checked_mul(stride).ok_or(too_large)
+            }
+            Some(SpirvType::Array { element, length_id }) => {
+                let length = self.constants.get(length_id).copied().unwrap_or(1);
+                let stride = match self
+                    .decorations
+                    .get(&type_id)
+                    .and_then(|decorations| decorations.array_stride)
+                {
+                    Some(stride) => stride,
+                    None => self.type_size(*element, matrix_stride, depth + 1)?,
+                };
+                length.checked_mul(stride).ok_or(too_large)
+            }
+            Some(SpirvType::Struct { members }) => {
+                let mut running_end: u32 = 0;
+                for (index, member) in members.iter().enumerate() {
+                    let decorations = self.member_decorations.get(&(type_id, index as u32));
+                    let member_offset = decorations
+                        .and_then(|decorations| decorations.offset)
+                        .unwrap_or(running_end);
+                    let member_stride =
+                        decorations.and_then(|decorations| decorations.matrix_stride);
+                    let member_end = match member_offset.checked_add(self.type_size(
+                        *member,
+                        member_stride,
+                        depth + 1,
+                    )?) {
+                        Some(member_end) => member_end,
+                        None => return Err(too_large),
+                    };
+                    running_end = std::cmp::max(running_end, member_end);
+                }
+                Ok(running_end)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn vertex_inputs(&self) -> Vec<VertexInput> {
+        let mut inputs: Vec<VertexInput> = self
+            .variables
+            .iter()
+            .filter(|variable| variable.storage_class == STORAGE_INPUT)
+            .filter_map(|variable| {
+                let decorations = self.decorations.get(&variable.id)?;
+                if decorations.built_in {
+                    return None;
+                }
+                let location = decorations.location?;
+                let pointee = self.pointee(variable.pointer_type)?;
+
+                Some(VertexInput {
+                    location,
+                    format: self.vertex_format(pointee),
+                })
+            })
+            .collect();
+
+        inputs.sort_by_key(|input| input.location);
+        inputs
+    }
+
+    fn vertex_format(&self, type_id: u32) -> Format {
+        let (component, count) = match self.types.get(&type_id) {
+            Some(SpirvType::Vector { component, count }) => (*component, *count),
+            _ => (type_id, 1),
+        };
+
+        match (self.types.get(&component), count) {
+            (Some(SpirvType::Float { width: 32 }), 1) => Format::R32_SFLOAT,
+            (Some(SpirvType::Float { width: 32 }), 2) => Format::R32G32_SFLOAT,
+            (Some(SpirvType::Float { width: 32 }), 3) => Format::R32G32B32_SFLOAT,
+            (Some(SpirvType::Float { width: 32 }), 4) => Format::R32G32B32A32_SFLOAT,
+            (Some(SpirvType::Int { width: 32, signed }), count) => match (signed, count) {
+                (true, 1) => Format::R32_SINT,
+                (true, 2) => Format::R32G32_SINT,
+                (true, 3) => Format::R32G32B32_SINT,
+                (true, 4) => Format::R32G32B32A32_SINT,
+                (false, 1) => Format::R32_UINT,
+                (false, 2) => Format::R32G32_UINT,
+                (false, 3) => Format::R32G32B32_UINT,
+                (false, 4) => Format::R32G32B32A32_UINT,
+                _ => Format::UNDEFINED,
+            },
+            _ => Format::UNDEFINED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXECUTION_MODEL_VERTEX: u32 = 0;
+    const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+    const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+    const DIM_2D: u32 = 1;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(text: &str) -> Vec<u32> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len() / 4 * 4 + 4, 0);
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    }
+
+    fn entry_point(execution_model: u32, id: u32) -> Vec<u32> {
+        let mut operands = vec![execution_model, id];
+        operands.extend(string("main"));
+        instruction(OP_ENTRY_POINT, &operands)
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 100, 0];
+        for instruction in instructions {
+            words.extend_from_slice(instruction);
+        }
+        words
+    }
+
+    #[test]
+    fn refuses_a_module_shorter_than_its_header() {
+        assert_eq!(
+            reflect(&[MAGIC, 0x0001_0000]).unwrap_err(),
+            SpirvError::TooShort
+        );
+    }
+
+    #[test]
+    fn refuses_a_bad_magic_number() {
+        let mut words = module(&[entry_point(EXECUTION_MODEL_FRAGMENT, 1)]);
+        words[0] = 0xdead_beef;
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::BadMagic(0xdead_beef)
+        );
+    }
+
+    #[test]
+    fn refuses_an_instruction_that_runs_off_the_end() {
+        let mut words = module(&[entry_point(EXECUTION_MODEL_FRAGMENT, 1)]);
+        words.truncate(words.len() - 1);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::TruncatedInstruction(HEADER_WORDS)
+        );
+    }
+
+    #[test]
+    fn refuses_a_zero_word_count() {
+        let words = module(&[vec![OP_CONSTANT]]);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::TruncatedInstruction(HEADER_WORDS)
+        );
+    }
+
+    #[test]
+    fn refuses_an_entry_point_without_a_name() {
+        let words = module(&[instruction(OP_ENTRY_POINT, &[EXECUTION_MODEL_FRAGMENT, 1])]);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::MissingOperands(HEADER_WORDS)
+        );
+    }
+
+    #[test]
+    fn refuses_a_short_local_size() {
+        let entry = entry_point(EXECUTION_MODEL_GL_COMPUTE, 1);
+        let words = module(&[
+            entry.clone(),
+            instruction(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 8, 8]),
+        ]);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::MissingOperands(HEADER_WORDS + entry.len())
+        );
+    }
+
+    #[test]
+    fn refuses_an_execution_mode_without_a_mode() {
+        let entry = entry_point(EXECUTION_MODEL_GL_COMPUTE, 1);
+        let words = module(&[entry.clone(), instruction(OP_EXECUTION_MODE, &[1])]);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::MissingOperands(HEADER_WORDS + entry.len())
+        );
+    }
+
+    #[test]
+    fn refuses_a_binding_decoration_without_a_value() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_FRAGMENT, 1),
+            instruction(OP_DECORATE, &[5, DECORATION_BINDING]),
+        ]);
+        assert!(matches!(
+            reflect(&words).unwrap_err(),
+            SpirvError::MissingOperands(_)
+        ));
+    }
+
+    #[test]
+    fn refuses_an_unterminated_entry_point_name() {
+        let words = module(&[instruction(
+            OP_ENTRY_POINT,
+            &[EXECUTION_MODEL_FRAGMENT, 1, u32::from_le_bytes(*b"main")],
+        )]);
+        assert_eq!(
+            reflect(&words).unwrap_err(),
+            SpirvError::UnterminatedString(HEADER_WORDS)
+        );
+    }
+
+    #[test]
+    fn refuses_a_module_without_an_entry_point() {
+        let words = module(&[instruction(OP_TYPE_FLOAT, &[2, 32])]);
+        assert_eq!(reflect(&words).unwrap_err(), SpirvError::NoEntryPoint);
+    }
+
+    #[test]
+    fn refuses_an_array_of_itself() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_FRAGMENT, 1),
+            instruction(OP_DECORATE, &[5, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[5, DECORATION_BINDING, 0]),
+            instruction(OP_TYPE_INT, &[6, 32, 0]),
+            instruction(OP_CONSTANT, &[6, 3, 4]),
+            instruction(OP_TYPE_ARRAY, &[2, 2, 3]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_UNIFORM_CONSTANT, 2]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_UNIFORM_CONSTANT]),
+        ]);
+        assert_eq!(reflect(&words).unwrap_err(), SpirvError::TypeTooDeep(2));
+    }
+
+    #[test]
+    fn refuses_a_push_constant_block_that_contains_itself() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_FRAGMENT, 1),
+            instruction(OP_TYPE_STRUCT, &[3, 3]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_PUSH_CONSTANT, 3]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_PUSH_CONSTANT]),
+        ]);
+        assert_eq!(reflect(&words).unwrap_err(), SpirvError::TypeTooDeep(3));
+    }
+
+    #[test]
+    fn refuses_an_array_count_that_overflows() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_FRAGMENT, 1),
+            instruction(OP_DECORATE, &[5, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[5, DECORATION_BINDING, 0]),
+            instruction(OP_TYPE_INT, &[6, 32, 0]),
+            instruction(OP_CONSTANT, &[6, 7, 0x1_0000]),
+            instruction(OP_TYPE_SAMPLER, &[8]),
+            instruction(OP_TYPE_ARRAY, &[9, 8, 7]),
+            instruction(OP_TYPE_ARRAY, &[2, 9, 7]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_UNIFORM_CONSTANT, 2]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_UNIFORM_CONSTANT]),
+        ]);
+        assert_eq!(reflect(&words).unwrap_err(), SpirvError::TypeTooLarge(2));
+    }
+
+    #[test]
+    fn reflects_a_compute_shader() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_GL_COMPUTE, 1),
+            instruction(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 8, 4, 1]),
+            instruction(OP_DECORATE, &[5, DECORATION_DESCRIPTOR_SET, 0]),
+            instruction(OP_DECORATE, &[5, DECORATION_BINDING, 1]),
+            instruction(OP_TYPE_INT, &[3, 32, 0]),
+            instruction(OP_TYPE_IMAGE, &[2, 3, DIM_2D, 0, 0, 0, 2, 0]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_UNIFORM_CONSTANT, 2]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_UNIFORM_CONSTANT]),
+        ]);
+
+        let reflection = reflect(&words).unwrap();
+        assert_eq!(reflection.execution_model, ExecutionModel::GLCompute);
+        assert_eq!(reflection.entry_point, "main");
+        assert_eq!(reflection.local_size, Some([8, 4, 1]));
+        assert_eq!(
+            reflection.descriptor_bindings,
+            vec![DescriptorBinding {
+                set: 0,
+                binding: 1,
+                descriptor_type: DescriptorType::STORAGE_IMAGE,
+                count: 1,
+            }]
+        );
+        assert!(reflection.push_constant_range.is_none());
+    }
+
+    #[test]
+    fn reflects_a_push_constant_block() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_FRAGMENT, 1),
+            instruction(OP_MEMBER_DECORATE, &[3, 0, DECORATION_OFFSET, 0]),
+            instruction(OP_MEMBER_DECORATE, &[3, 1, DECORATION_OFFSET, 4]),
+            instruction(OP_TYPE_INT, &[2, 32, 0]),
+            instruction(OP_TYPE_STRUCT, &[3, 2, 2]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_PUSH_CONSTANT, 3]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_PUSH_CONSTANT]),
+        ]);
+
+        let range = reflect(&words).unwrap().push_constant_range.unwrap();
+        assert_eq!(range.stage_flags, ShaderStageFlags::FRAGMENT);
+        assert_eq!(range.offset, 0);
+        assert_eq!(range.size, 8);
+    }
+
+    #[test]
+    fn reflects_vertex_inputs_without_built_ins() {
+        let words = module(&[
+            entry_point(EXECUTION_MODEL_VERTEX, 1),
+            instruction(OP_DECORATE, &[5, DECORATION_LOCATION, 0]),
+            instruction(OP_DECORATE, &[6, DECORATION_BUILT_IN, 42]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_VECTOR, &[3, 2, 2]),
+            instruction(OP_TYPE_POINTER, &[4, STORAGE_INPUT, 3]),
+            instruction(OP_VARIABLE, &[4, 5, STORAGE_INPUT]),
+            instruction(OP_VARIABLE, &[4, 6, STORAGE_INPUT]),
+        ]);
+
+        assert_eq!(
+            reflect(&words).unwrap().vertex_inputs,
+            vec![VertexInput {
+                location: 0,
+                format: Format::R32G32_SFLOAT,
+            }]
+        );
+    }
+}
@@ -1,169 +1,653 @@
+use ash::vk::ColorSpaceKHR;
+use ash::vk::CompositeAlphaFlagsKHR;
 use ash::vk::Fence;
+use ash::vk::Format;
 use ash::vk::Image;
 use ash::vk::ImageView;
 use ash::vk::PresentInfoKHR;
+use ash::vk::PresentModeKHR;
 use ash::vk::Queue;
 use ash::vk::Semaphore;
+use ash::vk::SurfaceCapabilitiesKHR;
 use ash::vk::SurfaceFormatKHR;
+use ash::vk::SurfaceTransformFlagsKHR;
 use ash::vk::SwapchainKHR;
-use ash::Device;
-use log::debug;
-use std::sync::OnceLock;
+use log::{debug, error, info, warn};
+use std::sync::{Mutex, OnceLock};
 
-use crate::setup::instance::VkContext;
+use crate::{
+    dust_errors::DustError,
+    setup::{debug, instance::VkContext},
+};
 
-// static DEVICE: OnceLock<Device> = OnceLock::new();
-static SWAPCHAIN: OnceLock<SwapchainKHR> = OnceLock::new();
-static SWAPCHAIN_DEVICE: OnceLock<ash::khr::swapchain::Device> = OnceLock::new();
-static SWAPCHAIN_IMAGES: OnceLock<Vec<Image>> = OnceLock::new();
-static SWAPCHAIN_SURFACE_FORMAT: OnceLock<SurfaceFormatKHR> = OnceLock::new();
-static mut SWAPCHAIN_VIEWS: OnceLock<Vec<ImageView>> = OnceLock::new();
+static SWAPCHAIN: OnceLock<Mutex<Option<SwapchainState>>> = OnceLock::new();
+static REQUESTED_MODE: OnceLock<Mutex<RequestedMode>> = OnceLock::new();
+static CONFIG: OnceLock<Mutex<SwapchainConfig>> = OnceLock::new();
 
-pub fn init(
-    // logical_device: Device,
+struct SwapchainState {
+    swapchain_device: ash::khr::swapchain::Device,
     swapchain: SwapchainKHR,
-    swapcahin_device: ash::khr::swapchain::Device,
-    swapchain_images: Vec<Image>,
-    swapchain_views: Vec<ImageView>,
-    swapchain_format: SurfaceFormatKHR,
-) {
-    // match DEVICE.set(logical_device) {
-    //     Ok(_) => {}
-    //     Err(_device) => {
-    //         panic!("Failed to set the DEVICE static in the swapchain module.");
-    //     }
-    // };
-
-    match SWAPCHAIN.set(swapchain) {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("Failed to set the SWAPCHAIN static in the swapchain module.");
+    images: Vec<Image>,
+    views: Vec<ImageView>,
+    settings: SwapchainSettings,
+}
+
+// How the swapchain's pixels are encoded, which decides what the last pass has to write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SurfaceEncoding {
+    // 8-bit sRGB; the hardware applies the sRGB curve on write.
+    Srgb,
+    // 10-bit UNORM shown as sRGB; the last pass has to apply gamma itself.
+    Unorm10,
+    // 8-bit UNORM shown as sRGB; the last pass has to apply gamma itself.
+    Unorm8,
+    // 10-bit HDR10: BT.2020 primaries and the ST 2084 (PQ) curve, applied by the last pass.
+    Hdr10,
+    // 16-bit float scRGB: linear, with values above 1.0 brighter than SDR white.
+    ScRgb,
+}
+
+impl SurfaceEncoding {
+    // The formats this encoding can use, best first, and the colour space they are shown in.
+    fn candidates(&self) -> (&'static [Format], ColorSpaceKHR) {
+        match self {
+            SurfaceEncoding::Srgb => (
+                &[
+                    Format::B8G8R8A8_SRGB,
+                    Format::R8G8B8A8_SRGB,
+                    Format::A8B8G8R8_SRGB_PACK32,
+                ],
+                ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            SurfaceEncoding::Unorm10 => (
+                &[
+                    Format::A2B10G10R10_UNORM_PACK32,
+                    Format::A2R10G10B10_UNORM_PACK32,
+                ],
+                ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            SurfaceEncoding::Unorm8 => (
+                &[Format::B8G8R8A8_UNORM, Format::R8G8B8A8_UNORM],
+                ColorSpaceKHR::SRGB_NONLINEAR,
+            ),
+            SurfaceEncoding::Hdr10 => (
+                &[
+                    Format::A2B10G10R10_UNORM_PACK32,
+                    Format::A2R10G10B10_UNORM_PACK32,
+                ],
+                ColorSpaceKHR::HDR10_ST2084_EXT,
+            ),
+            SurfaceEncoding::ScRgb => (
+                &[Format::R16G16B16A16_SFLOAT],
+                ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            ),
         }
-    };
+    }
+
+    // Whether the final pass must apply the sRGB curve itself, because the format will not.
+    pub fn needs_manual_gamma(&self) -> bool {
+        matches!(self, SurfaceEncoding::Unorm10 | SurfaceEncoding::Unorm8)
+    }
 
-    match SWAPCHAIN_DEVICE.set(swapcahin_device) {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("Failed to set the SWAPCHAIN_DEVICE static in the swapchain module.");
+    // HDR colour spaces only exist with VK_EXT_swapchain_colorspace enabled on the instance.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, SurfaceEncoding::Hdr10 | SurfaceEncoding::ScRgb)
+    }
+}
+
+// *** SwapchainConfig
+//
+// What to ask of the swapchain.  extra_images are requested on top of the surface's minimum,
+// which is often two and stalls the CPU waiting for an image to come back.  encodings are tried
+// in order against the formats the surface offers.  Set with set_config before the Vulkan
+// context is created.
+//
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapchainConfig {
+    pub extra_images: u32,
+    pub encodings: Vec<SurfaceEncoding>,
+}
+
+impl SwapchainConfig {
+    pub fn new() -> SwapchainConfig {
+        SwapchainConfig {
+            extra_images: 1,
+            encodings: vec![
+                SurfaceEncoding::Srgb,
+                SurfaceEncoding::Unorm10,
+                SurfaceEncoding::Unorm8,
+            ],
         }
-    };
+    }
+
+    // HDR where the surface offers it, the usual SDR encodings where it does not.
+    pub fn hdr() -> SwapchainConfig {
+        SwapchainConfig::new().encodings(vec![
+            SurfaceEncoding::Hdr10,
+            SurfaceEncoding::ScRgb,
+            SurfaceEncoding::Srgb,
+            SurfaceEncoding::Unorm10,
+            SurfaceEncoding::Unorm8,
+        ])
+    }
+
+    pub fn extra_images(mut self, extra_images: u32) -> SwapchainConfig {
+        self.extra_images = extra_images;
+        self
+    }
+
+    pub fn encodings(mut self, encodings: Vec<SurfaceEncoding>) -> SwapchainConfig {
+        self.encodings = encodings;
+        self
+    }
+
+    fn wants_hdr(&self) -> bool {
+        self.encodings.iter().any(SurfaceEncoding::is_hdr)
+    }
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> SwapchainConfig {
+        SwapchainConfig::new()
+    }
+}
+
+// What the swapchain was actually created with, for the renderer to work to.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapchainSettings {
+    pub format: SurfaceFormatKHR,
+    pub encoding: SurfaceEncoding,
+    pub image_count: u32,
+    pub composite_alpha: CompositeAlphaFlagsKHR,
+    pub pre_transform: SurfaceTransformFlagsKHR,
+    pub present_mode: PresentModeKHR,
+}
+
+fn config() -> &'static Mutex<SwapchainConfig> {
+    CONFIG.get_or_init(|| Mutex::new(SwapchainConfig::new()))
+}
 
-    match SWAPCHAIN_SURFACE_FORMAT.set(swapchain_format) {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("Failed to set the SWAPCHAIN_SURFACE_FORMAT static in the swapchain module");
+// Only read when the Vulkan context is created; later changes have no effect.
+pub fn set_config(swapchain_config: SwapchainConfig) {
+    *config().lock().unwrap() = swapchain_config;
+}
+
+// Whether the instance should enable VK_EXT_swapchain_colorspace, if the loader has it.
+pub fn wants_extended_color_spaces() -> bool {
+    config().lock().unwrap().wants_hdr()
+}
+
+// *** choose_settings(capabilities, formats, present_modes, extended_color_spaces)
+//
+// Settles the swapchain configuration against what the surface supports: the first of the
+// configured encodings the surface has a format for, the image count clamped to the surface's
+// range, opaque composition where possible and no pre-rotation where possible.  HDR encodings
+// are skipped unless extended_color_spaces says the instance has VK_EXT_swapchain_colorspace.
+//
+pub fn choose_settings(
+    capabilities: &SurfaceCapabilitiesKHR,
+    formats: &[SurfaceFormatKHR],
+    present_modes: &[PresentModeKHR],
+    extended_color_spaces: bool,
+) -> Result<SwapchainSettings, DustError> {
+    let swapchain_config = config().lock().unwrap().clone();
+
+    let (format, encoding) = choose_format(&swapchain_config, formats, extended_color_spaces)?;
+
+    // A maximum of 0 means there is no maximum.
+    let mut image_count = capabilities.min_image_count + swapchain_config.extra_images;
+    if capabilities.max_image_count > 0 {
+        image_count = std::cmp::min(image_count, capabilities.max_image_count);
+    }
+
+    let composite_alpha = match [
+        CompositeAlphaFlagsKHR::OPAQUE,
+        CompositeAlphaFlagsKHR::INHERIT,
+        CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+        CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+    ]
+    .into_iter()
+    .find(|mode| capabilities.supported_composite_alpha.contains(*mode))
+    {
+        Some(mode) => mode,
+        None => {
+            return Err(DustError::NoSuitableDevice(String::from(
+                "the surface supports no composite alpha mode",
+            )));
         }
     };
 
-    match SWAPCHAIN_IMAGES.set(swapchain_images) {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("Failed to assign the swapchain images to the static storage vector");
+    let pre_transform = if capabilities
+        .supported_transforms
+        .contains(SurfaceTransformFlagsKHR::IDENTITY)
+    {
+        SurfaceTransformFlagsKHR::IDENTITY
+    } else {
+        capabilities.current_transform
+    };
+
+    let settings = SwapchainSettings {
+        format,
+        encoding,
+        image_count,
+        composite_alpha,
+        pre_transform,
+        present_mode: choose_present_mode(present_mode(), present_modes),
+    };
+    info!(
+        "Swapchain: {:?} in {:?} ({:?}), {} images, {:?}, {:?}",
+        settings.format.format,
+        settings.format.color_space,
+        settings.encoding,
+        settings.image_count,
+        settings.composite_alpha,
+        settings.pre_transform
+    );
+
+    Ok(settings)
+}
+
+fn choose_format(
+    swapchain_config: &SwapchainConfig,
+    formats: &[SurfaceFormatKHR],
+    extended_color_spaces: bool,
+) -> Result<(SurfaceFormatKHR, SurfaceEncoding), DustError> {
+    for encoding in &swapchain_config.encodings {
+        if encoding.is_hdr() && !extended_color_spaces {
+            continue;
+        }
+
+        let (candidates, color_space) = encoding.candidates();
+        for candidate in candidates {
+            if let Some(format) = formats
+                .iter()
+                .find(|format| format.format == *candidate && format.color_space == color_space)
+            {
+                return Ok((*format, *encoding));
+            }
         }
     }
 
-    match unsafe { SWAPCHAIN_VIEWS.set(swapchain_views) } {
-        Ok(_) => {}
-        Err(_) => {
-            panic!("Failed to assign the swapchain views to the static storage vector",);
+    // None of the configured encodings: take the surface's own first choice, and treat it as
+    // needing gamma unless it is one of the sRGB formats.
+    match formats.first() {
+        Some(format) => {
+            let encoding = if SurfaceEncoding::Srgb
+                .candidates()
+                .0
+                .contains(&format.format)
+            {
+                SurfaceEncoding::Srgb
+            } else {
+                SurfaceEncoding::Unorm8
+            };
+            warn!(
+                "The surface offers none of {:?}; using {:?} in {:?} as {:?}",
+                swapchain_config.encodings, format.format, format.color_space, encoding
+            );
+            Ok((*format, encoding))
         }
+        None => Err(DustError::NoSuitableDevice(String::from(
+            "the surface reports no formats",
+        ))),
     }
 }
 
-pub fn get_swapchain_format() -> &'static SurfaceFormatKHR {
-    match SWAPCHAIN_SURFACE_FORMAT.get() {
-        Some(fmt) => fmt,
+struct RequestedMode {
+    mode: PresentMode,
+    // Set by set_present_mode, cleared once the swapchain has been rebuilt for it.
+    pending: bool,
+}
+
+// How finished frames are handed to the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PresentMode {
+    // Each frame waits for a vertical blank: no tearing, and no more frames than the refresh rate.
+    Vsync,
+    // Vsync while the frame rate keeps up; a late frame is shown at once, tearing, rather than
+    // held for the next vertical blank.
+    AdaptiveVsync,
+    // No tearing and no waiting: the newest finished frame is shown at each vertical blank, and
+    // the rest are thrown away.
+    Mailbox,
+    // Frames are shown the moment they are finished.  Lowest latency, but tears.
+    Immediate,
+}
+
+impl PresentMode {
+    // Accepts what name() gives, ignoring case.
+    pub fn parse(value: &str) -> Option<PresentMode> {
+        match value.trim().to_lowercase().as_str() {
+            "vsync" => Some(PresentMode::Vsync),
+            "adaptive-vsync" => Some(PresentMode::AdaptiveVsync),
+            "mailbox" => Some(PresentMode::Mailbox),
+            "immediate" => Some(PresentMode::Immediate),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresentMode::Vsync => "vsync",
+            PresentMode::AdaptiveVsync => "adaptive-vsync",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+        }
+    }
+
+    // The Vulkan modes that give this behaviour, best first.  Every list ends in FIFO, the one
+    // mode every device has to support.
+    fn preferences(&self) -> &'static [PresentModeKHR] {
+        match self {
+            PresentMode::Vsync => &[PresentModeKHR::FIFO],
+            PresentMode::AdaptiveVsync => &[PresentModeKHR::FIFO_RELAXED, PresentModeKHR::FIFO],
+            PresentMode::Mailbox => &[PresentModeKHR::MAILBOX, PresentModeKHR::FIFO],
+            PresentMode::Immediate => &[
+                PresentModeKHR::IMMEDIATE,
+                PresentModeKHR::MAILBOX,
+                PresentModeKHR::FIFO,
+            ],
+        }
+    }
+}
+
+// *** choose_present_mode(requested: PresentMode, supported: &[PresentModeKHR]) -> PresentModeKHR
+//
+// The Vulkan present mode to create a swapchain with: the best of requested's preferences the
+// surface supports.  Falls back to FIFO, with a warning, for surfaces that somehow list none.
+//
+pub fn choose_present_mode(requested: PresentMode, supported: &[PresentModeKHR]) -> PresentModeKHR {
+    let preferences = requested.preferences();
+    match preferences.iter().find(|mode| supported.contains(mode)) {
+        Some(mode) => {
+            if *mode != preferences[0] {
+                info!(
+                    "{:?} is not supported for {:?}; falling back to {:?}",
+                    preferences[0], requested, mode
+                );
+            }
+            *mode
+        }
         None => {
-            panic!("It appears you have attempted to utilize the Swapchain module without initializing Vulkan.");
+            warn!(
+                "The surface reports none of {:?} (it has {:?}); using FIFO regardless.",
+                preferences, supported
+            );
+            PresentModeKHR::FIFO
         }
     }
 }
 
-pub fn next_swapchain_image(
-    signal_acquired: Semaphore,
-    block_till_acquired: Fence,
-) -> (u32, &'static ImageView, bool) {
-    debug!(
-        "Out of curiosity, how big are semaphore and fence? {} & {}",
-        std::mem::size_of::<Semaphore>(),
-        std::mem::size_of::<Fence>()
-    );
+fn requested_mode() -> &'static Mutex<RequestedMode> {
+    REQUESTED_MODE.get_or_init(|| {
+        Mutex::new(RequestedMode {
+            mode: PresentMode::Mailbox,
+            pending: false,
+        })
+    })
+}
 
-    debug!(
-        "Also out of curiosity - how big are swapchain device & swapchain? {}, {}",
-        std::mem::size_of::<SwapchainKHR>(),
-        std::mem::size_of::<ash::khr::swapchain::Device>()
-    );
+// *** set_present_mode(mode: PresentMode)
+//
+// Asks for a different present mode.  Before the Vulkan context exists this just decides what the
+// swapchain is first created with; afterwards the swapchain is rebuilt at the start of the next
+// frame.  Defaults to Mailbox.
+//
+pub fn set_present_mode(mode: PresentMode) {
+    let mut requested = requested_mode().lock().unwrap();
+    requested.pending = requested.mode != mode || requested.pending;
+    requested.mode = mode;
+}
 
-    debug!(
-        "One more question: how big is this Image? {}",
-        std::mem::size_of::<Image>()
-    );
+// The present mode last asked for, which may not yet be in effect.
+pub fn present_mode() -> PresentMode {
+    requested_mode().lock().unwrap().mode
+}
 
-    debug!(
-        "Last question: how big is just plain Device? {}",
-        std::mem::size_of::<Device>()
-    );
+// The Vulkan present mode the swapchain was actually created with.
+pub fn active_present_mode() -> Result<PresentModeKHR, DustError> {
+    with_swapchain(|state| state.settings.present_mode)
+}
 
-    let (image_index, suboptimal) = match unsafe {
-        SWAPCHAIN_DEVICE.get().unwrap().acquire_next_image(
-            *SWAPCHAIN.get().unwrap(),
-            100000,
-            signal_acquired,
-            block_till_acquired,
-        )
-    } {
-        Ok(index) => index,
-        Err(msg) => {
-            panic!("Failed to acquire next image index: {:?}", msg);
-        }
+// The swapchain's format, encoding, image count and so on, as created.
+pub fn settings() -> Result<SwapchainSettings, DustError> {
+    with_swapchain(|state| state.settings)
+}
+
+pub fn init(
+    swapchain: SwapchainKHR,
+    swapchain_device: ash::khr::swapchain::Device,
+    swapchain_images: Vec<Image>,
+    swapchain_views: Vec<ImageView>,
+    settings: SwapchainSettings,
+) {
+    name_images(&swapchain_images);
+    // Whatever was asked for before now is what the swapchain was just made with.
+    requested_mode().lock().unwrap().pending = false;
+
+    let state = SwapchainState {
+        swapchain_device,
+        swapchain,
+        images: swapchain_images,
+        views: swapchain_views,
+        settings,
     };
 
-    let image = match unsafe { SWAPCHAIN_VIEWS.get().unwrap().get(image_index as usize) } {
-        Some(image) => image,
-        None => {
-            panic!(
-                "Failed to retrieve the actual image from the static - no image at index {} was found.", image_index
-            );
+    // A rebuilt device brings a new swapchain with it; the old one went in destroy().
+    *SWAPCHAIN.get_or_init(|| Mutex::new(None)).lock().unwrap() = Some(state);
+
+    info!("Presenting with {:?}", settings.present_mode);
+}
+
+fn with_swapchain<T>(action: impl FnOnce(&mut SwapchainState) -> T) -> Result<T, DustError> {
+    match SWAPCHAIN
+        .get_or_init(|| Mutex::new(None))
+        .lock()
+        .unwrap()
+        .as_mut()
+    {
+        Some(state) => Ok(action(state)),
+        None => Err(DustError::NotInitialized("the swapchain")),
+    }
+}
+
+fn name_images(images: &[Image]) {
+    for (index, image) in images.iter().enumerate() {
+        debug::name_object(*image, &format!("swapchain_image_{}", index));
+    }
+}
+
+pub fn get_swapchain_format() -> Result<SurfaceFormatKHR, DustError> {
+    with_swapchain(|state| state.settings.format)
+}
+
+// *** apply_present_mode(ctxt: &VkContext) -> Result<(), DustError>
+//
+// Frame boundary hook: if a new present mode was asked for, rebuilds the swapchain with it.  The
+// GPU is drained first, since the old images may still be in flight.  Nothing is rebuilt when the
+// request comes down to the Vulkan mode already in use.
+//
+pub fn apply_present_mode(ctxt: &VkContext) -> Result<(), DustError> {
+    let requested = {
+        let mut requested = requested_mode().lock().unwrap();
+        if !requested.pending {
+            return Ok(());
         }
+        requested.pending = false;
+        requested.mode
     };
 
-    (image_index, image, suboptimal)
+    let chosen = choose_present_mode(requested, &ctxt.surface_present_modes()?);
+
+    with_swapchain(|state| {
+        if chosen == state.settings.present_mode {
+            debug!("{:?} is already presenting with {:?}", requested, chosen);
+            return Ok(());
+        }
+
+        if let Err(msg) = unsafe { ctxt.logical_device.device_wait_idle() } {
+            return Err(DustError::vulkan(
+                "waiting for the device before rebuilding the swapchain",
+                msg,
+            ));
+        }
+
+        let settings = SwapchainSettings {
+            present_mode: chosen,
+            ..state.settings
+        };
+        let (swapchain, images, views) =
+            ctxt.build_swapchain(&state.swapchain_device, &settings, state.swapchain)?;
+        name_images(&images);
+
+        unsafe {
+            for view in state.views.drain(..) {
+                ctxt.logical_device.destroy_image_view(view, None);
+            }
+            state
+                .swapchain_device
+                .destroy_swapchain(state.swapchain, None);
+        }
+
+        state.swapchain = swapchain;
+        state.images = images;
+        state.views = views;
+        state.settings = settings;
+
+        info!("Swapchain rebuilt; presenting with {:?}", chosen);
+        Ok(())
+    })?
+}
+
+pub fn next_swapchain_image(
+    signal_acquired: Semaphore,
+    block_till_acquired: Fence,
+) -> Result<(u32, ImageView, bool), DustError> {
+    with_swapchain(|state| {
+        let (image_index, suboptimal) = match unsafe {
+            state.swapchain_device.acquire_next_image(
+                state.swapchain,
+                u64::MAX,
+                signal_acquired,
+                block_till_acquired,
+            )
+        } {
+            Ok(index) => index,
+            Err(msg) => {
+                return Err(DustError::vulkan("acquiring a swapchain image", msg));
+            }
+        };
+
+        // An index the swapchain has no image for means it is not the one the image came from.
+        match state.views.get(image_index as usize) {
+            Some(image) => Ok((image_index, *image, suboptimal)),
+            None => Err(DustError::vulkan(
+                "acquiring a swapchain image",
+                ash::vk::Result::ERROR_OUT_OF_DATE_KHR,
+            )),
+        }
+    })?
 }
 
 pub fn present_swapchain_image(
     image_index: u32,
     present_on: &Queue,
     wait_semaphores: &[Semaphore],
-) -> Result<bool, ash::vk::Result> {
-    let swapchain = [*SWAPCHAIN.get().unwrap(); 1];
-    let images = [image_index; 1];
-    let present_info = PresentInfoKHR::default()
-        .swapchains(&swapchain)
-        .image_indices(&images)
-        .wait_semaphores(wait_semaphores);
+) -> Result<bool, DustError> {
+    with_swapchain(|state| {
+        let swapchain = [state.swapchain; 1];
+        let images = [image_index; 1];
+        let present_info = PresentInfoKHR::default()
+            .swapchains(&swapchain)
+            .image_indices(&images)
+            .wait_semaphores(wait_semaphores);
 
-    unsafe {
-        SWAPCHAIN_DEVICE
-            .get()
-            .unwrap()
-            .queue_present(*present_on, &present_info)
-    }
+        match unsafe {
+            state
+                .swapchain_device
+                .queue_present(*present_on, &present_info)
+        } {
+            Ok(suboptimal) => Ok(suboptimal),
+            Err(msg) => Err(DustError::vulkan("presenting a swapchain image", msg)),
+        }
+    })?
 }
 
 pub fn destroy(ctxt: &VkContext) {
     debug!("Swapchain objects being destroyed.");
-    unsafe {
-        SWAPCHAIN_DEVICE
-            .get()
-            .unwrap()
-            .destroy_swapchain(*SWAPCHAIN.get().unwrap(), None);
-
-        let size = SWAPCHAIN_VIEWS.get().unwrap().len();
-        SWAPCHAIN_VIEWS
-            .get_mut()
-            .unwrap()
-            .drain(0..size)
-            .for_each(|view| ctxt.logical_device.destroy_image_view(view, None));
+    match SWAPCHAIN
+        .get()
+        .and_then(|state| state.lock().unwrap().take())
+    {
+        // The views are of the swapchain's images, so they go first.
+        Some(state) => unsafe {
+            state
+                .views
+                .into_iter()
+                .for_each(|view| ctxt.logical_device.destroy_image_view(view, None));
+
+            state
+                .swapchain_device
+                .destroy_swapchain(state.swapchain, None);
+        },
+        None => {
+            error!("The swapchain was removed prior to the destroy action being invoked.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODES: [PresentMode; 4] = [
+        PresentMode::Vsync,
+        PresentMode::AdaptiveVsync,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    #[test]
+    fn parse_takes_back_what_name_gives() {
+        for mode in MODES {
+            assert_eq!(PresentMode::parse(mode.name()), Some(mode));
+        }
+        assert_eq!(PresentMode::parse(" VSync "), Some(PresentMode::Vsync));
+        assert_eq!(PresentMode::parse("fifo"), None);
+    }
+
+    #[test]
+    fn chooses_the_best_supported_preference() {
+        let everything = [
+            PresentModeKHR::IMMEDIATE,
+            PresentModeKHR::MAILBOX,
+            PresentModeKHR::FIFO,
+            PresentModeKHR::FIFO_RELAXED,
+        ];
+        assert_eq!(
+            choose_present_mode(PresentMode::AdaptiveVsync, &everything),
+            PresentModeKHR::FIFO_RELAXED
+        );
+        assert_eq!(
+            choose_present_mode(PresentMode::Immediate, &everything),
+            PresentModeKHR::IMMEDIATE
+        );
+
+        let no_immediate = [PresentModeKHR::FIFO, PresentModeKHR::MAILBOX];
+        assert_eq!(
+            choose_present_mode(PresentMode::Immediate, &no_immediate),
+            PresentModeKHR::MAILBOX
+        );
+    }
+
+    #[test]
+    fn every_mode_falls_back_to_fifo() {
+        for mode in MODES {
+            assert_eq!(
+                choose_present_mode(mode, &[PresentModeKHR::FIFO]),
+                PresentModeKHR::FIFO
+            );
+            assert_eq!(choose_present_mode(mode, &[]), PresentModeKHR::FIFO);
+        }
     }
 }
@@ -0,0 +1,448 @@
+use std::sync::{Arc, Mutex, OnceLock};
+
+use ash::{
+    vk::{
+        AccessFlags, AttachmentDescription, AttachmentDescriptionFlags, AttachmentLoadOp,
+        AttachmentReference, AttachmentStoreOp, ClearColorValue, ClearDepthStencilValue,
+        ClearValue, Extent2D, Extent3D, Format, ImageCreateFlags, ImageCreateInfo, ImageLayout,
+        ImageTiling, ImageType, ImageUsageFlags, ImageView, PipelineBindPoint, PipelineStageFlags,
+        RenderPass, RenderPassCreateFlags, RenderPassCreateInfo, SampleCountFlags, SharingMode,
+        SubpassDependency, SubpassDescription, SubpassDescriptionFlags, SUBPASS_EXTERNAL,
+    },
+    Device,
+};
+use log::{debug, error};
+
+use crate::{dust_errors::DustError, setup::instance::VkContext};
+
+use super::{image::DustImage, swapchain, transfer};
+
+// *** AttachmentSetup
+//
+// What a render pass draws into, as far as pipeline compatibility goes: the colour format, the
+// depth format if there is a depth buffer, and the sample count of both.  Pipelines are built
+// against a setup, and can only be used in render passes made from an equal one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentSetup {
+    pub color_format: Format,
+    pub depth_format: Option<Format>,
+    pub samples: SampleCountFlags,
+}
+
+impl AttachmentSetup {
+    pub fn new(color_format: Format) -> AttachmentSetup {
+        AttachmentSetup {
+            color_format,
+            depth_format: None,
+            samples: SampleCountFlags::TYPE_1,
+        }
+    }
+
+    pub fn depth(mut self, depth_format: Format) -> AttachmentSetup {
+        self.depth_format = Some(depth_format);
+        self
+    }
+
+    pub fn samples(mut self, samples: SampleCountFlags) -> AttachmentSetup {
+        self.samples = samples;
+        self
+    }
+
+    pub fn is_multisampled(&self) -> bool {
+        self.samples != SampleCountFlags::TYPE_1
+    }
+
+    // A single colour attachment, so a render pass can draw straight into the output image with
+    // nothing else attached.
+    pub fn is_plain(&self) -> bool {
+        self.depth_format.is_none() && !self.is_multisampled()
+    }
+}
+
+// *** make_render_pass(device, setup, load_op, final_layout) -> Result<RenderPass, DustError>
+//
+// A single subpass render pass for setup.  Attachments come in a fixed order: the colour
+// attachment, then the depth attachment if there is one, then - when multisampled - the single
+// sample attachment the colour is resolved into.  Whichever attachment holds the finished image
+// is stored and left in final_layout; the multisampled colour and the depth are thrown away.
+//
+// load_op applies to the colour attachment.  Every attachment starts from UNDEFINED, so LOAD is
+// not an option.  A final_layout of SHADER_READ_ONLY_OPTIMAL adds the dependency that makes the
+// result visible to fragment shaders sampling it afterwards.
+//
+pub fn make_render_pass(
+    device: &Device,
+    setup: &AttachmentSetup,
+    load_op: AttachmentLoadOp,
+    final_layout: ImageLayout,
+) -> Result<RenderPass, DustError> {
+    let multisampled = setup.is_multisampled();
+
+    let mut attachments = vec![AttachmentDescription::default()
+        .flags(AttachmentDescriptionFlags::empty())
+        .format(setup.color_format)
+        .samples(setup.samples)
+        .load_op(load_op)
+        .store_op(if multisampled {
+            AttachmentStoreOp::DONT_CARE
+        } else {
+            AttachmentStoreOp::STORE
+        })
+        .stencil_load_op(AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+        .initial_layout(ImageLayout::UNDEFINED)
+        .final_layout(if multisampled {
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+        } else {
+            final_layout
+        })];
+    let color_references = [AttachmentReference::default()
+        .attachment(0)
+        .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+
+    let depth_reference = AttachmentReference::default()
+        .attachment(attachments.len() as u32)
+        .layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    if let Some(depth_format) = setup.depth_format {
+        attachments.push(
+            AttachmentDescription::default()
+                .flags(AttachmentDescriptionFlags::empty())
+                .format(depth_format)
+                .samples(setup.samples)
+                .load_op(AttachmentLoadOp::CLEAR)
+                .store_op(AttachmentStoreOp::DONT_CARE)
+                .stencil_load_op(AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+                .initial_layout(ImageLayout::UNDEFINED)
+                .final_layout(ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+        );
+    }
+
+    let resolve_references = [AttachmentReference::default()
+        .attachment(attachments.len() as u32)
+        .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+    if multisampled {
+        attachments.push(
+            AttachmentDescription::default()
+                .flags(AttachmentDescriptionFlags::empty())
+                .format(setup.color_format)
+                .samples(SampleCountFlags::TYPE_1)
+                .load_op(AttachmentLoadOp::DONT_CARE)
+                .store_op(AttachmentStoreOp::STORE)
+                .stencil_load_op(AttachmentLoadOp::DONT_CARE)
+                .stencil_store_op(AttachmentStoreOp::DONT_CARE)
+                .initial_layout(ImageLayout::UNDEFINED)
+                .final_layout(final_layout),
+        );
+    }
+
+    let mut subpass = SubpassDescription::default()
+        .flags(SubpassDescriptionFlags::empty())
+        .pipeline_bind_point(PipelineBindPoint::GRAPHICS)
+        .color_attachments(&color_references);
+    if setup.depth_format.is_some() {
+        subpass = subpass.depth_stencil_attachment(&depth_reference);
+    }
+    if multisampled {
+        subpass = subpass.resolve_attachments(&resolve_references);
+    }
+    let subpasses = [subpass];
+
+    // Wait for whatever last wrote or sampled these attachments - the previous frame's depth
+    // test, or a post-processing pass reading a target this pass is about to overwrite.
+    let mut dependencies = vec![SubpassDependency::default()
+        .src_subpass(SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | PipelineStageFlags::LATE_FRAGMENT_TESTS
+                | PipelineStageFlags::FRAGMENT_SHADER,
+        )
+        .dst_stage_mask(
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+        )
+        .src_access_mask(AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_access_mask(
+            AccessFlags::COLOR_ATTACHMENT_WRITE
+                | AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        )];
+    if final_layout == ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+        dependencies.push(
+            SubpassDependency::default()
+                .src_subpass(0)
+                .dst_subpass(SUBPASS_EXTERNAL)
+                .src_stage_mask(PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(PipelineStageFlags::FRAGMENT_SHADER)
+                .src_access_mask(AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(AccessFlags::SHADER_READ),
+        );
+    }
+
+    match unsafe {
+        device.create_render_pass(
+            &RenderPassCreateInfo::default()
+                .flags(RenderPassCreateFlags::empty())
+                .attachments(&attachments)
+                .subpasses(&subpasses)
+                .dependencies(&dependencies),
+            None,
+        )
+    } {
+        Ok(render_pass) => Ok(render_pass),
+        Err(msg) => Err(DustError::vulkan("creating a render pass", msg)),
+    }
+}
+
+// *** RenderTargets
+//
+// The attachments a render pass needs besides the image it finally draws into: the depth buffer,
+// and the multisampled colour image that gets resolved into the output.  Neither outlives the
+// render pass, so one set serves every swapchain image of the same extent.
+pub struct RenderTargets {
+    pub setup: AttachmentSetup,
+    pub extent: Extent2D,
+    depth: Option<DustImage>,
+    multisampled_color: Option<DustImage>,
+}
+
+// *** new(ctxt: &VkContext, extent: Extent2D, setup: AttachmentSetup) -> Result<RenderTargets, DustError>
+//
+// Creates whatever setup calls for at extent.  A plain setup needs nothing, and gets nothing.
+//
+pub fn new(
+    ctxt: &VkContext,
+    extent: Extent2D,
+    setup: AttachmentSetup,
+) -> Result<RenderTargets, DustError> {
+    let depth = setup
+        .depth_format
+        .map(|depth_format| {
+            transfer::make_image(
+                ctxt,
+                &attachment_info(
+                    depth_format,
+                    extent,
+                    setup.samples,
+                    ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                ),
+            )
+        })
+        .transpose()?;
+
+    let multisampled_color = setup
+        .is_multisampled()
+        .then(|| {
+            transfer::make_image(
+                ctxt,
+                &attachment_info(
+                    setup.color_format,
+                    extent,
+                    setup.samples,
+                    ImageUsageFlags::COLOR_ATTACHMENT,
+                ),
+            )
+        })
+        .transpose()?;
+
+    if let Some(depth) = &depth {
+        depth.set_name("depth_target");
+    }
+    if let Some(color) = &multisampled_color {
+        color.set_name("msaa_color_target");
+    }
+
+    Ok(RenderTargets {
+        setup,
+        extent,
+        depth,
+        multisampled_color,
+    })
+}
+
+impl RenderTargets {
+    // The framebuffer attachments, in the order make_render_pass declares them, for a pass that
+    // ends up in output.
+    pub fn attachments(&self, output: ImageView) -> Vec<ImageView> {
+        let mut views = Vec::new();
+
+        match &self.multisampled_color {
+            Some(color) => views.push(color.view),
+            None => views.push(output),
+        }
+        if let Some(depth) = &self.depth {
+            views.push(depth.view);
+        }
+        if self.multisampled_color.is_some() {
+            views.push(output);
+        }
+
+        views
+    }
+
+    // One clear value per attachment: color for the colour, the far plane for depth.
+    pub fn clear_values(&self, color: ClearColorValue) -> Vec<ClearValue> {
+        let mut values = vec![ClearValue { color }];
+
+        if self.depth.is_some() {
+            values.push(ClearValue {
+                depth_stencil: ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            });
+        }
+        if self.multisampled_color.is_some() {
+            values.push(ClearValue { color });
+        }
+
+        values
+    }
+}
+
+fn attachment_info<'a>(
+    format: Format,
+    extent: Extent2D,
+    samples: SampleCountFlags,
+    usage: ImageUsageFlags,
+) -> ImageCreateInfo<'a> {
+    ImageCreateInfo::default()
+        .initial_layout(ImageLayout::UNDEFINED)
+        .sharing_mode(SharingMode::EXCLUSIVE)
+        .image_type(ImageType::TYPE_2D)
+        .array_layers(1)
+        .format(format)
+        .extent(
+            Extent3D::default()
+                .width(extent.width)
+                .height(extent.height)
+                .depth(1),
+        )
+        .mip_levels(1)
+        .samples(samples)
+        .flags(ImageCreateFlags::empty())
+        // Nothing reads these back after the render pass, so the driver may keep them on chip.
+        .usage(usage | ImageUsageFlags::TRANSIENT_ATTACHMENT)
+        .tiling(ImageTiling::OPTIMAL)
+}
+
+static SCENE: OnceLock<Mutex<SceneTargets>> = OnceLock::new();
+
+// The attachments the scene is composited with.  By default that is the bare swapchain image, as
+// the 2D compositor needs nothing more; configure_scene() asks for depth and MSAA.
+struct SceneTargets {
+    logical_device: Arc<Device>,
+    depth_format: Option<Format>,
+    samples: SampleCountFlags,
+    current: Option<RenderTargets>,
+}
+
+// Called again with the new device when the device is rebuilt.  The configured depth and sample
+// count are kept; the attachments are made again at the next frame.
+pub fn init(logical_device: Arc<Device>) {
+    if let Some(scene) = SCENE.get() {
+        scene.lock().unwrap().logical_device = logical_device;
+        return;
+    }
+
+    let scene = SceneTargets {
+        logical_device,
+        depth_format: None,
+        samples: SampleCountFlags::TYPE_1,
+        current: None,
+    };
+
+    if SCENE.set(Mutex::new(scene)).is_err() {
+        panic!("Unable to set the scene targets static.");
+    }
+}
+
+pub fn destroy() {
+    match SCENE.get() {
+        Some(scene) => {
+            scene.lock().unwrap().current = None;
+        }
+        None => {
+            error!("The scene targets were never initialized; nothing to destroy.");
+        }
+    }
+}
+
+fn with_scene<R>(action: impl FnOnce(&mut SceneTargets) -> R) -> Result<R, DustError> {
+    match SCENE.get() {
+        Some(scene) => Ok(action(&mut scene.lock().unwrap())),
+        None => Err(DustError::NotInitialized("the scene targets")),
+    }
+}
+
+// *** configure_scene(ctxt: &VkContext, depth: bool, samples: SampleCountFlags) -> Result<SampleCountFlags, DustError>
+//
+// Chooses the attachments the scene is drawn with from the next frame on: a depth buffer in the
+// best format the device offers, and up to samples MSAA samples.  The sample count is clamped to
+// what the device supports, and the one actually used is returned.
+//
+pub fn configure_scene(
+    ctxt: &VkContext,
+    depth: bool,
+    samples: SampleCountFlags,
+) -> Result<SampleCountFlags, DustError> {
+    let depth_format = match depth {
+        true => Some(ctxt.depth_format()?),
+        false => None,
+    };
+    let samples = ctxt.max_sample_count(samples);
+
+    debug!(
+        "Scene attachments: depth {:?}, {:?} samples",
+        depth_format, samples
+    );
+
+    with_scene(|scene| {
+        scene.depth_format = depth_format;
+        scene.samples = samples;
+    })?;
+
+    Ok(samples)
+}
+
+// *** scene(ctxt, extent, output, clear_color) -> Result<(AttachmentSetup, Vec<ImageView>, Vec<ClearValue>), DustError>
+//
+// Everything needed to begin the scene render pass this frame, drawing into output at extent:
+// its setup, framebuffer attachments and clear values.  The depth and MSAA images are
+// (re)created when the extent, swapchain format or configuration changes.
+//
+pub fn scene(
+    ctxt: &VkContext,
+    extent: Extent2D,
+    output: ImageView,
+    clear_color: ClearColorValue,
+) -> Result<(AttachmentSetup, Vec<ImageView>, Vec<ClearValue>), DustError> {
+    with_scene(|scene| {
+        let mut setup =
+            AttachmentSetup::new(swapchain::get_swapchain_format()?.format).samples(scene.samples);
+        if let Some(depth_format) = scene.depth_format {
+            setup = setup.depth(depth_format);
+        }
+
+        let stale = match &scene.current {
+            Some(current) => current.setup != setup || current.extent != extent,
+            None => true,
+        };
+
+        if stale {
+            if scene.current.is_some() {
+                if let Err(msg) = unsafe { scene.logical_device.device_wait_idle() } {
+                    return Err(DustError::vulkan("waiting for the device to go idle", msg));
+                }
+            }
+            scene.current = Some(new(ctxt, extent, setup)?);
+        }
+
+        let current = scene.current.as_ref().unwrap();
+        Ok((
+            setup,
+            current.attachments(output),
+            current.clear_values(clear_color),
+        ))
+    })?
+}
@@ -2,7 +2,7 @@ use crate::dust_errors::DustError;
 use ash::vk::{
     AccessFlags, AccessFlags2, Buffer, BufferCopy, BufferCreateFlags, BufferCreateInfo,
     BufferImageCopy, BufferMemoryBarrier, BufferUsageFlags, CommandBuffer, CommandBufferBeginInfo,
-    CommandBufferUsageFlags, DependencyFlags, DependencyInfo, DeviceMemory, Fence,
+    CommandBufferUsageFlags, DependencyFlags, DependencyInfo, DeviceMemory, Extent2D, Fence,
     FenceCreateFlags, FenceCreateInfo, Image, ImageAspectFlags, ImageCreateInfo, ImageLayout,
     ImageMemoryBarrier, ImageMemoryBarrier2, ImageSubresourceRange, MemoryAllocateInfo,
     MemoryMapFlags, MemoryPropertyFlags, PhysicalDeviceMemoryProperties, PipelineStageFlags,
@@ -10,9 +10,64 @@ use ash::vk::{
 };
 use log::debug;
 
-use crate::setup::instance::VkContext;
+use crate::setup::{debug, instance::VkContext};
 
-use super::{image::DustImage, pools, util};
+use super::{
+    buffer::{self, DustBuffer},
+    image::DustImage,
+    pools, util,
+};
+
+// *** TransferObjects
+//
+// Everything a copy creates for its own use on the way: the staging buffers and their memory,
+// the transfer command buffers, and the semaphores and fences ordering the submissions.  Each is
+// pushed here as soon as it exists and release() destroys the lot however the copy ends, so an
+// early return cannot leak any of it.
+#[derive(Default)]
+struct TransferObjects {
+    staging: Vec<(Buffer, DeviceMemory)>,
+    command_buffers: Vec<CommandBuffer>,
+    semaphores: Vec<Semaphore>,
+    fences: Vec<Fence>,
+    // Set once anything is submitted, cleared once every submission is known to have finished.
+    in_flight: bool,
+}
+
+impl TransferObjects {
+    fn release(self, ctxt: &VkContext) -> Result<(), DustError> {
+        let device = &ctxt.logical_device;
+        let mut result = Ok(());
+
+        // A copy abandoned part way may have left work on the queue that still uses these.
+        if self.in_flight {
+            if let Err(msg) = unsafe { device.device_wait_idle() } {
+                result = Err(DustError::vulkan("waiting for an abandoned transfer", msg));
+            }
+        }
+
+        if !self.command_buffers.is_empty() {
+            if let Err(msg) = pools::release_transfer_buffers(ctxt, &self.command_buffers) {
+                result = Err(msg);
+            }
+        }
+
+        unsafe {
+            for (buffer, memory) in self.staging {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+            for semaphore in self.semaphores {
+                device.destroy_semaphore(semaphore, None);
+            }
+            for fence in self.fences {
+                device.destroy_fence(fence, None);
+            }
+        }
+
+        result
+    }
+}
 
 pub fn copy_to_image<T>(
     data: &[T],
@@ -20,21 +75,45 @@ pub fn copy_to_image<T>(
     image_props: &ImageCreateInfo,
     target_layout: ImageLayout,
     target_queue_family: u32,
-) -> (DustImage, Semaphore)
+) -> Result<(DustImage, Semaphore), DustError>
 where
     T: Sized + Clone + Copy,
 {
-    let (transfer_buffer, memory_handle) = make_buffer_and_copy(data, ctxt);
+    let image = make_image(ctxt, image_props)?;
 
-    let image_target = match unsafe { ctxt.logical_device.create_image(image_props, None) } {
-        Ok(image) => image,
-        Err(msg) => {
-            panic!("Failed to create image: {:?}", msg);
+    let mut objects = TransferObjects::default();
+    let copied = record_image_copy(data, ctxt, &image, image_props, target_layout, &mut objects);
+    let released = objects.release(ctxt);
+
+    // On failure the image is dropped here, after release() has made sure nothing still uses it.
+    match (copied, released) {
+        (Ok(ready), Ok(())) => {
+            image.set_layout(target_layout);
+            Ok((image, ready))
         }
-    };
+        (Ok(ready), Err(msg)) => {
+            unsafe { ctxt.logical_device.destroy_semaphore(ready, None) };
+            Err(msg)
+        }
+        (Err(msg), _) => Err(msg),
+    }
+}
 
-    let device_memory =
-        back_image_with_memory(ctxt, &image_target, &MemoryPropertyFlags::DEVICE_LOCAL);
+// Fills the freshly made image from data and hands it over to the graphics queue family, in
+// target_layout.  Returns the semaphore signalled once the image is ready for use.
+fn record_image_copy<T>(
+    data: &[T],
+    ctxt: &VkContext,
+    image: &DustImage,
+    image_props: &ImageCreateInfo,
+    target_layout: ImageLayout,
+    objects: &mut TransferObjects,
+) -> Result<Semaphore, DustError>
+where
+    T: Sized + Clone + Copy,
+{
+    let transfer_buffer = make_buffer_and_copy(data, ctxt, objects)?;
+    let image_target = image.image;
 
     let image_subresource = ash::vk::ImageSubresourceLayers::default()
         .mip_level(0)
@@ -92,7 +171,8 @@ where
         .subresource_range(transfer_subresource_range);
     let transfer_back_barriers = vec![from_transfer_dst_layout];
 
-    let cmd_buffer = crate::graphics::pools::reserve_transfer_buffer(ctxt);
+    let cmd_buffer = crate::graphics::pools::reserve_transfer_buffer(ctxt)?;
+    objects.command_buffers.push(cmd_buffer);
 
     let copy_into_dependency_info = DependencyInfo::default()
         // .memory_barriers(&[])
@@ -106,7 +186,10 @@ where
         // .buffer_memory_barriers(&[])
         .dependency_flags(DependencyFlags::empty());
 
-    let copy_and_transition_complete_semaphore = util::create_binary_semaphore(ctxt);
+    let copy_and_transition_complete_semaphore = util::create_binary_semaphore(ctxt)?;
+    objects
+        .semaphores
+        .push(copy_and_transition_complete_semaphore);
 
     unsafe {
         match ctxt.logical_device.begin_command_buffer(
@@ -115,9 +198,10 @@ where
         ) {
             Ok(_) => {}
             Err(msg) => {
-                panic!("Unable to begin command buffer: {:?}", msg);
+                return Err(DustError::vulkan("beginning the image copy commands", msg));
             }
         };
+        debug::begin_label(cmd_buffer, "copy_to_image");
 
         ctxt.logical_device.cmd_pipeline_barrier2(
             cmd_buffer,
@@ -147,35 +231,38 @@ where
             // &[],
             // &transfer_back_barriers,
         );
+        debug::end_label(cmd_buffer);
         match ctxt.logical_device.end_command_buffer(cmd_buffer) {
             Ok(_) => {}
             Err(msg) => {
-                panic!("Unable to end the image copy command buffer: {:?}", msg);
+                return Err(DustError::vulkan("ending the image copy commands", msg));
             }
         };
     }
 
     let buffers = [cmd_buffer];
     let signal_semaphores = [copy_and_transition_complete_semaphore];
-    run_commands_blocking(ctxt, &buffers, &signal_semaphores);
+    run_commands_blocking(ctxt, objects, &buffers, &signal_semaphores)?;
 
     let (released_semaphore, released_fence) = image_transfer_family_release(
         ctxt,
+        objects,
         image_target,
         &transfer_subresource_range,
-        pools::get_graphics_queue_family(),
+        pools::get_graphics_queue_family()?,
         signal_semaphores[0],
         Some((ImageLayout::TRANSFER_DST_OPTIMAL, target_layout)),
-    );
+    )?;
 
     let (acquired_semaphore, acquired_fence) = image_transfer_family_acquire(
         ctxt,
+        objects,
         image_target,
         &transfer_subresource_range,
-        pools::get_transfer_queue_family(),
+        pools::get_transfer_queue_family()?,
         released_semaphore,
         Some((ImageLayout::TRANSFER_DST_OPTIMAL, target_layout)),
-    );
+    )?;
 
     let ownership_transfer_fences = [released_fence, acquired_fence];
     unsafe {
@@ -183,51 +270,98 @@ where
             ctxt.logical_device
                 .wait_for_fences(&ownership_transfer_fences, true, 10000000000)
         {
-            panic!("Waiting for the image release/acquire failed: {:?}", msg);
+            return Err(DustError::vulkan(
+                "waiting for the image release and acquire",
+                msg,
+            ));
         }
     }
+    objects.in_flight = false;
 
-    // cleanup
-    unsafe {
-        ctxt.logical_device.destroy_buffer(transfer_buffer, None);
-        ctxt.logical_device.free_memory(memory_handle, None);
-        ctxt.logical_device
-            .destroy_semaphore(copy_and_transition_complete_semaphore, None);
-        ctxt.logical_device
-            .destroy_semaphore(released_semaphore, None);
-        ctxt.logical_device.destroy_fence(released_fence, None);
-        ctxt.logical_device.destroy_fence(acquired_fence, None);
-    }
+    // The acquired semaphore goes to the caller, so release() must leave it alone.
+    objects
+        .semaphores
+        .retain(|semaphore| *semaphore != acquired_semaphore);
 
-    (
-        crate::graphics::image::new(
-            image_target,
-            image_props.format,
-            device_memory,
-            ctxt.logical_device.clone(),
-        ),
-        acquired_semaphore,
+    Ok(acquired_semaphore)
+}
+
+// *** make_image(ctxt: &VkContext, image_props: &ImageCreateInfo) -> DustImage
+//
+// Creates an image in device local memory with nothing in it, for the GPU to fill - a compute
+// shader's storage image, say.  It starts out UNDEFINED; the first barrier recorded against it
+// moves it into whatever layout its first use needs.
+//
+pub fn make_image(ctxt: &VkContext, image_props: &ImageCreateInfo) -> Result<DustImage, DustError> {
+    let image = match unsafe { ctxt.logical_device.create_image(image_props, None) } {
+        Ok(image) => image,
+        Err(msg) => {
+            return Err(DustError::vulkan("creating an image", msg));
+        }
+    };
+
+    let device_memory =
+        match back_image_with_memory(ctxt, &image, &MemoryPropertyFlags::DEVICE_LOCAL) {
+            Ok(memory) => memory,
+            Err(msg) => {
+                unsafe { ctxt.logical_device.destroy_image(image, None) };
+                return Err(msg);
+            }
+        };
+
+    crate::graphics::image::new(
+        image,
+        image_props.format,
+        Extent2D::default()
+            .width(image_props.extent.width)
+            .height(image_props.extent.height),
+        ImageLayout::UNDEFINED,
+        device_memory,
+        ctxt.logical_device.clone(),
     )
 }
 
-pub fn copy_to_buffer<T>(data: &[T], ctxt: &VkContext, usage: BufferUsageFlags) -> Buffer
+pub fn copy_to_buffer<T>(
+    data: &[T],
+    ctxt: &VkContext,
+    usage: BufferUsageFlags,
+) -> Result<DustBuffer, DustError>
 where
     T: Sized + Copy + Clone,
 {
     let size_in_bytes = std::mem::size_of_val(data) as u64;
+    let buffer = make_device_buffer(ctxt, size_in_bytes, usage | BufferUsageFlags::TRANSFER_DST)?;
+
+    let mut objects = TransferObjects::default();
+    let copied = record_buffer_copy(data, ctxt, &buffer, &mut objects);
+    let released = objects.release(ctxt);
 
-    let (transfer_buffer, mem_handle) = make_buffer_and_copy(data, ctxt);
+    // As for images, a failed copy drops the buffer only once release() has waited for the queue.
+    copied.and(released)?;
+    Ok(buffer)
+}
+
+fn record_buffer_copy<T>(
+    data: &[T],
+    ctxt: &VkContext,
+    buffer: &DustBuffer,
+    objects: &mut TransferObjects,
+) -> Result<(), DustError>
+where
+    T: Sized + Copy + Clone,
+{
+    let size_in_bytes = std::mem::size_of_val(data) as u64;
 
-    let perm_buffer = make_buffer(ctxt, size_in_bytes, usage | BufferUsageFlags::TRANSFER_DST);
-    let _perm_handle =
-        back_buffer_with_memory(ctxt, &perm_buffer, &MemoryPropertyFlags::DEVICE_LOCAL);
+    let transfer_buffer = make_buffer_and_copy(data, ctxt, objects)?;
+    let perm_buffer = buffer.buffer;
 
     let copy_region: [BufferCopy; 1] = [BufferCopy::default()
         .size(size_in_bytes)
         .src_offset(0)
         .dst_offset(0)];
 
-    let cmd_buffer = crate::graphics::pools::reserve_transfer_buffer(ctxt);
+    let cmd_buffer = crate::graphics::pools::reserve_transfer_buffer(ctxt)?;
+    objects.command_buffers.push(cmd_buffer);
 
     let begin_info =
         CommandBufferBeginInfo::default().flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT);
@@ -248,7 +382,7 @@ where
         {
             Ok(_) => {}
             Err(msg) => {
-                panic!("Failed to begin buffer: {:?}", msg);
+                return Err(DustError::vulkan("beginning the buffer copy commands", msg));
             }
         }
         ctxt.logical_device.cmd_copy_buffer(
@@ -275,28 +409,102 @@ where
         {
             Ok(_) => {}
             Err(msg) => {
-                panic!("Ending command buffer for transfer failed: {:?}", msg);
+                return Err(DustError::vulkan("ending the buffer copy commands", msg));
             }
         }
     }
 
-    // let commands_to_run = &cmd_buffer[0..1];
+    run_commands_blocking(ctxt, objects, &[cmd_buffer], &[])?;
+    objects.in_flight = false;
 
-    run_commands_blocking(ctxt, &[cmd_buffer], &[]);
+    Ok(())
+}
 
-    unsafe {
-        ctxt.logical_device.destroy_buffer(transfer_buffer, None);
-        ctxt.logical_device.free_memory(mem_handle, None);
-        // ctxt.logical_device.destroy_fence(fence, None);
-    }
-    perm_buffer
+// *** make_mapped_buffer(ctxt: &VkContext, size_in_bytes: u64, usage: BufferUsageFlags) -> DustBuffer
+//
+// Creates a buffer in host visible, host coherent memory and leaves it persistently mapped.  Meant
+// for data that is rewritten by the CPU every frame (vertex and index data for the batch renderer,
+// for example) where staging through copy_to_buffer would cost a transfer submission per frame.
+//
+pub fn make_mapped_buffer(
+    ctxt: &VkContext,
+    size_in_bytes: u64,
+    usage: BufferUsageFlags,
+) -> Result<DustBuffer, DustError> {
+    let mapped_buffer = make_buffer(ctxt, size_in_bytes, usage)?;
+
+    let mem_handle = match back_buffer_with_memory(
+        ctxt,
+        &mapped_buffer,
+        &(MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT),
+    ) {
+        Ok(memory) => memory,
+        Err(msg) => {
+            unsafe { ctxt.logical_device.destroy_buffer(mapped_buffer, None) };
+            return Err(msg);
+        }
+    };
+
+    let void_ptr = match unsafe {
+        ctxt.logical_device
+            .map_memory(mem_handle, 0, size_in_bytes, MemoryMapFlags::empty())
+    } {
+        Ok(ptr) => ptr,
+        Err(msg) => {
+            unsafe {
+                ctxt.logical_device.destroy_buffer(mapped_buffer, None);
+                ctxt.logical_device.free_memory(mem_handle, None);
+            }
+            return Err(DustError::vulkan("mapping buffer memory", msg));
+        }
+    };
+
+    Ok(buffer::new(
+        mapped_buffer,
+        size_in_bytes,
+        mem_handle,
+        Some(void_ptr),
+        ctxt.logical_device.clone(),
+    ))
+}
+
+// *** make_device_buffer(ctxt: &VkContext, size_in_bytes: u64, usage: BufferUsageFlags) -> DustBuffer
+//
+// Creates an unmapped buffer in device local memory, for data that only the GPU reads and
+// writes - storage buffers produced by one compute dispatch and consumed by the next, for
+// example.
+//
+pub fn make_device_buffer(
+    ctxt: &VkContext,
+    size_in_bytes: u64,
+    usage: BufferUsageFlags,
+) -> Result<DustBuffer, DustError> {
+    let device_buffer = make_buffer(ctxt, size_in_bytes, usage)?;
+
+    let mem_handle =
+        match back_buffer_with_memory(ctxt, &device_buffer, &MemoryPropertyFlags::DEVICE_LOCAL) {
+            Ok(memory) => memory,
+            Err(msg) => {
+                unsafe { ctxt.logical_device.destroy_buffer(device_buffer, None) };
+                return Err(msg);
+            }
+        };
+
+    Ok(buffer::new(
+        device_buffer,
+        size_in_bytes,
+        mem_handle,
+        None,
+        ctxt.logical_device.clone(),
+    ))
 }
 
 fn run_commands_blocking(
     ctxt: &VkContext,
+    objects: &mut TransferObjects,
     buffers: &[CommandBuffer],
     signal_complete_semaphore: &[Semaphore],
-) {
+) -> Result<(), DustError> {
     let submit_info = SubmitInfo::default()
         .command_buffers(buffers)
         // .wait_semaphores(&[])
@@ -310,19 +518,20 @@ fn run_commands_blocking(
         match ctxt.logical_device.create_fence(&fence_create_info, None) {
             Ok(fence) => fence,
             Err(msg) => {
-                panic!("Failed to create fence: {:?}", msg);
+                return Err(DustError::vulkan("creating a fence", msg));
             }
         }
     };
+    objects.fences.push(fence);
 
     unsafe {
         match ctxt
             .logical_device
             .queue_submit(ctxt.transfer_queue, &submits, fence)
         {
-            Ok(_) => {}
+            Ok(_) => objects.in_flight = true,
             Err(msg) => {
-                panic!("Queue submission failed: {:?}", msg);
+                return Err(DustError::vulkan("submitting transfer commands", msg));
             }
         }
     };
@@ -334,14 +543,19 @@ fn run_commands_blocking(
         {
             Ok(_) => {}
             Err(msg) => {
-                panic!("Waiting for fence at end of transfer errored: {:?}", msg);
+                return Err(DustError::vulkan("waiting for a transfer to finish", msg));
             }
         }
-        ctxt.logical_device.destroy_fence(fence, None);
     };
+
+    Ok(())
 }
 
-fn make_buffer_and_copy<T>(data: &[T], ctxt: &VkContext) -> (Buffer, DeviceMemory)
+fn make_buffer_and_copy<T>(
+    data: &[T],
+    ctxt: &VkContext,
+    objects: &mut TransferObjects,
+) -> Result<Buffer, DustError>
 where
     T: Sized + Copy + Clone,
 {
@@ -353,15 +567,22 @@ where
         ctxt,
         size_in_bytes,
         BufferUsageFlags::TRANSFER_SRC | BufferUsageFlags::TRANSFER_DST,
-    );
+    )?;
 
-    let mem_handle = back_buffer_with_memory(
+    let mem_handle = match back_buffer_with_memory(
         ctxt,
         &transfer_buffer,
         &(MemoryPropertyFlags::HOST_VISIBLE
             | MemoryPropertyFlags::HOST_COHERENT
             | MemoryPropertyFlags::DEVICE_LOCAL),
-    );
+    ) {
+        Ok(memory) => memory,
+        Err(msg) => {
+            unsafe { ctxt.logical_device.destroy_buffer(transfer_buffer, None) };
+            return Err(msg);
+        }
+    };
+    objects.staging.push((transfer_buffer, mem_handle));
 
     let void_ptr = match unsafe {
         ctxt.logical_device
@@ -369,7 +590,7 @@ where
     } {
         Ok(ptr) => ptr,
         Err(msg) => {
-            panic!("Failed to map the buffer backed memory to host: {:?}", msg);
+            return Err(DustError::vulkan("mapping buffer memory", msg));
         }
     };
 
@@ -384,14 +605,14 @@ where
         ctxt.logical_device.unmap_memory(mem_handle);
     }
 
-    (transfer_buffer, mem_handle)
+    Ok(transfer_buffer)
 }
 
 fn back_image_with_memory(
     ctxt: &VkContext,
     image: &Image,
     desired_properties: &MemoryPropertyFlags,
-) -> DeviceMemory {
+) -> Result<DeviceMemory, DustError> {
     let image_memory_requirements =
         unsafe { ctxt.logical_device.get_image_memory_requirements(*image) };
 
@@ -402,10 +623,7 @@ fn back_image_with_memory(
     ) {
         Ok(props) => props,
         Err(msg) => {
-            panic!(
-                "Unable to find a memory type matching required properties: {:?}",
-                msg
-            );
+            return Err(msg);
         }
     };
 
@@ -416,14 +634,15 @@ fn back_image_with_memory(
     let mem_handle = match unsafe { ctxt.logical_device.allocate_memory(&mem_alloc_info, None) } {
         Ok(handle) => handle,
         Err(msg) => {
-            panic!("Unable to allocate memory for image: {:?}", msg);
+            return Err(DustError::vulkan("allocating image memory", msg));
         }
     };
 
     match unsafe { ctxt.logical_device.bind_image_memory(*image, mem_handle, 0) } {
-        Ok(_) => mem_handle,
+        Ok(_) => Ok(mem_handle),
         Err(msg) => {
-            panic!("Unable to bind device memory to image: {:?}", msg);
+            unsafe { ctxt.logical_device.free_memory(mem_handle, None) };
+            Err(DustError::vulkan("binding image memory", msg))
         }
     }
 }
@@ -432,7 +651,7 @@ fn back_buffer_with_memory(
     ctxt: &VkContext,
     buffer: &Buffer,
     desired_properties: &MemoryPropertyFlags,
-) -> DeviceMemory {
+) -> Result<DeviceMemory, DustError> {
     let transfer_memory_requirements =
         unsafe { ctxt.logical_device.get_buffer_memory_requirements(*buffer) };
 
@@ -443,7 +662,7 @@ fn back_buffer_with_memory(
     ) {
         Ok(props) => props,
         Err(msg) => {
-            panic!("A viable memory heap could not be found: {:?}", msg);
+            return Err(msg);
         }
     };
 
@@ -454,10 +673,7 @@ fn back_buffer_with_memory(
     let mem_handle = match unsafe { ctxt.logical_device.allocate_memory(&mem_alloc_info, None) } {
         Ok(handle) => handle,
         Err(msg) => {
-            panic!(
-                "Unable to allocate buffer sized {}: {:?}",
-                transfer_memory_requirements.size, msg
-            );
+            return Err(DustError::vulkan("allocating buffer memory", msg));
         }
     };
 
@@ -467,17 +683,19 @@ fn back_buffer_with_memory(
     } {
         Ok(_) => {}
         Err(msg) => {
-            panic!(
-                "Failed to bind the buffer to the allocated memory: {:?}",
-                msg
-            );
+            unsafe { ctxt.logical_device.free_memory(mem_handle, None) };
+            return Err(DustError::vulkan("binding buffer memory", msg));
         }
     }
 
-    mem_handle
+    Ok(mem_handle)
 }
 
-fn make_buffer(ctxt: &VkContext, buffer_size: u64, flags: BufferUsageFlags) -> Buffer {
+fn make_buffer(
+    ctxt: &VkContext,
+    buffer_size: u64,
+    flags: BufferUsageFlags,
+) -> Result<Buffer, DustError> {
     let transfer_buffer_info = BufferCreateInfo::default()
         .size(buffer_size)
         .usage(flags)
@@ -488,10 +706,8 @@ fn make_buffer(ctxt: &VkContext, buffer_size: u64, flags: BufferUsageFlags) -> B
         ctxt.logical_device
             .create_buffer(&transfer_buffer_info, None)
     } {
-        Ok(buffer) => buffer,
-        Err(msg) => {
-            panic!("Transfer buffer creation failed: {:?}", msg);
-        }
+        Ok(buffer) => Ok(buffer),
+        Err(msg) => Err(DustError::vulkan("creating a buffer", msg)),
     }
 }
 
@@ -512,20 +728,21 @@ pub fn match_memory_type(
     Err(DustError::NoMatchingMemoryType)
 }
 
-pub fn image_transfer_family_release(
+fn image_transfer_family_release(
     ctxt: &VkContext,
+    objects: &mut TransferObjects,
     image: Image,
     subresource: &ImageSubresourceRange,
     new_queue_family: u32,
     available: Semaphore,
     from_layout: Option<(ImageLayout, ImageLayout)>,
-) -> (Semaphore, Fence) {
+) -> Result<(Semaphore, Fence), DustError> {
     debug!(
         "Releasing image with aspect mask of {:?}.",
         subresource.aspect_mask
     );
     let image_barrier = ImageMemoryBarrier2::default()
-        .src_queue_family_index(pools::get_transfer_queue_family())
+        .src_queue_family_index(pools::get_transfer_queue_family()?)
         .dst_queue_family_index(new_queue_family)
         .src_access_mask(AccessFlags2::TRANSFER_WRITE)
         .src_stage_mask(PipelineStageFlags2::COPY)
@@ -543,13 +760,16 @@ pub fn image_transfer_family_release(
 
     let dependency_info = DependencyInfo::default().image_memory_barriers(&release_barriers);
 
-    let release_semaphore = [util::create_binary_semaphore(ctxt)];
-    let released_fence = util::create_fence(ctxt);
+    let release_semaphore = [util::create_binary_semaphore(ctxt)?];
+    objects.semaphores.push(release_semaphore[0]);
+    let released_fence = util::create_fence(ctxt)?;
+    objects.fences.push(released_fence);
     let available_semaphore = [available];
 
     let queue = ctxt.transfer_queue;
 
-    let buffer = pools::reserve_transfer_buffer(ctxt);
+    let buffer = pools::reserve_transfer_buffer(ctxt)?;
+    objects.command_buffers.push(buffer);
     let buffers = [buffer];
 
     let begin_info =
@@ -564,34 +784,55 @@ pub fn image_transfer_family_release(
     let submits = [submit_info];
 
     unsafe {
-        ctxt.logical_device
-            .begin_command_buffer(buffer, &begin_info);
+        if let Err(msg) = ctxt
+            .logical_device
+            .begin_command_buffer(buffer, &begin_info)
+        {
+            return Err(DustError::vulkan(
+                "releasing an image to another queue family",
+                msg,
+            ));
+        }
         ctxt.logical_device
             .cmd_pipeline_barrier2(buffer, &dependency_info);
-        ctxt.logical_device.end_command_buffer(buffer);
+        if let Err(msg) = ctxt.logical_device.end_command_buffer(buffer) {
+            return Err(DustError::vulkan(
+                "releasing an image to another queue family",
+                msg,
+            ));
+        }
 
-        ctxt.logical_device
-            .queue_submit(queue, &submits, released_fence);
+        if let Err(msg) = ctxt
+            .logical_device
+            .queue_submit(queue, &submits, released_fence)
+        {
+            return Err(DustError::vulkan(
+                "releasing an image to another queue family",
+                msg,
+            ));
+        }
+        objects.in_flight = true;
     }
 
-    (release_semaphore[0], released_fence)
+    Ok((release_semaphore[0], released_fence))
 }
 
-pub fn image_transfer_family_acquire(
+fn image_transfer_family_acquire(
     ctxt: &VkContext,
+    objects: &mut TransferObjects,
     image: Image,
     subresource: &ImageSubresourceRange,
     previous_queue_family: u32,
     available: Semaphore,
     to_layout: Option<(ImageLayout, ImageLayout)>,
-) -> (Semaphore, Fence) {
+) -> Result<(Semaphore, Fence), DustError> {
     debug!(
         "Acquiring image with aspect mask: {:?}",
         subresource.aspect_mask
     );
     let image_barrier = ImageMemoryBarrier2::default()
         .src_queue_family_index(previous_queue_family)
-        .dst_queue_family_index(pools::get_graphics_queue_family())
+        .dst_queue_family_index(pools::get_graphics_queue_family()?)
         .src_access_mask(AccessFlags2::TRANSFER_WRITE)
         .src_stage_mask(PipelineStageFlags2::COPY)
         .subresource_range(*subresource)
@@ -608,13 +849,16 @@ pub fn image_transfer_family_acquire(
 
     let dependency_info = DependencyInfo::default().image_memory_barriers(&release_barriers);
 
-    let release_semaphore = [util::create_binary_semaphore(ctxt)];
-    let acquired_fence = util::create_fence(ctxt);
+    let release_semaphore = [util::create_binary_semaphore(ctxt)?];
+    objects.semaphores.push(release_semaphore[0]);
+    let acquired_fence = util::create_fence(ctxt)?;
+    objects.fences.push(acquired_fence);
     let available_semaphore = [available];
 
     let queue = ctxt.transfer_queue;
 
-    let buffer = pools::reserve_transfer_buffer(ctxt);
+    let buffer = pools::reserve_transfer_buffer(ctxt)?;
+    objects.command_buffers.push(buffer);
     let buffers = [buffer];
 
     let begin_info =
@@ -629,17 +873,37 @@ pub fn image_transfer_family_acquire(
     let submits = [submit_info];
 
     unsafe {
-        ctxt.logical_device
-            .begin_command_buffer(buffer, &begin_info);
+        if let Err(msg) = ctxt
+            .logical_device
+            .begin_command_buffer(buffer, &begin_info)
+        {
+            return Err(DustError::vulkan(
+                "acquiring an image from another queue family",
+                msg,
+            ));
+        }
         ctxt.logical_device
             .cmd_pipeline_barrier2(buffer, &dependency_info);
-        ctxt.logical_device.end_command_buffer(buffer);
+        if let Err(msg) = ctxt.logical_device.end_command_buffer(buffer) {
+            return Err(DustError::vulkan(
+                "acquiring an image from another queue family",
+                msg,
+            ));
+        }
 
-        ctxt.logical_device
-            .queue_submit(queue, &submits, acquired_fence);
+        if let Err(msg) = ctxt
+            .logical_device
+            .queue_submit(queue, &submits, acquired_fence)
+        {
+            return Err(DustError::vulkan(
+                "acquiring an image from another queue family",
+                msg,
+            ));
+        }
+        objects.in_flight = true;
     }
 
-    (release_semaphore[0], acquired_fence)
+    Ok((release_semaphore[0], acquired_fence))
 }
 
 // pub fn image_graphics_family_release(
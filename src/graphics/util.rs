@@ -1,33 +1,29 @@
-use crate::setup::instance::VkContext;
+use crate::{dust_errors::DustError, setup::instance::VkContext};
 
 use ash::vk::{
     Fence, FenceCreateFlags, FenceCreateInfo, Semaphore, SemaphoreCreateFlags, SemaphoreCreateInfo,
 };
 
-pub fn create_fence(ctxt: &VkContext) -> Fence {
+pub fn create_fence(ctxt: &VkContext) -> Result<Fence, DustError> {
     match unsafe {
         ctxt.logical_device.create_fence(
             &FenceCreateInfo::default().flags(FenceCreateFlags::empty()),
             None,
         )
     } {
-        Ok(fence) => fence,
-        Err(msg) => {
-            panic!("Unable to create fence: {:?}", msg);
-        }
+        Ok(fence) => Ok(fence),
+        Err(msg) => Err(DustError::vulkan("creating a fence", msg)),
     }
 }
 
-pub fn create_binary_semaphore(ctxt: &VkContext) -> Semaphore {
+pub fn create_binary_semaphore(ctxt: &VkContext) -> Result<Semaphore, DustError> {
     match unsafe {
         ctxt.logical_device.create_semaphore(
             &SemaphoreCreateInfo::default().flags(SemaphoreCreateFlags::empty()),
             None,
         )
     } {
-        Ok(sem) => sem,
-        Err(msg) => {
-            panic!("Unable to create semaphore: {:?}", msg);
-        }
+        Ok(sem) => Ok(sem),
+        Err(msg) => Err(DustError::vulkan("creating a semaphore", msg)),
     }
 }
@@ -1,8 +1,21 @@
+// A key, by its X keysym.  The lock keys are ToggleKeys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum KeyStroke {
     Key(u32),
     ToggleKey(u32),
 }
 
+// What the window's event thread hands the game, in the order it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    KeyDown(KeyStroke),
+    KeyUp(KeyStroke),
+    FocusGained,
+    FocusLost,
+    // The window manager's close button.
+    CloseRequested,
+}
+
 pub enum Symbol {
     PrintableSymbol(PrintableSymbols),
     UnprintableSymbol(UnprintableSymbols),
@@ -0,0 +1,52 @@
+//! dust: a Vulkan renderer for a Doom-style engine, drawing into an X11 window through XCB.
+//!
+//! A game drives it in roughly this order:
+//!
+//!   config::load()                        read the configuration file and command line
+//!   xcb_window::open()                    connect and put up the configured window
+//!   xcb_window::event_loop(conn, sender)  on its own thread, feeding InputEvents to the game
+//!   VkContext::builder()...build()        choose a device and make the swapchain for the window
+//!   bitmap::new(), resident::upload()     decode images and keep them on the GPU
+//!   GameLoop::new(...).run(game, input)   tick the Game at a fixed rate and render it in between
+//!   composite_hud(), ...                  draw and present a frame, from Game::render
+//!
+//! and shuts down in the reverse: once run() returns - on a quit, a closed window, or SIGINT or
+//! SIGTERM after shutdown::install_signal_handlers() - the VkContext is dropped, which waits for
+//! the device to go idle first, then xcb_window::stop_event_loop() lets the event thread be joined,
+//! and xcb_window::close_window() takes the window down.
+//!
+//! Every fallible step returns a DustError.  One that is_lost() is survivable: VkContext::recover
+//! rebuilds the context and brings the resident textures back.
+
+mod dust_errors;
+mod graphics;
+mod input;
+mod runtime;
+mod setup;
+
+pub use dust_errors::DustError;
+pub use graphics::batch::Quad;
+pub use graphics::bitmap::{self, Bitmap, BitmapError};
+pub use graphics::buffer::DustBuffer;
+pub use graphics::image::{DustImage, TextureFilter};
+pub use graphics::palette::{self, PaletteFramebuffer};
+pub use graphics::postprocess::{self, PostEffect};
+pub use graphics::render::{
+    composite_hud, composite_palette, create_texture_descriptor_set_layout, finish_frames,
+    present_scaled,
+};
+pub use graphics::resident::{self, TextureId, TextureSource};
+pub use graphics::scaling::{Placement, ScaleFilter, ScaleMode, VirtualResolution};
+pub use graphics::swapchain::{self, PresentMode, SurfaceEncoding, SwapchainConfig};
+pub use graphics::{compute, frame_limiter, pipelines, targets, transfer};
+pub use input::input::{InputEvent, KeyStroke, PrintableSymbols, Symbol, UnprintableSymbols};
+pub use runtime::game_loop::{Flow, Game, GameLoop, DOOM_TICK_RATE};
+pub use runtime::shutdown;
+pub use runtime::stats::{self, Stats};
+pub use setup::config::{self, Config, ConfigError};
+pub use setup::device_selection::DeviceOverride;
+pub use setup::instance::{VkContext, VkContextBuilder};
+pub use setup::key_mapper::KeyMapper;
+pub use setup::requirements::{self, DeviceFeature, DeviceLimit, DeviceRequirements};
+pub use setup::xcb_keymapper::{self, XcbKeyMapper};
+pub use setup::xcb_window;
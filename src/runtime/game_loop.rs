@@ -0,0 +1,401 @@
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use log::{debug, info};
+
+use crate::{dust_errors::DustError, input::input::InputEvent};
+
+use super::{shutdown, stats};
+
+// How often a paused loop looks to see whether it has been asked to shut down.
+const PAUSED_POLL: Duration = Duration::from_millis(100);
+
+// Doom ran its simulation 35 times a second, and its timings all assume it.
+pub const DOOM_TICK_RATE: u32 = 35;
+
+// Whether the game wants to carry on after a tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+// *** Game
+//
+// What the game loop drives.  tick() advances the simulation by one fixed step, given the input
+// that arrived since the last tick.  render() draws the state alpha of the way from the previous
+// tick to the latest one, alpha being in [0, 1), so that motion stays smooth when frames and
+// ticks do not line up.
+//
+pub trait Game {
+    fn tick(&mut self, input: &[InputEvent], step: Duration) -> Flow;
+    fn render(&mut self, alpha: f32) -> Result<(), DustError>;
+}
+
+// *** GameLoop
+//
+// Runs the simulation at a fixed rate and renders as often as the present mode and frame cap let
+// it.  Frame time is banked, and spent a tick at a time; what is left over when the next frame is
+// drawn becomes its alpha.
+//
+pub struct GameLoop {
+    tick_rate: u32,
+    step: Duration,
+    max_ticks_per_frame: u32,
+    pause_on_focus_loss: bool,
+}
+
+// How a frame ended.
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    Drawn,
+    // The loop paused for the focus and has it back; nothing was drawn.
+    Resumed,
+    // The loop is to stop.
+    Done,
+}
+
+// What draining the input channel found.
+enum Drained {
+    Input,
+    FocusLost,
+    Closed,
+}
+
+impl GameLoop {
+    // tick_rate is in ticks a second.
+    pub fn new(tick_rate: u32) -> GameLoop {
+        let tick_rate = tick_rate.max(1);
+        GameLoop {
+            tick_rate,
+            step: Duration::from_secs(1) / tick_rate,
+            max_ticks_per_frame: 5,
+            pause_on_focus_loss: true,
+        }
+    }
+
+    // After a long frame - a hitch, a breakpoint - no more than this many ticks are run to catch
+    // up, and the rest of the time is let go, rather than each late frame making the next later.
+    // Defaults to 5.
+    pub fn max_ticks_per_frame(mut self, max_ticks_per_frame: u32) -> GameLoop {
+        self.max_ticks_per_frame = max_ticks_per_frame.max(1);
+        self
+    }
+
+    // Whether the simulation stops, and nothing is drawn, while the window does not have the
+    // focus.  Defaults to true.
+    pub fn pause_on_focus_loss(mut self, pause_on_focus_loss: bool) -> GameLoop {
+        self.pause_on_focus_loss = pause_on_focus_loss;
+        self
+    }
+
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    // *** run<G: Game>(&self, game: &mut G, input: &Receiver<InputEvent>) -> Result<(), DustError>
+    //
+    // Ticks and renders game until a tick returns Flow::Quit, the window is closed, the event
+    // thread hangs up or shutdown is requested.  input is drained before every tick, and each tick
+    // gets what had arrived by then.  A render error ends the loop and is handed back; the loop can
+    // be run again once it has been dealt with.  Returning is the cue to tear everything down.
+    //
+    pub fn run<G: Game>(
+        &self,
+        game: &mut G,
+        input: &Receiver<InputEvent>,
+    ) -> Result<(), DustError> {
+        info!("Game loop running at {} ticks a second.", self.tick_rate);
+        let mut events = Vec::new();
+        let mut previous = Instant::now();
+        let mut lag = Duration::ZERO;
+        stats::restart_window();
+
+        loop {
+            if shutdown::requested() {
+                info!("Shutdown requested; leaving the game loop.");
+                return Ok(());
+            }
+
+            let frame_start = Instant::now();
+            let frame_time = frame_start - previous;
+            previous = frame_start;
+
+            match self.frame(game, input, &mut events, &mut lag, frame_time)? {
+                Frame::Drawn => {}
+                Frame::Resumed => {
+                    // The time away is not made up for.
+                    previous = Instant::now();
+                    stats::restart_window();
+                }
+                Frame::Done => return Ok(()),
+            }
+        }
+    }
+
+    // One trip round run(): banks frame_time on top of lag, runs the ticks it pays for and renders
+    // what is left over.
+    fn frame<G: Game>(
+        &self,
+        game: &mut G,
+        input: &Receiver<InputEvent>,
+        events: &mut Vec<InputEvent>,
+        lag: &mut Duration,
+        frame_time: Duration,
+    ) -> Result<Frame, DustError> {
+        *lag = (*lag + frame_time).min(self.step * self.max_ticks_per_frame);
+
+        while *lag >= self.step {
+            match drain(input, events) {
+                Drained::Input => {}
+                Drained::FocusLost if self.pause_on_focus_loss => {
+                    info!("The window lost the focus; pausing.");
+                    if !wait_for_focus(input, events) {
+                        return Ok(Frame::Done);
+                    }
+                    info!("The window has the focus again; carrying on.");
+                    *lag = Duration::ZERO;
+                    return Ok(Frame::Resumed);
+                }
+                Drained::FocusLost => {}
+                Drained::Closed => {
+                    shutdown::request();
+                    return Ok(Frame::Done);
+                }
+            }
+
+            let tick_start = Instant::now();
+            let flow = game.tick(events, self.step);
+            stats::record_tick(tick_start.elapsed());
+            events.clear();
+
+            if flow == Flow::Quit {
+                info!("The game asked to quit.");
+                shutdown::request();
+                return Ok(Frame::Done);
+            }
+            *lag -= self.step;
+        }
+
+        game.render(lag.as_secs_f32() / self.step.as_secs_f32())?;
+        stats::record_frame(frame_time);
+        Ok(Frame::Drawn)
+    }
+}
+
+// Moves everything waiting on input into events, noting whether the window ended up without the
+// focus, or is to close.
+fn drain(input: &Receiver<InputEvent>, events: &mut Vec<InputEvent>) -> Drained {
+    let mut focus_lost = false;
+    loop {
+        match input.try_recv() {
+            Ok(event) => {
+                match event {
+                    InputEvent::FocusLost => focus_lost = true,
+                    InputEvent::FocusGained => focus_lost = false,
+                    InputEvent::CloseRequested => {
+                        info!("The window was closed; leaving the game loop.");
+                        return Drained::Closed;
+                    }
+                    _ => {}
+                }
+                events.push(event);
+            }
+            Err(TryRecvError::Empty) => {
+                return if focus_lost {
+                    Drained::FocusLost
+                } else {
+                    Drained::Input
+                };
+            }
+            Err(TryRecvError::Disconnected) => {
+                debug!("The input channel closed; leaving the game loop.");
+                return Drained::Closed;
+            }
+        }
+    }
+}
+
+// Blocks until the window has the focus back, keeping whatever else arrives in the meantime for
+// the next tick.  False if the window closes, the event thread hangs up or shutdown is requested
+// first.
+fn wait_for_focus(input: &Receiver<InputEvent>, events: &mut Vec<InputEvent>) -> bool {
+    loop {
+        match input.recv_timeout(PAUSED_POLL) {
+            Ok(InputEvent::FocusGained) => {
+                events.push(InputEvent::FocusGained);
+                return true;
+            }
+            Ok(InputEvent::CloseRequested) => {
+                info!("The window was closed while paused.");
+                shutdown::request();
+                return false;
+            }
+            Ok(event) => events.push(event),
+            Err(RecvTimeoutError::Timeout) => {
+                if shutdown::requested() {
+                    info!("Shutdown requested while paused.");
+                    return false;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                shutdown::request();
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Sender},
+        thread,
+    };
+
+    use super::*;
+    use crate::input::input::KeyStroke;
+
+    // Four ticks a second, so that a step is a whole number of milliseconds.
+    const TICK_RATE: u32 = 4;
+    const STEP: Duration = Duration::from_millis(250);
+
+    // Keeps what the loop handed it.
+    #[derive(Default)]
+    struct Recording {
+        ticks: Vec<Vec<InputEvent>>,
+        alphas: Vec<f32>,
+    }
+
+    impl Game for Recording {
+        fn tick(&mut self, input: &[InputEvent], step: Duration) -> Flow {
+            assert_eq!(step, STEP);
+            self.ticks.push(input.to_vec());
+            Flow::Continue
+        }
+
+        fn render(&mut self, alpha: f32) -> Result<(), DustError> {
+            self.alphas.push(alpha);
+            Ok(())
+        }
+    }
+
+    struct Harness {
+        game_loop: GameLoop,
+        game: Recording,
+        sender: Sender<InputEvent>,
+        input: Receiver<InputEvent>,
+        events: Vec<InputEvent>,
+        lag: Duration,
+    }
+
+    impl Harness {
+        fn new(game_loop: GameLoop) -> Harness {
+            let (sender, input) = mpsc::channel();
+            Harness {
+                game_loop,
+                game: Recording::default(),
+                sender,
+                input,
+                events: Vec::new(),
+                lag: Duration::ZERO,
+            }
+        }
+
+        // Runs a frame that took frame_time, returning how it ended and the ticks it ran.
+        fn frame(&mut self, frame_time: Duration) -> (Frame, usize) {
+            let ticks = self.game.ticks.len();
+            let frame = self
+                .game_loop
+                .frame(
+                    &mut self.game,
+                    &self.input,
+                    &mut self.events,
+                    &mut self.lag,
+                    frame_time,
+                )
+                .unwrap();
+            (frame, self.game.ticks.len() - ticks)
+        }
+    }
+
+    #[test]
+    fn spends_banked_time_a_step_at_a_time() {
+        let mut harness = Harness::new(GameLoop::new(TICK_RATE));
+
+        assert_eq!(harness.frame(STEP / 2), (Frame::Drawn, 0));
+        assert_eq!(harness.frame(STEP * 3 / 2), (Frame::Drawn, 2));
+        assert_eq!(harness.frame(STEP / 4), (Frame::Drawn, 0));
+        assert_eq!(harness.frame(STEP * 3 / 4), (Frame::Drawn, 1));
+
+        assert_eq!(harness.game.alphas, vec![0.5, 0.0, 0.25, 0.0]);
+        assert_eq!(harness.lag, Duration::ZERO);
+    }
+
+    #[test]
+    fn lets_go_of_time_past_the_catch_up_limit() {
+        let mut harness = Harness::new(GameLoop::new(TICK_RATE).max_ticks_per_frame(3));
+
+        assert_eq!(harness.frame(STEP * 100), (Frame::Drawn, 3));
+        assert_eq!(harness.lag, Duration::ZERO);
+
+        // The hitch is not paid back over the frames that follow.
+        assert_eq!(harness.frame(STEP / 2), (Frame::Drawn, 0));
+        assert_eq!(harness.game.alphas, vec![0.0, 0.5]);
+    }
+
+    #[test]
+    fn hands_input_to_the_next_tick_only() {
+        let mut harness = Harness::new(GameLoop::new(TICK_RATE));
+        let key = KeyStroke::Key(0x61);
+        harness.sender.send(InputEvent::KeyDown(key)).unwrap();
+        harness.sender.send(InputEvent::KeyUp(key)).unwrap();
+
+        // Not enough time for a tick: the input waits for one.
+        assert_eq!(harness.frame(STEP / 2), (Frame::Drawn, 0));
+        assert_eq!(harness.frame(STEP * 3 / 2), (Frame::Drawn, 2));
+        assert_eq!(
+            harness.game.ticks,
+            vec![
+                vec![InputEvent::KeyDown(key), InputEvent::KeyUp(key)],
+                Vec::new()
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_the_bank_after_a_pause() {
+        let mut harness = Harness::new(GameLoop::new(TICK_RATE));
+        assert_eq!(harness.frame(STEP / 2), (Frame::Drawn, 0));
+
+        // The focus comes back once the loop is waiting for it.
+        harness.sender.send(InputEvent::FocusLost).unwrap();
+        let sender = harness.sender.clone();
+        let regain = thread::spawn(move || {
+            thread::sleep(PAUSED_POLL / 2);
+            sender.send(InputEvent::FocusGained).unwrap();
+        });
+        assert_eq!(harness.frame(STEP), (Frame::Resumed, 0));
+        regain.join().unwrap();
+        assert_eq!(harness.lag, Duration::ZERO);
+        assert_eq!(harness.game.alphas, vec![0.5]);
+
+        // Both focus changes are the next tick's input.
+        assert_eq!(harness.frame(STEP), (Frame::Drawn, 1));
+        assert_eq!(
+            harness.game.ticks,
+            vec![vec![InputEvent::FocusLost, InputEvent::FocusGained]]
+        );
+    }
+
+    #[test]
+    fn keeps_ticking_without_the_focus_when_asked_to() {
+        let mut harness = Harness::new(GameLoop::new(TICK_RATE).pause_on_focus_loss(false));
+        harness.sender.send(InputEvent::FocusLost).unwrap();
+
+        assert_eq!(harness.frame(STEP * 2), (Frame::Drawn, 2));
+        assert_eq!(harness.game.ticks[0], vec![InputEvent::FocusLost]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod game_loop;
+pub mod shutdown;
+pub mod stats;
@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{error, info};
+
+// Set by request() and by the signal handlers; the game loop stops at the next frame once it is.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Asks the game loop to stop, from anywhere - a quit menu, another thread.
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+// Only an atomic store is safe in a signal handler; the game loop does the rest.
+extern "C" fn on_signal(_signal: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// *** install_signal_handlers()
+//
+// Turns SIGINT and SIGTERM into a shutdown request, so that Ctrl-C or a kill still takes the
+// device down in order.  The handlers go back to the default after firing once: a second Ctrl-C
+// kills a shutdown that has hung.
+//
+pub fn install_signal_handlers() {
+    for (signal, name) in [(libc::SIGINT, "SIGINT"), (libc::SIGTERM, "SIGTERM")] {
+        let installed = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut()) == 0
+        };
+
+        if installed {
+            info!("{} now shuts the game down in order.", name);
+        } else {
+            error!(
+                "Unable to handle {}: {}",
+                name,
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+// Averages are taken over windows this long, so the numbers on the HUD hold still long enough to
+// read.
+const WINDOW: Duration = Duration::from_secs(1);
+
+static STATS: OnceLock<Mutex<Recorder>> = OnceLock::new();
+
+// *** Stats
+//
+// How the game loop is keeping up.  The times and the frame rate are averages over the last whole
+// second; the counts are totals since the loop started.
+//
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    // From the start of one frame to the start of the next.
+    pub frame_time: Duration,
+    // How long a simulation tick takes to run.
+    pub tick_time: Duration,
+    pub fps: f32,
+    // Simulation ticks run a second; the tick rate, unless the simulation is falling behind.
+    pub tps: f32,
+    pub frames: u64,
+    pub ticks: u64,
+}
+
+struct Recorder {
+    published: Stats,
+    window_start: Option<Instant>,
+    frames: u32,
+    frame_time: Duration,
+    ticks: u32,
+    tick_time: Duration,
+}
+
+impl Recorder {
+    fn new() -> Recorder {
+        Recorder {
+            published: Stats::default(),
+            window_start: None,
+            frames: 0,
+            frame_time: Duration::ZERO,
+            ticks: 0,
+            tick_time: Duration::ZERO,
+        }
+    }
+
+    fn record_tick(&mut self, tick_time: Duration) {
+        self.ticks += 1;
+        self.tick_time += tick_time;
+        self.published.ticks += 1;
+    }
+
+    // record_frame(), with now the time the frame was recorded.
+    fn record_frame(&mut self, frame_time: Duration, now: Instant) {
+        self.frames += 1;
+        self.frame_time += frame_time;
+        self.published.frames += 1;
+
+        let window_start = *self.window_start.get_or_insert(now);
+        let elapsed = now - window_start;
+        if elapsed < WINDOW {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f32();
+        self.published.fps = self.frames as f32 / seconds;
+        self.published.tps = self.ticks as f32 / seconds;
+        self.published.frame_time = self.frame_time / self.frames;
+        self.published.tick_time = if self.ticks > 0 {
+            self.tick_time / self.ticks
+        } else {
+            Duration::ZERO
+        };
+
+        self.window_start = Some(now);
+        self.clear_window();
+    }
+
+    fn restart_window(&mut self) {
+        self.window_start = None;
+        self.clear_window();
+    }
+
+    fn clear_window(&mut self) {
+        self.frames = 0;
+        self.frame_time = Duration::ZERO;
+        self.ticks = 0;
+        self.tick_time = Duration::ZERO;
+    }
+}
+
+fn recorder() -> &'static Mutex<Recorder> {
+    STATS.get_or_init(|| Mutex::new(Recorder::new()))
+}
+
+// The latest figures, for the HUD or the log.
+pub fn stats() -> Stats {
+    recorder().lock().unwrap().published
+}
+
+// Called by the game loop after each tick it runs.
+pub fn record_tick(tick_time: Duration) {
+    recorder().lock().unwrap().record_tick(tick_time);
+}
+
+// *** record_frame(frame_time: Duration)
+//
+// Called by the game loop once a frame, with the time since the last one.  Closes the averaging
+// window once it has run for a second.
+//
+pub fn record_frame(frame_time: Duration) {
+    recorder()
+        .lock()
+        .unwrap()
+        .record_frame(frame_time, Instant::now());
+}
+
+// Starts a fresh averaging window, so that time spent paused does not drag the averages down.
+pub fn restart_window() {
+    recorder().lock().unwrap().restart_window();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn publishes_nothing_until_a_window_closes() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new();
+        for frame in 0..50 {
+            recorder.record_tick(Duration::from_millis(2));
+            recorder.record_frame(FRAME, start + FRAME * frame);
+        }
+
+        let published = recorder.published;
+        assert_eq!(published.frames, 50);
+        assert_eq!(published.ticks, 50);
+        assert_eq!(published.fps, 0.0);
+        assert_eq!(published.frame_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn averages_over_the_window() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new();
+        for frame in 0..=50 {
+            if frame % 2 == 0 {
+                recorder.record_tick(Duration::from_millis(4));
+            }
+            recorder.record_frame(FRAME, start + FRAME * frame);
+        }
+
+        // The window opened with the first frame and closed a second later, on the 51st.
+        let published = recorder.published;
+        assert_eq!(published.fps, 51.0);
+        assert_eq!(published.tps, 26.0);
+        assert_eq!(published.frame_time, FRAME);
+        assert_eq!(published.tick_time, Duration::from_millis(4));
+        assert_eq!(published.frames, 51);
+        assert_eq!(published.ticks, 26);
+    }
+
+    #[test]
+    fn the_next_window_starts_where_the_last_closed() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new();
+        recorder.record_frame(FRAME, start);
+        recorder.record_frame(FRAME, start + WINDOW);
+
+        recorder.record_frame(Duration::from_millis(500), start + WINDOW * 3 / 2);
+        recorder.record_frame(Duration::from_millis(500), start + WINDOW * 2);
+
+        let published = recorder.published;
+        assert_eq!(published.fps, 2.0);
+        assert_eq!(published.tps, 0.0);
+        assert_eq!(published.frame_time, Duration::from_millis(500));
+        assert_eq!(published.tick_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn a_restart_drops_the_time_spent_paused() {
+        let start = Instant::now();
+        let mut recorder = Recorder::new();
+        recorder.record_frame(FRAME, start);
+        recorder.restart_window();
+
+        // Ten seconds later, the window opens afresh rather than closing over the pause.
+        let resumed = start + WINDOW * 10;
+        recorder.record_frame(FRAME, resumed);
+        assert_eq!(recorder.published.fps, 0.0);
+
+        recorder.record_frame(FRAME, resumed + WINDOW);
+        assert_eq!(recorder.published.fps, 2.0);
+        assert_eq!(recorder.published.frames, 3);
+    }
+}
@@ -0,0 +1,907 @@
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    slice::Iter,
+};
+
+use log::{debug, info};
+
+use crate::graphics::swapchain::PresentMode;
+use crate::setup::device_selection::{self, DeviceOverride};
+
+// Names a configuration file to use instead of the one in the XDG config directory.
+pub const CONFIG_ENV_VAR: &str = "DUST_CONFIG";
+
+const CONFIG_DIR_NAME: &str = "dust";
+const CONFIG_FILE_NAME: &str = "dust.toml";
+
+// *** Config
+//
+// Everything about how the engine starts that a player might want to change.  Built by load() from
+// the defaults, the configuration file, the environment and the command line, in that order, and
+// checked before it is handed out.
+//
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub graphics: GraphicsConfig,
+    pub assets: AssetConfig,
+    // The file the configuration was read from, and the one save() writes.
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowConfig {
+    // The RandR name of the monitor to open on, e.g. "DP-1".  None is the primary monitor.
+    pub monitor: Option<String>,
+    // The window's size.  None makes it fullscreen.
+    pub resolution: Option<(u32, u32)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphicsConfig {
+    // None takes the best scoring device.
+    pub device: Option<DeviceOverride>,
+    pub present_mode: PresentMode,
+    // Frames a second; 0 is no cap.
+    pub frame_cap: u32,
+    // None looks in the shaders directory beside the executable.
+    pub shader_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetConfig {
+    pub directory: PathBuf,
+}
+
+impl Config {
+    pub fn new(path: PathBuf) -> Config {
+        Config {
+            window: WindowConfig {
+                monitor: None,
+                resolution: None,
+            },
+            graphics: GraphicsConfig {
+                device: None,
+                present_mode: PresentMode::Mailbox,
+                frame_cap: 0,
+                shader_dir: None,
+            },
+            assets: AssetConfig {
+                directory: PathBuf::from("resources"),
+            },
+            path,
+        }
+    }
+
+    // *** save(&self) -> Result<(), ConfigError>
+    //
+    // Writes the configuration to path, creating its directory if need be.  Every setting is
+    // written, defaults included, each under a comment saying what it takes.
+    //
+    pub fn save(&self) -> Result<(), ConfigError> {
+        if let Some(directory) = self.path.parent() {
+            if let Err(msg) = fs::create_dir_all(directory) {
+                return Err(ConfigError::Unwritable {
+                    path: self.path.clone(),
+                    reason: msg.to_string(),
+                });
+            }
+        }
+
+        match fs::write(&self.path, self.to_toml()) {
+            Ok(()) => {
+                info!("Configuration written to {:?}", self.path);
+                Ok(())
+            }
+            Err(msg) => Err(ConfigError::Unwritable {
+                path: self.path.clone(),
+                reason: msg.to_string(),
+            }),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut contents = String::new();
+        let mut section = "";
+
+        for setting in &SETTINGS {
+            // Every setting is named section.key.
+            let (setting_section, key) = setting.name.split_once('.').unwrap_or(("", setting.name));
+            if setting_section != section {
+                if !section.is_empty() {
+                    contents.push('\n');
+                }
+                contents.push_str(&format!("[{}]\n", setting_section));
+                section = setting_section;
+            }
+            contents.push_str(&format!("# {}\n", setting.expected));
+            contents.push_str(&format!("{} = {}\n", key, (setting.get)(self)));
+        }
+
+        contents
+    }
+}
+
+impl AssetConfig {
+    // Where the asset called name is, e.g. path("Doom_status_bar.bmp").
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(name)
+    }
+}
+
+// Where a setting's value came from, for error messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    File { path: PathBuf, line: usize },
+    Environment(&'static str),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "the default"),
+            Origin::File { path, line } => write!(f, "{}, line {}", path.display(), line),
+            Origin::Environment(var) => write!(f, "${}", var),
+            Origin::Flag(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Unreadable {
+        path: PathBuf,
+        reason: String,
+    },
+    Unwritable {
+        path: PathBuf,
+        reason: String,
+    },
+    // A line of the file that is not a [section] or a key = value.
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        reason: &'static str,
+    },
+    UnknownSetting {
+        origin: Origin,
+        name: String,
+    },
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue {
+        origin: Origin,
+        setting: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Unreadable { path, reason } => {
+                write!(f, "cannot read {}: {}", path.display(), reason)
+            }
+            ConfigError::Unwritable { path, reason } => {
+                write!(f, "cannot write {}: {}", path.display(), reason)
+            }
+            ConfigError::Syntax { path, line, reason } => {
+                write!(f, "{}, line {}: {}", path.display(), line, reason)
+            }
+            ConfigError::UnknownSetting { origin, name } => write!(
+                f,
+                "{}: there is no setting called {}; the settings are {}",
+                origin,
+                name,
+                SETTINGS
+                    .iter()
+                    .map(|setting| setting.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ConfigError::UnknownFlag(flag) => {
+                write!(f, "unknown option {}\n\n{}", flag, usage())
+            }
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::InvalidValue {
+                origin,
+                setting,
+                value,
+                reason,
+            } => write!(
+                f,
+                "{}: {:?} will not do for {}: {}",
+                origin, value, setting, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// One setting, under the names it goes by in the file, the environment and on the command line.
+struct Setting {
+    name: &'static str,
+    env_var: &'static str,
+    flag: &'static str,
+    // What the setting takes, for the file's comments and for errors.
+    expected: &'static str,
+    // None if value does not parse.
+    set: fn(&mut Config, &str) -> Option<()>,
+    // The value as it is written to the file.
+    get: fn(&Config) -> String,
+}
+
+static SETTINGS: [Setting; 7] = [
+    Setting {
+        name: "window.monitor",
+        env_var: "DUST_MONITOR",
+        flag: "--monitor",
+        expected: "a RandR monitor name such as \"DP-1\", or \"primary\"",
+        set: set_monitor,
+        get: get_monitor,
+    },
+    Setting {
+        name: "window.resolution",
+        env_var: "DUST_RESOLUTION",
+        flag: "--resolution",
+        expected: "WIDTHxHEIGHT such as \"1920x1080\", or \"fullscreen\"",
+        set: set_resolution,
+        get: get_resolution,
+    },
+    Setting {
+        name: "graphics.device",
+        env_var: device_selection::DEVICE_ENV_VAR,
+        flag: "--device",
+        expected: "a device index, part of a device name, or \"auto\"",
+        set: set_device,
+        get: get_device,
+    },
+    Setting {
+        name: "graphics.present_mode",
+        env_var: "DUST_PRESENT_MODE",
+        flag: "--present-mode",
+        expected: "\"vsync\", \"adaptive-vsync\", \"mailbox\" or \"immediate\"",
+        set: set_present_mode,
+        get: get_present_mode,
+    },
+    Setting {
+        name: "graphics.frame_cap",
+        env_var: "DUST_FRAME_CAP",
+        flag: "--frame-cap",
+        expected: "the most frames a second, or 0 for no cap",
+        set: set_frame_cap,
+        get: get_frame_cap,
+    },
+    Setting {
+        name: "graphics.shader_dir",
+        env_var: "DUST_SHADER_DIR",
+        flag: "--shader-dir",
+        expected: "a directory of compiled shaders, or \"\" for the one beside the executable",
+        set: set_shader_dir,
+        get: get_shader_dir,
+    },
+    Setting {
+        name: "assets.directory",
+        env_var: "DUST_ASSETS",
+        flag: "--assets",
+        expected: "the directory the game's images are loaded from",
+        set: set_asset_directory,
+        get: get_asset_directory,
+    },
+];
+
+fn set_monitor(config: &mut Config, value: &str) -> Option<()> {
+    config.window.monitor = match value {
+        "" | "primary" => None,
+        name => Some(String::from(name)),
+    };
+    Some(())
+}
+
+fn get_monitor(config: &Config) -> String {
+    quote(config.window.monitor.as_deref().unwrap_or("primary"))
+}
+
+fn set_resolution(config: &mut Config, value: &str) -> Option<()> {
+    config.window.resolution = if value == "fullscreen" {
+        None
+    } else {
+        let (width, height) = value.split_once('x')?;
+        let width = width.trim().parse::<u32>().ok()?;
+        let height = height.trim().parse::<u32>().ok()?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some((width, height))
+    };
+    Some(())
+}
+
+fn get_resolution(config: &Config) -> String {
+    match config.window.resolution {
+        Some((width, height)) => quote(&format!("{}x{}", width, height)),
+        None => quote("fullscreen"),
+    }
+}
+
+fn set_device(config: &mut Config, value: &str) -> Option<()> {
+    config.graphics.device = match value {
+        "auto" => None,
+        choice => DeviceOverride::parse(choice),
+    };
+    Some(())
+}
+
+fn get_device(config: &Config) -> String {
+    match &config.graphics.device {
+        Some(DeviceOverride::Index(index)) => quote(&index.to_string()),
+        Some(DeviceOverride::Name(name)) => quote(name),
+        None => quote("auto"),
+    }
+}
+
+fn set_present_mode(config: &mut Config, value: &str) -> Option<()> {
+    config.graphics.present_mode = PresentMode::parse(value)?;
+    Some(())
+}
+
+fn get_present_mode(config: &Config) -> String {
+    quote(config.graphics.present_mode.name())
+}
+
+fn set_frame_cap(config: &mut Config, value: &str) -> Option<()> {
+    config.graphics.frame_cap = value.parse::<u32>().ok()?;
+    Some(())
+}
+
+fn get_frame_cap(config: &Config) -> String {
+    config.graphics.frame_cap.to_string()
+}
+
+fn set_shader_dir(config: &mut Config, value: &str) -> Option<()> {
+    config.graphics.shader_dir = match value {
+        "" => None,
+        directory => Some(PathBuf::from(directory)),
+    };
+    Some(())
+}
+
+fn get_shader_dir(config: &Config) -> String {
+    match &config.graphics.shader_dir {
+        Some(directory) => quote(&directory.to_string_lossy()),
+        None => quote(""),
+    }
+}
+
+fn set_asset_directory(config: &mut Config, value: &str) -> Option<()> {
+    if value.is_empty() {
+        return None;
+    }
+    config.assets.directory = PathBuf::from(value);
+    Some(())
+}
+
+fn get_asset_directory(config: &Config) -> String {
+    quote(&config.assets.directory.to_string_lossy())
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// *** default_path() -> PathBuf
+//
+// $XDG_CONFIG_HOME/dust/dust.toml, falling back to ~/.config when XDG_CONFIG_HOME is unset or, as
+// the spec says to treat it, not absolute.
+//
+pub fn default_path() -> PathBuf {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(directory) if Path::new(&directory).is_absolute() => PathBuf::from(directory),
+        _ => match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".config"),
+            None => PathBuf::from("."),
+        },
+    };
+
+    config_home.join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME)
+}
+
+// The options load() understands, one to a line.
+pub fn usage() -> String {
+    let mut lines = vec![
+        format!(
+            "  {:<24}read PATH instead of {}",
+            "--config PATH",
+            default_path().display()
+        ),
+        format!(
+            "  {:<24}write the configuration back to that file",
+            "--save-config"
+        ),
+    ];
+    for setting in &SETTINGS {
+        lines.push(format!(
+            "  {:<24}{}, or ${}: {}",
+            format!("{} VALUE", setting.flag),
+            setting.name,
+            setting.env_var,
+            setting.expected
+        ));
+    }
+
+    lines.join("\n")
+}
+
+// What the command line asked for.
+struct Args {
+    config_path: Option<PathBuf>,
+    save: bool,
+    values: Vec<(&'static Setting, String)>,
+}
+
+// *** load(args: &[String]) -> Result<Config, ConfigError>
+//
+// Builds the configuration from the defaults, the configuration file, DUST_* environment variables
+// and args - the command line less the program name - each overriding the last.  The file is
+// default_path(), unless $DUST_CONFIG or --config names another; only the default one may be
+// missing.  With --save-config the result is written back to the file once it has been checked.
+//
+pub fn load(args: &[String]) -> Result<Config, ConfigError> {
+    load_with(args, |var| env::var(var).ok())
+}
+
+// load(), reading the environment through var.
+fn load_with(args: &[String], var: impl Fn(&str) -> Option<String>) -> Result<Config, ConfigError> {
+    let args = parse_args(args)?;
+    let named_path = match args.config_path {
+        Some(path) => Some(path),
+        None => var(CONFIG_ENV_VAR).map(PathBuf::from),
+    };
+
+    let path = named_path.clone().unwrap_or_else(default_path);
+    let mut config = Config::new(path.clone());
+    let mut origins = HashMap::<&'static str, Origin>::new();
+
+    if named_path.is_some() || path.exists() {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(msg) => {
+                return Err(ConfigError::Unreadable {
+                    path,
+                    reason: msg.to_string(),
+                });
+            }
+        };
+        for (name, value, line) in parse_file(&path, &contents)? {
+            let origin = Origin::File {
+                path: path.clone(),
+                line,
+            };
+            match SETTINGS.iter().find(|setting| setting.name == name) {
+                Some(setting) => apply(&mut config, setting, &value, origin, &mut origins)?,
+                None => return Err(ConfigError::UnknownSetting { origin, name }),
+            }
+        }
+        debug!("Configuration read from {:?}", path);
+    } else {
+        debug!("No configuration file at {:?}; using the defaults.", path);
+    }
+
+    for setting in &SETTINGS {
+        if let Some(value) = var(setting.env_var) {
+            let origin = Origin::Environment(setting.env_var);
+            apply(&mut config, setting, &value, origin, &mut origins)?;
+        }
+    }
+
+    for (setting, value) in args.values {
+        let origin = Origin::Flag(setting.flag);
+        apply(&mut config, setting, &value, origin, &mut origins)?;
+    }
+
+    validate(&config, &origins)?;
+
+    if args.save {
+        config.save()?;
+    }
+
+    Ok(config)
+}
+
+fn apply(
+    config: &mut Config,
+    setting: &'static Setting,
+    value: &str,
+    origin: Origin,
+    origins: &mut HashMap<&'static str, Origin>,
+) -> Result<(), ConfigError> {
+    match (setting.set)(config, value.trim()) {
+        Some(()) => {
+            origins.insert(setting.name, origin);
+            Ok(())
+        }
+        None => Err(ConfigError::InvalidValue {
+            origin,
+            setting: setting.name,
+            value: String::from(value),
+            reason: format!("expected {}", setting.expected),
+        }),
+    }
+}
+
+// The checks that need every source in first: the directories named have to exist.
+fn validate(config: &Config, origins: &HashMap<&'static str, Origin>) -> Result<(), ConfigError> {
+    let mut directories = vec![("assets.directory", &config.assets.directory)];
+    if let Some(shader_dir) = &config.graphics.shader_dir {
+        directories.push(("graphics.shader_dir", shader_dir));
+    }
+
+    for (setting, directory) in directories {
+        if !directory.is_dir() {
+            return Err(ConfigError::InvalidValue {
+                origin: origins.get(setting).cloned().unwrap_or(Origin::Default),
+                setting,
+                value: directory.to_string_lossy().into_owned(),
+                reason: String::from("there is no such directory"),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Args, ConfigError> {
+    let mut parsed = Args {
+        config_path: None,
+        save: false,
+        values: Vec::new(),
+    };
+
+    let mut remaining = args.iter();
+    while let Some(arg) = remaining.next() {
+        // Both --flag value and --flag=value.
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value)),
+            None => (arg.as_str(), None),
+        };
+
+        if flag == "--save-config" && inline_value.is_none() {
+            parsed.save = true;
+        } else if flag == "--config" {
+            let value = flag_value(flag, inline_value, &mut remaining)?;
+            parsed.config_path = Some(PathBuf::from(value));
+        } else {
+            match SETTINGS.iter().find(|setting| setting.flag == flag) {
+                Some(setting) => {
+                    let value = flag_value(flag, inline_value, &mut remaining)?;
+                    parsed.values.push((setting, value));
+                }
+                None => return Err(ConfigError::UnknownFlag(arg.clone())),
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn flag_value(
+    flag: &str,
+    inline_value: Option<&str>,
+    remaining: &mut Iter<String>,
+) -> Result<String, ConfigError> {
+    match inline_value {
+        Some(value) => Ok(String::from(value)),
+        None => match remaining.next() {
+            Some(value) => Ok(value.clone()),
+            None => Err(ConfigError::MissingValue(String::from(flag))),
+        },
+    }
+}
+
+// *** parse_file(path: &Path, contents: &str) -> Result<Vec<(String, String, usize)>, ConfigError>
+//
+// The handful of TOML the configuration needs: [section] headers, and key = value pairs whose
+// value is a basic string or a bare word such as 144 or true.  Returns section.key, the value
+// with any quotes taken off, and its line number.
+//
+fn parse_file(path: &Path, contents: &str) -> Result<Vec<(String, String, usize)>, ConfigError> {
+    let mut entries = Vec::new();
+    let mut section = String::new();
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line = index + 1;
+        let syntax_error = |reason| ConfigError::Syntax {
+            path: PathBuf::from(path),
+            line,
+            reason,
+        };
+
+        let text = raw_line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        if let Some(header) = text.strip_prefix('[') {
+            match header.split_once(']') {
+                Some((name, rest)) if is_comment(rest) && !name.trim().is_empty() => {
+                    section = String::from(name.trim());
+                }
+                _ => return Err(syntax_error("a section header looks like [name]")),
+            }
+            continue;
+        }
+
+        let (key, value) = match text.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+            _ => return Err(syntax_error("expected key = value")),
+        };
+
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => match unquote(quoted) {
+                Ok((value, rest)) if is_comment(rest) => value,
+                Ok(_) => return Err(syntax_error("only a comment may follow a string")),
+                Err(reason) => return Err(syntax_error(reason)),
+            },
+            None => {
+                let bare = value.split('#').next().unwrap_or("").trim();
+                if bare.is_empty() || bare.contains(char::is_whitespace) {
+                    return Err(syntax_error(
+                        "values are \"quoted strings\" or single words such as 144",
+                    ));
+                }
+                String::from(bare)
+            }
+        };
+
+        let name = if section.is_empty() {
+            String::from(key)
+        } else {
+            format!("{}.{}", section, key)
+        };
+        entries.push((name, value, line));
+    }
+
+    Ok(entries)
+}
+
+// Whether what follows a value is nothing or only a comment.
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+// Reads a basic string up to its closing quote, returning it unescaped along with whatever follows,
+// or what is wrong with it.
+fn unquote(quoted: &str) -> Result<(String, &str), &'static str> {
+    const UNTERMINATED: &str = "unterminated string";
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &quoted[index + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some(_) => return Err("unknown escape; a string may use \\\", \\\\, \\n and \\t"),
+                None => return Err(UNTERMINATED),
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err(UNTERMINATED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Vec<(String, String, usize)>, ConfigError> {
+        parse_file(Path::new("dust.toml"), contents)
+    }
+
+    fn syntax_reason(contents: &str) -> &'static str {
+        match parse(contents) {
+            Err(ConfigError::Syntax { reason, .. }) => reason,
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    // A directory of its own under the system one, removed again on drop.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let directory =
+                env::temp_dir().join(format!("dust-config-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&directory).unwrap();
+            Scratch(directory)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn parses_sections_strings_and_bare_words() {
+        let entries = parse(
+            "# dust\n\
+             top = 1\n\
+             \n\
+             [window]\n\
+             monitor = \"DP-1\"  # the left one\n\
+             [ graphics ] # comment\n\
+             frame_cap = 144 # a cap\n\
+             shader_dir = \"a \\\"b\\\" \\\\c\\n\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                (String::from("top"), String::from("1"), 2),
+                (String::from("window.monitor"), String::from("DP-1"), 5),
+                (String::from("graphics.frame_cap"), String::from("144"), 7),
+                (
+                    String::from("graphics.shader_dir"),
+                    String::from("a \"b\" \\c\n"),
+                    8
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_a_syntax_error() {
+        match parse("[window]\nmonitor\n") {
+            Err(ConfigError::Syntax { line, reason, .. }) => {
+                assert_eq!(line, 2);
+                assert_eq!(reason, "expected key = value");
+            }
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn refuses_malformed_lines() {
+        assert_eq!(
+            syntax_reason("[window\n"),
+            "a section header looks like [name]"
+        );
+        assert_eq!(syntax_reason("[]\n"), "a section header looks like [name]");
+        assert_eq!(syntax_reason(" = 1\n"), "expected key = value");
+        assert_eq!(
+            syntax_reason("monitor = DP 1\n"),
+            "values are \"quoted strings\" or single words such as 144"
+        );
+        assert_eq!(
+            syntax_reason("monitor = \"DP-1\" trailing\n"),
+            "only a comment may follow a string"
+        );
+    }
+
+    #[test]
+    fn tells_a_bad_escape_from_an_unterminated_string() {
+        assert_eq!(syntax_reason("monitor = \"DP-1\n"), "unterminated string");
+        assert_eq!(syntax_reason("monitor = \"DP-1\\\n"), "unterminated string");
+        assert_eq!(
+            syntax_reason("monitor = \"C:\\dust\"\n"),
+            "unknown escape; a string may use \\\", \\\\, \\n and \\t"
+        );
+    }
+
+    #[test]
+    fn every_setting_is_in_a_section() {
+        for setting in &SETTINGS {
+            assert!(
+                setting.name.contains('.'),
+                "{} has no section",
+                setting.name
+            );
+        }
+    }
+
+    #[test]
+    fn written_configuration_reads_back_the_same() {
+        let mut config = Config::new(PathBuf::from("dust.toml"));
+        config.window.monitor = Some(String::from("HDMI \"A\" \\ 1"));
+        config.window.resolution = Some((1920, 1080));
+        config.graphics.device = Some(DeviceOverride::Name(String::from("radeon")));
+        config.graphics.present_mode = PresentMode::Immediate;
+        config.graphics.frame_cap = 144;
+        config.graphics.shader_dir = Some(PathBuf::from("/opt/dust/shaders"));
+        config.assets.directory = PathBuf::from("wads");
+
+        let mut read = Config::new(PathBuf::from("dust.toml"));
+        for (name, value, _) in parse(&config.to_toml()).unwrap() {
+            let setting = SETTINGS
+                .iter()
+                .find(|setting| setting.name == name)
+                .unwrap();
+            assert_eq!((setting.set)(&mut read, &value), Some(()), "{}", name);
+        }
+
+        assert_eq!(read, config);
+    }
+
+    #[test]
+    fn the_command_line_beats_the_environment_which_beats_the_file() {
+        let scratch = Scratch::new("precedence");
+        let path = scratch.0.join("dust.toml");
+        fs::write(
+            &path,
+            format!(
+                "[window]\nmonitor = \"DP-1\"\n\
+                 [graphics]\nframe_cap = 60\npresent_mode = \"vsync\"\n\
+                 [assets]\ndirectory = {}\n",
+                quote(&scratch.0.to_string_lossy())
+            ),
+        )
+        .unwrap();
+
+        let environment = HashMap::from([
+            ("DUST_FRAME_CAP", String::from("120")),
+            ("DUST_PRESENT_MODE", String::from("immediate")),
+        ]);
+        let args = [
+            format!("--config={}", path.display()),
+            String::from("--present-mode"),
+            String::from("mailbox"),
+        ];
+
+        let config = load_with(&args, |var| environment.get(var).cloned()).unwrap();
+        assert_eq!(config.path, path);
+        assert_eq!(config.window.monitor.as_deref(), Some("DP-1"));
+        assert_eq!(config.graphics.frame_cap, 120);
+        assert_eq!(config.graphics.present_mode, PresentMode::Mailbox);
+        assert_eq!(config.assets.directory, scratch.0);
+    }
+
+    #[test]
+    fn an_invalid_value_names_where_it_came_from() {
+        let scratch = Scratch::new("origin");
+        let path = scratch.0.join("dust.toml");
+        fs::write(&path, "[graphics]\nframe_cap = lots\n").unwrap();
+
+        let args = [
+            String::from("--config"),
+            path.to_string_lossy().into_owned(),
+        ];
+        match load_with(&args, |_| None) {
+            Err(ConfigError::InvalidValue {
+                origin, setting, ..
+            }) => {
+                assert_eq!(origin, Origin::File { path, line: 2 });
+                assert_eq!(setting, "graphics.frame_cap");
+            }
+            other => panic!("expected an invalid value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_flag_needs_its_value() {
+        let args = [String::from("--frame-cap")];
+        assert!(matches!(
+            parse_args(&args),
+            Err(ConfigError::MissingValue(flag)) if flag == "--frame-cap"
+        ));
+
+        let args = [String::from("--frame-rate=60")];
+        assert!(matches!(
+            parse_args(&args),
+            Err(ConfigError::UnknownFlag(_))
+        ));
+    }
+}
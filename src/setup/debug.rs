@@ -0,0 +1,259 @@
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    sync::{Mutex, OnceLock},
+};
+
+use ash::{
+    vk::{
+        self, CommandBuffer, DebugUtilsLabelEXT, DebugUtilsMessageSeverityFlagsEXT,
+        DebugUtilsMessageTypeFlagsEXT, DebugUtilsMessengerCallbackDataEXT,
+        DebugUtilsMessengerCreateInfoEXT, DebugUtilsMessengerEXT, DebugUtilsObjectNameInfoEXT,
+        Handle,
+    },
+    Device, Entry, Instance,
+};
+use log::{debug, error, info, trace, warn};
+
+use crate::dust_errors::DustError;
+
+// Debugging is off unless the vulkan-validation feature is built in, or DUST_VALIDATION is set to
+// anything but 0 in the environment.  Either way it needs the Khronos validation layer installed;
+// without it the engine carries on, undebugged, with a warning.
+pub const VALIDATION_ENV_VAR: &str = "DUST_VALIDATION";
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+static DEBUG: OnceLock<Mutex<DebugState>> = OnceLock::new();
+
+struct DebugState {
+    instance_utils: ash::ext::debug_utils::Instance,
+    messenger: DebugUtilsMessengerEXT,
+    device_utils: Option<ash::ext::debug_utils::Device>,
+}
+
+// *** enabled(entry: &Entry) -> bool
+//
+// Whether validation and debug naming are on for this run.  Decided once, on the first call,
+// from the feature, the environment and the layers the loader can find.
+//
+pub fn enabled(entry: &Entry) -> bool {
+    *ENABLED.get_or_init(|| {
+        let requested = cfg!(feature = "vulkan-validation")
+            || std::env::var(VALIDATION_ENV_VAR).is_ok_and(|value| value != "0");
+        if !requested {
+            return false;
+        }
+
+        let layers = match unsafe { entry.enumerate_instance_layer_properties() } {
+            Ok(layers) => layers,
+            Err(msg) => {
+                warn!(
+                    "Unable to list instance layers; validation is off: {:?}",
+                    msg
+                );
+                return false;
+            }
+        };
+        let available = layers.iter().any(|layer| {
+            layer
+                .layer_name_as_c_str()
+                .is_ok_and(|name| name == VALIDATION_LAYER)
+        });
+
+        if !available {
+            warn!(
+                "Validation was requested, but {:?} is not installed; carrying on without it.",
+                VALIDATION_LAYER
+            );
+        }
+        available
+    })
+}
+
+fn is_enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+// The layers and extensions instance creation needs on top of its own when debugging is on.
+pub fn instance_layers(entry: &Entry) -> Vec<*const c_char> {
+    if enabled(entry) {
+        vec![VALIDATION_LAYER.as_ptr()]
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn instance_extensions(entry: &Entry) -> Vec<*const c_char> {
+    if enabled(entry) {
+        vec![vk::EXT_DEBUG_UTILS_NAME.as_ptr()]
+    } else {
+        Vec::new()
+    }
+}
+
+// *** messenger_create_info() -> DebugUtilsMessengerCreateInfoEXT
+//
+// Describes the messenger: warnings and errors always, everything down to verbose if the log
+// level lets it through.  Also chained onto the instance create info, so instance creation and
+// destruction are covered by the callback too.
+//
+pub fn messenger_create_info<'a>() -> DebugUtilsMessengerCreateInfoEXT<'a> {
+    let mut severities =
+        DebugUtilsMessageSeverityFlagsEXT::WARNING | DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    if log::log_enabled!(log::Level::Info) {
+        severities |= DebugUtilsMessageSeverityFlagsEXT::INFO;
+    }
+    if log::log_enabled!(log::Level::Trace) {
+        severities |= DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+    }
+
+    DebugUtilsMessengerCreateInfoEXT::default()
+        .message_severity(severities)
+        .message_type(
+            DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+        )
+        .pfn_user_callback(Some(vulkan_debug_callback))
+}
+
+// *** init(entry: &Entry, instance: &Instance) -> Result<(), DustError>
+//
+// Creates the messenger, once the instance exists.  Does nothing with debugging off.
+//
+pub fn init(entry: &Entry, instance: &Instance) -> Result<(), DustError> {
+    if !enabled(entry) {
+        return Ok(());
+    }
+
+    let instance_utils = ash::ext::debug_utils::Instance::new(entry, instance);
+    let messenger = match unsafe {
+        instance_utils.create_debug_utils_messenger(&messenger_create_info(), None)
+    } {
+        Ok(messenger) => messenger,
+        Err(msg) => {
+            return Err(DustError::vulkan("creating the debug messenger", msg));
+        }
+    };
+
+    let state = DebugState {
+        instance_utils,
+        messenger,
+        device_utils: None,
+    };
+    // Rebuilding after a lost device makes a new instance, and a new messenger with it.
+    match DEBUG.get() {
+        Some(previous) => *previous.lock().unwrap() = state,
+        None => {
+            if DEBUG.set(Mutex::new(state)).is_err() {
+                panic!("Unable to set the debug messenger static.");
+            }
+        }
+    }
+
+    debug!("Validation enabled; Vulkan messages are routed to the log.");
+    Ok(())
+}
+
+// Hooks up object naming and command buffer labels once the logical device exists.
+pub fn init_device(instance: &Instance, device: &Device) {
+    if let Some(state) = DEBUG.get() {
+        state.lock().unwrap().device_utils =
+            Some(ash::ext::debug_utils::Device::new(instance, device));
+    }
+}
+
+// Must run before the instance is destroyed.
+pub fn destroy() {
+    if let Some(state) = DEBUG.get() {
+        let mut state = state.lock().unwrap();
+        state.device_utils = None;
+        unsafe {
+            state
+                .instance_utils
+                .destroy_debug_utils_messenger(state.messenger, None)
+        };
+        state.messenger = DebugUtilsMessengerEXT::null();
+    }
+}
+
+fn with_device_utils(action: impl FnOnce(&ash::ext::debug_utils::Device)) {
+    if !is_enabled() {
+        return;
+    }
+
+    if let Some(state) = DEBUG.get() {
+        if let Some(device_utils) = &state.lock().unwrap().device_utils {
+            action(device_utils);
+        }
+    }
+}
+
+// *** name_object<H: Handle>(handle: H, name: &str)
+//
+// Gives a Vulkan object a name for validation messages and capture tools to show.  Free with
+// debugging off.
+//
+pub fn name_object<H: Handle>(handle: H, name: &str) {
+    with_device_utils(|device_utils| {
+        let name = CString::new(name).unwrap_or_default();
+        let info = DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+
+        if let Err(msg) = unsafe { device_utils.set_debug_utils_object_name(&info) } {
+            warn!("Unable to name {:?}: {:?}", name, msg);
+        }
+    })
+}
+
+// *** begin_label(command_buffer: CommandBuffer, name: &str)
+//
+// Opens a labelled region of command_buffer; every begin needs a matching end_label.  Validation
+// messages about commands inside the region mention it.
+//
+pub fn begin_label(command_buffer: CommandBuffer, name: &str) {
+    with_device_utils(|device_utils| {
+        let name = CString::new(name).unwrap_or_default();
+        let label = DebugUtilsLabelEXT::default().label_name(&name);
+
+        unsafe { device_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+    })
+}
+
+pub fn end_label(command_buffer: CommandBuffer) {
+    with_device_utils(|device_utils| unsafe {
+        device_utils.cmd_end_debug_utils_label(command_buffer)
+    })
+}
+
+unsafe extern "system" fn vulkan_debug_callback(
+    severity: DebugUtilsMessageSeverityFlagsEXT,
+    message_type: DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const DebugUtilsMessengerCallbackDataEXT<'_>,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    if callback_data.is_null() {
+        return vk::FALSE;
+    }
+
+    let data = &*callback_data;
+    let message = data
+        .message_as_c_str()
+        .map(CStr::to_string_lossy)
+        .unwrap_or_default();
+
+    match severity {
+        DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+            error!("[vulkan {:?}] {}", message_type, message)
+        }
+        DebugUtilsMessageSeverityFlagsEXT::WARNING => {
+            warn!("[vulkan {:?}] {}", message_type, message)
+        }
+        DebugUtilsMessageSeverityFlagsEXT::INFO => info!("[vulkan {:?}] {}", message_type, message),
+        _ => trace!("[vulkan {:?}] {}", message_type, message),
+    }
+
+    // Returning true would abort the call that triggered the message; only the layers should.
+    vk::FALSE
+}
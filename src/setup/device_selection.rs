@@ -0,0 +1,481 @@
+use std::sync::{Mutex, OnceLock};
+
+use ash::{
+    vk::{
+        MemoryHeapFlags, PhysicalDevice, PhysicalDeviceType, QueueFamilyProperties, QueueFlags,
+        SurfaceKHR,
+    },
+    Instance,
+};
+use log::{info, warn};
+
+use crate::{
+    dust_errors::DustError,
+    setup::requirements::{self, DeviceRequirements},
+};
+
+// Set to a device index or (part of) a device name to pick that device over the best scoring one,
+// e.g. DUST_DEVICE=llvmpipe to force lavapipe.  Takes precedence over set_override().
+pub const DEVICE_ENV_VAR: &str = "DUST_DEVICE";
+
+static OVERRIDE: OnceLock<Mutex<Option<DeviceOverride>>> = OnceLock::new();
+
+// A user's choice of device: its position in the enumeration order, or a case-insensitive
+// fragment of its name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceOverride {
+    Index(usize),
+    Name(String),
+}
+
+impl DeviceOverride {
+    // All digits is an index; anything else is a name.
+    pub fn parse(value: &str) -> Option<DeviceOverride> {
+        let value = value.trim();
+        if value.is_empty() {
+            None
+        } else if let Ok(index) = value.parse::<usize>() {
+            Some(DeviceOverride::Index(index))
+        } else {
+            Some(DeviceOverride::Name(value.to_lowercase()))
+        }
+    }
+
+    fn matches(&self, candidate: &Candidate) -> bool {
+        match self {
+            DeviceOverride::Index(index) => candidate.index == *index,
+            DeviceOverride::Name(name) => candidate.name.to_lowercase().contains(name.as_str()),
+        }
+    }
+}
+
+// *** set_override(choice: Option<DeviceOverride>)
+//
+// Records the device the configuration asks for.  Must be called before the Vulkan context is
+// created to have any effect; the environment variable still wins over it.
+//
+pub fn set_override(choice: Option<DeviceOverride>) {
+    *OVERRIDE.get_or_init(|| Mutex::new(None)).lock().unwrap() = choice;
+}
+
+fn requested_override() -> Option<DeviceOverride> {
+    if let Ok(value) = std::env::var(DEVICE_ENV_VAR) {
+        if let Some(choice) = DeviceOverride::parse(&value) {
+            return Some(choice);
+        }
+    }
+
+    OVERRIDE
+        .get()
+        .and_then(|choice| choice.lock().unwrap().clone())
+}
+
+// What selection found out about one physical device.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub index: usize,
+    pub physical_device: PhysicalDevice,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub vram: u64,
+    // Why the device cannot be used, if it cannot.
+    pub rejection: Option<String>,
+}
+
+impl Candidate {
+    // Ranked by type first - discrete, integrated, virtual, then software - then by how much
+    // device local memory there is.
+    fn rank(&self) -> (u32, u64) {
+        let type_rank = match self.device_type {
+            PhysicalDeviceType::DISCRETE_GPU => 4,
+            PhysicalDeviceType::INTEGRATED_GPU => 3,
+            PhysicalDeviceType::VIRTUAL_GPU => 2,
+            PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+        (type_rank, self.vram)
+    }
+}
+
+// *** select(instance, surface_instance, surface, requirements) -> Result<PhysicalDevice, DustError>
+//
+// Picks the physical device to run on.  Every device is checked for a graphics queue family that
+// can present to surface, and against requirements; the ones that pass are ranked, and the best
+// taken unless the user asked for a particular one.  The full list of candidates, and why any were
+// turned down, goes to the log.  Fails with NoSuitableDevice if nothing is usable, or if the
+// device the user asked for is not.
+//
+pub fn select(
+    instance: &Instance,
+    surface_instance: &ash::khr::surface::Instance,
+    surface: SurfaceKHR,
+    requirements: &DeviceRequirements,
+) -> Result<PhysicalDevice, DustError> {
+    let physical_devices = match unsafe { instance.enumerate_physical_devices() } {
+        Ok(physical_devices) => physical_devices,
+        Err(msg) => {
+            return Err(DustError::vulkan("listing the physical devices", msg));
+        }
+    };
+
+    if physical_devices.is_empty() {
+        return Err(DustError::NoSuitableDevice(String::from(
+            "no Vulkan compatible physical devices were found",
+        )));
+    }
+
+    let candidates: Vec<Candidate> = physical_devices
+        .iter()
+        .enumerate()
+        .map(|(index, physical_device)| {
+            evaluate(
+                instance,
+                surface_instance,
+                surface,
+                requirements,
+                index,
+                *physical_device,
+            )
+        })
+        .collect();
+
+    info!("Vulkan devices:");
+    for candidate in &candidates {
+        match &candidate.rejection {
+            None => info!(
+                "  [{}] {} ({:?}, {} MiB device local)",
+                candidate.index,
+                candidate.name,
+                candidate.device_type,
+                candidate.vram / (1024 * 1024)
+            ),
+            Some(reason) => info!(
+                "  [{}] {} ({:?}) - unusable: {}",
+                candidate.index, candidate.name, candidate.device_type, reason
+            ),
+        }
+    }
+
+    let chosen = choose(&candidates, requested_override())?;
+    Ok(chosen.physical_device)
+}
+
+// The candidate the user asked for if it matches one, or else the best ranked usable one.
+fn choose(
+    candidates: &[Candidate],
+    choice: Option<DeviceOverride>,
+) -> Result<&Candidate, DustError> {
+    if let Some(choice) = choice {
+        match candidates
+            .iter()
+            .find(|candidate| choice.matches(candidate))
+        {
+            Some(candidate) => {
+                if let Some(reason) = &candidate.rejection {
+                    return Err(DustError::NoSuitableDevice(format!(
+                        "the requested device {} cannot be used: {}",
+                        candidate.name, reason
+                    )));
+                }
+                info!("Using {} as requested", candidate.name);
+                return Ok(candidate);
+            }
+            None => {
+                warn!(
+                    "No device matches the requested {:?}; choosing one instead.",
+                    choice
+                );
+            }
+        }
+    }
+
+    match candidates
+        .iter()
+        .filter(|candidate| candidate.rejection.is_none())
+        .max_by_key(|candidate| candidate.rank())
+    {
+        Some(best) => {
+            info!("Using {}", best.name);
+            Ok(best)
+        }
+        None => Err(DustError::NoSuitableDevice(String::from(
+            "none of the Vulkan devices can run Dust; see the device list in the log",
+        ))),
+    }
+}
+
+fn evaluate(
+    instance: &Instance,
+    surface_instance: &ash::khr::surface::Instance,
+    surface: SurfaceKHR,
+    requirements: &DeviceRequirements,
+    index: usize,
+    physical_device: PhysicalDevice,
+) -> Candidate {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+    let name = properties
+        .device_name_as_c_str()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| String::from("<unnamed device>"));
+
+    let vram = memory.memory_heaps[..memory.memory_heap_count as usize]
+        .iter()
+        .filter(|heap| heap.flags.contains(MemoryHeapFlags::DEVICE_LOCAL))
+        .map(|heap| heap.size)
+        .sum();
+
+    Candidate {
+        index,
+        physical_device,
+        name,
+        device_type: properties.device_type,
+        vram,
+        rejection: find_rejection(
+            instance,
+            surface_instance,
+            surface,
+            requirements,
+            physical_device,
+        )
+        .err(),
+    }
+}
+
+// *** presenting_graphics_family(instance, surface_instance, surface, physical_device) -> Option<u32>
+//
+// The first queue family on physical_device that can both draw and present to surface: the one
+// select() insists on, and the one the context does all its drawing and presenting on.
+//
+pub fn presenting_graphics_family(
+    instance: &Instance,
+    surface_instance: &ash::khr::surface::Instance,
+    surface: SurfaceKHR,
+    physical_device: PhysicalDevice,
+) -> Option<u32> {
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+    first_graphics_family(&queue_families, |family_index| unsafe {
+        surface_instance
+            .get_physical_device_surface_support(physical_device, family_index, surface)
+            .unwrap_or(false)
+    })
+}
+
+fn first_graphics_family(
+    queue_families: &[QueueFamilyProperties],
+    presents: impl Fn(u32) -> bool,
+) -> Option<u32> {
+    queue_families
+        .iter()
+        .enumerate()
+        .filter(|(_, family)| family.queue_flags.contains(QueueFlags::GRAPHICS))
+        .map(|(family_index, _)| family_index as u32)
+        .find(|family_index| presents(*family_index))
+}
+
+// *** transfer_family(queue_families: &[QueueFamilyProperties], graphics_family: u32) -> u32
+//
+// A queue family given over to transfers, so that uploads can run alongside drawing.  Plenty of
+// devices - lavapipe, most integrated GPUs - have none; uploads then go through graphics_family,
+// which can always transfer.
+//
+pub fn transfer_family(queue_families: &[QueueFamilyProperties], graphics_family: u32) -> u32 {
+    queue_families
+        .iter()
+        .position(|family| {
+            family.queue_flags.contains(QueueFlags::TRANSFER)
+                && !family.queue_flags.contains(QueueFlags::GRAPHICS)
+        })
+        .map(|family_index| family_index as u32)
+        .unwrap_or(graphics_family)
+}
+
+fn find_rejection(
+    instance: &Instance,
+    surface_instance: &ash::khr::surface::Instance,
+    surface: SurfaceKHR,
+    requirements: &DeviceRequirements,
+    physical_device: PhysicalDevice,
+) -> Result<(), String> {
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    if !queue_families
+        .iter()
+        .any(|family| family.queue_flags.contains(QueueFlags::GRAPHICS))
+    {
+        return Err(String::from("no graphics queue family"));
+    }
+
+    if presenting_graphics_family(instance, surface_instance, surface, physical_device).is_none() {
+        return Err(String::from(
+            "no graphics queue family can present to the window",
+        ));
+    }
+
+    match requirements::negotiate(instance, physical_device, requirements) {
+        Ok(_) => {}
+        Err(DustError::MissingRequirements(missing)) => {
+            return Err(format!("missing {}", missing.join(", ")));
+        }
+        Err(msg) => {
+            return Err(format!("{:?}", msg));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn candidate(
+        index: usize,
+        name: &str,
+        device_type: PhysicalDeviceType,
+        vram: u64,
+    ) -> Candidate {
+        Candidate {
+            index,
+            physical_device: PhysicalDevice::from_raw(index as u64 + 1),
+            name: String::from(name),
+            device_type,
+            vram,
+            rejection: None,
+        }
+    }
+
+    fn family(queue_flags: QueueFlags) -> QueueFamilyProperties {
+        QueueFamilyProperties::default()
+            .queue_flags(queue_flags)
+            .queue_count(1)
+    }
+
+    fn machine() -> Vec<Candidate> {
+        vec![
+            candidate(
+                0,
+                "llvmpipe (LLVM 17.0.6, 256 bits)",
+                PhysicalDeviceType::CPU,
+                0,
+            ),
+            candidate(
+                1,
+                "Intel(R) UHD Graphics 630",
+                PhysicalDeviceType::INTEGRATED_GPU,
+                GIB,
+            ),
+            candidate(
+                2,
+                "AMD Radeon RX 6600",
+                PhysicalDeviceType::DISCRETE_GPU,
+                8 * GIB,
+            ),
+        ]
+    }
+
+    #[test]
+    fn parses_an_index_or_a_name() {
+        assert_eq!(DeviceOverride::parse(" 2 "), Some(DeviceOverride::Index(2)));
+        assert_eq!(
+            DeviceOverride::parse("LLVMpipe"),
+            Some(DeviceOverride::Name(String::from("llvmpipe")))
+        );
+        assert_eq!(DeviceOverride::parse("  "), None);
+    }
+
+    #[test]
+    fn a_name_matches_part_of_a_device_name_in_any_case() {
+        let radeon = &machine()[2];
+        assert!(DeviceOverride::parse("radeon").unwrap().matches(radeon));
+        assert!(DeviceOverride::parse("RX 66").unwrap().matches(radeon));
+        assert!(!DeviceOverride::parse("geforce").unwrap().matches(radeon));
+        assert!(DeviceOverride::Index(2).matches(radeon));
+        assert!(!DeviceOverride::Index(1).matches(radeon));
+    }
+
+    #[test]
+    fn ranks_by_type_before_memory() {
+        let software = candidate(0, "cpu", PhysicalDeviceType::CPU, 64 * GIB);
+        let integrated = candidate(1, "igpu", PhysicalDeviceType::INTEGRATED_GPU, GIB);
+        let small = candidate(2, "small", PhysicalDeviceType::DISCRETE_GPU, 2 * GIB);
+        let large = candidate(3, "large", PhysicalDeviceType::DISCRETE_GPU, 8 * GIB);
+
+        assert!(integrated.rank() > software.rank());
+        assert!(small.rank() > integrated.rank());
+        assert!(large.rank() > small.rank());
+    }
+
+    #[test]
+    fn chooses_the_best_usable_device() {
+        let mut candidates = machine();
+        assert_eq!(choose(&candidates, None).unwrap().index, 2);
+
+        candidates[2].rejection = Some(String::from("no graphics queue family"));
+        assert_eq!(choose(&candidates, None).unwrap().index, 1);
+
+        for candidate in &mut candidates {
+            candidate.rejection = Some(String::from("missing feature Synchronization2"));
+        }
+        assert!(matches!(
+            choose(&candidates, None),
+            Err(DustError::NoSuitableDevice(_))
+        ));
+    }
+
+    #[test]
+    fn the_requested_device_wins_unless_it_is_unusable() {
+        let mut candidates = machine();
+        let choice = DeviceOverride::parse("llvmpipe");
+        assert_eq!(choose(&candidates, choice.clone()).unwrap().index, 0);
+
+        candidates[0].rejection = Some(String::from("missing feature Synchronization2"));
+        assert!(matches!(
+            choose(&candidates, choice),
+            Err(DustError::NoSuitableDevice(_))
+        ));
+    }
+
+    #[test]
+    fn an_override_matching_nothing_falls_back_to_the_best() {
+        let choice = DeviceOverride::parse("7");
+        assert_eq!(choose(&machine(), choice).unwrap().index, 2);
+    }
+
+    #[test]
+    fn the_graphics_family_has_to_present() {
+        let families = [
+            family(QueueFlags::TRANSFER),
+            family(QueueFlags::GRAPHICS | QueueFlags::COMPUTE),
+            family(QueueFlags::GRAPHICS | QueueFlags::TRANSFER),
+        ];
+        assert_eq!(first_graphics_family(&families, |_| true), Some(1));
+        assert_eq!(
+            first_graphics_family(&families, |index| index != 1),
+            Some(2)
+        );
+        assert_eq!(first_graphics_family(&families, |index| index == 0), None);
+    }
+
+    #[test]
+    fn transfers_go_to_a_dedicated_family_when_there_is_one() {
+        let dedicated = [
+            family(QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER),
+            family(QueueFlags::COMPUTE | QueueFlags::TRANSFER),
+            family(QueueFlags::TRANSFER),
+        ];
+        assert_eq!(transfer_family(&dedicated, 0), 1);
+
+        let shared = [family(
+            QueueFlags::GRAPHICS | QueueFlags::COMPUTE | QueueFlags::TRANSFER,
+        )];
+        assert_eq!(transfer_family(&shared, 0), 0);
+    }
+}
@@ -1,16 +1,13 @@
 use ash::vk::{
     ApplicationInfo,
-    CommandBuffer,
-    CommandBufferAllocateInfo,
-    CommandBufferLevel,
     CommandPool,
     CommandPoolCreateFlags,
     CommandPoolCreateInfo,
     ComponentSwizzle,
-    CompositeAlphaFlagsKHR,
     DeviceCreateInfo,
     DeviceQueueCreateInfo,
     Format,
+    FormatFeatureFlags,
     Image,
     ImageAspectFlags,
     ImageUsageFlags,
@@ -21,47 +18,47 @@ use ash::vk::{
     MemoryPropertyFlags,
     PhysicalDevice,
     PhysicalDeviceMemoryProperties,
-    PhysicalDeviceProperties,
-    PhysicalDeviceSynchronization2Features,
-    PhysicalDeviceType,
     PresentModeKHR,
     Queue,
     QueueFamilyProperties,
-    QueueFlags,
+    SampleCountFlags,
+    Semaphore,
     SharingMode,
     SurfaceCapabilitiesKHR,
     SurfaceFormatKHR,
     SurfaceKHR,
-    SurfaceTransformFlagsKHR,
     SwapchainCreateFlagsKHR,
     SwapchainCreateInfoKHR,
     SwapchainKHR,
     XcbSurfaceCreateInfoKHR, // QUEUE_FAMILY_EXTERNAL,
 };
 use ash::{Device, Entry, Instance};
-use core::panic;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 use std::ffi::{c_void, CStr, CString};
+use std::path::PathBuf;
 use std::sync::Arc;
 use xcb::ffi::xcb_connection_t;
 use xcb::x::Window;
 use xcb::Xid;
 
 use crate::dust_errors::DustError;
+use crate::graphics::swapchain::{self, PresentMode, SwapchainConfig, SwapchainSettings};
+use crate::graphics::{frame_limiter, shaders};
+use crate::setup::config::GraphicsConfig;
+use crate::setup::device_selection::DeviceOverride;
+use crate::setup::requirements::DeviceRequirements;
+use crate::setup::{debug, device_selection, requirements};
 
 pub struct VkContext {
+    // Keeps the Vulkan loader open for as long as the instance, and across a recover.
     entry: ash::Entry,
     instance: ash::Instance,
     physical_device: PhysicalDevice,
     pub physical_memory_properties: PhysicalDeviceMemoryProperties,
-    physical_ext_names: Vec<String>,
     // device_queue_create_info: Vec<DeviceQueueCreateInfo<'a>>,
-    pub graphics_queues: Vec<u32>,
-    graphics_counts: Vec<u32>,
-    graphics_priorities: Vec<Vec<f32>>,
-    pub transfer_queues: Vec<u32>,
-    transfer_counts: Vec<u32>,
-    transfer_priorities: Vec<Vec<f32>>,
+    pub graphics_family: u32,
+    // The same as graphics_family on devices without a dedicated transfer family.
+    pub transfer_family: u32,
     // graphics_queue_create_infos: Vec<DeviceQueueCreateInfo<'a>>,
     // transfer_queue_create_infos: Vec<DeviceQueueCreateInfo<'a>>,
     pub logical_device: Arc<Device>,
@@ -70,6 +67,11 @@ pub struct VkContext {
     khr_surface_instance: ash::khr::surface::Instance,
     surface: SurfaceKHR,
     pub surface_capabilities: SurfaceCapabilitiesKHR,
+    // The window the surface was made for, so a lost surface can be made again.
+    xcb_ptr: *mut xcb_connection_t,
+    xcb_window: Window,
+    // Set once the Vulkan objects are gone, by a recovery or by Drop, so they only go once.
+    torn_down: bool,
     // pub surface_formats: SurfaceFormatKHR,
     // presentation_queues: Vec<&'a DeviceQueueCreateInfo<'a>>,
     // pub swapchain_device: ash::khr::swapchain::Device,
@@ -82,126 +84,190 @@ pub struct VkContext {
 }
 
 #[cfg(all(target_os = "linux", not(target_os = "windows")))]
-pub fn default(xcb_ptr: *mut xcb_connection_t, xcb_window: &Window) -> VkContext {
-    use crate::graphics;
+pub fn default(
+    xcb_ptr: *mut xcb_connection_t,
+    xcb_window: &Window,
+) -> Result<VkContext, DustError> {
+    let entry: ash::Entry = init()?;
+    create(entry, xcb_ptr, xcb_window)
+}
+
+// Everything default() does once the loader is open; recover() comes in here with the loader it
+// already has.
+#[cfg(all(target_os = "linux", not(target_os = "windows")))]
+fn create(
+    entry: ash::Entry,
+    xcb_ptr: *mut xcb_connection_t,
+    xcb_window: &Window,
+) -> Result<VkContext, DustError> {
+    let instance: ash::Instance = instance(&entry)?;
+    if let Err(msg) = debug::init(&entry, &instance) {
+        unsafe { instance.destroy_instance(None) };
+        return Err(msg);
+    }
+
+    // The surface comes first, so devices that cannot present to it are never chosen.
+    let xcb_surface_instance: ash::khr::xcb_surface::Instance =
+        ash::khr::xcb_surface::Instance::new(&entry, &instance);
+    let khr_surface_instance: ash::khr::surface::Instance =
+        ash::khr::surface::Instance::new(&entry, &instance);
+    let surface: SurfaceKHR = match xcb_surface(&xcb_surface_instance, xcb_ptr, xcb_window) {
+        Ok(surface) => surface,
+        Err(msg) => {
+            abandon_instance(&instance);
+            return Err(msg);
+        }
+    };
+
+    // Nothing made on the way to a failure is left behind, so a rebuild can simply try again.
+    match build(
+        entry,
+        instance.clone(),
+        khr_surface_instance.clone(),
+        surface,
+        xcb_ptr,
+        *xcb_window,
+    ) {
+        Ok(context) => Ok(context),
+        Err(msg) => {
+            unsafe { khr_surface_instance.destroy_surface(surface, None) };
+            abandon_instance(&instance);
+            Err(msg)
+        }
+    }
+}
 
-    let entry: ash::Entry = init();
-    let instance: ash::Instance = instance(&entry);
+fn abandon_instance(instance: &Instance) {
+    debug::destroy();
+    unsafe { instance.destroy_instance(None) };
+}
 
-    let physical_device: PhysicalDevice = enumerate_physical_devs(&instance);
+#[cfg(all(target_os = "linux", not(target_os = "windows")))]
+fn build(
+    entry: Entry,
+    instance: Instance,
+    khr_surface_instance: ash::khr::surface::Instance,
+    surface: SurfaceKHR,
+    xcb_ptr: *mut xcb_connection_t,
+    xcb_window: Window,
+) -> Result<VkContext, DustError> {
+    use crate::graphics;
+
+    let requirements = requirements::requirements();
+    let physical_device: PhysicalDevice =
+        device_selection::select(&instance, &khr_surface_instance, surface, &requirements)?;
+    let enabled_device = match requirements::negotiate(&instance, physical_device, &requirements) {
+        Ok(enabled_device) => enabled_device,
+        Err(msg) => {
+            error!(
+                "The selected device does not meet the requirements: {}",
+                msg
+            );
+            return Err(msg);
+        }
+    };
     let physical_memory_properties = get_physical_memory_properties(&instance, &physical_device);
-    let physical_ext_names: Vec<String> =
-        find_extensions_supported_by_pdev(&instance, physical_device);
+    let physical_device_properties =
+        unsafe { instance.get_physical_device_properties(physical_device) };
 
     let queue_family_properties =
         unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
 
     show_queue_family_properties(&queue_family_properties);
 
-    let (transfer_queues, transfer_queue_counts, transfer_priorities) = fill_queue_bits(
-        &queue_family_properties,
-        5,
-        &QueueFlags::TRANSFER,
-        &QueueFlags::GRAPHICS,
-    );
+    // Drawing and presenting share one family - the one selection made sure can present - and
+    // uploads get a family of their own when the device has one.
+    let graphics_family = match device_selection::presenting_graphics_family(
+        &instance,
+        &khr_surface_instance,
+        surface,
+        physical_device,
+    ) {
+        Some(graphics_family) => graphics_family,
+        None => {
+            return Err(DustError::NoSuitableDevice(String::from(
+                "the selected device can no longer present to the window",
+            )));
+        }
+    };
+    let transfer_family =
+        device_selection::transfer_family(&queue_family_properties, graphics_family);
 
-    let (graphics_queues, graphics_queue_counts, graphics_priorities) = fill_queue_bits(
-        &queue_family_properties,
-        1,
-        &QueueFlags::GRAPHICS,
-        &QueueFlags::empty(),
+    debug!(
+        "Graphics queue family {}, transfer queue family {}",
+        graphics_family, transfer_family
     );
 
-    debug!("Graphics queue indices: {:?}", graphics_queues);
-    debug!("Graphics queue counts: {:?}", graphics_queue_counts);
-    debug!("Graphics queue priorities: {:?}", graphics_priorities);
-
-    let transfer_queue_create_infos = construct_queue_create_info(
-        &transfer_queues,
-        &transfer_queue_counts,
-        transfer_priorities.as_slice(),
-    );
+    let queue_priorities = [1.0f32];
+    let mut all_queue_create_info = vec![DeviceQueueCreateInfo::default()
+        .queue_family_index(graphics_family)
+        .queue_priorities(&queue_priorities)];
+    if transfer_family != graphics_family {
+        all_queue_create_info.push(
+            DeviceQueueCreateInfo::default()
+                .queue_family_index(transfer_family)
+                .queue_priorities(&queue_priorities),
+        );
+    }
 
-    transfer_queue_create_infos
-        .iter()
-        .for_each(|dq| debug!("Transfer queue priorities: {:?} ", dq.p_queue_priorities));
+    let surface_capabilities: SurfaceCapabilitiesKHR = map_physical_device_to_surface_properties(
+        &khr_surface_instance,
+        &physical_device,
+        &surface,
+    )?;
+    let swapchain_settings = graphics::swapchain::choose_settings(
+        &surface_capabilities,
+        &supported_surface_formats(&khr_surface_instance, physical_device, &surface)?,
+        &supported_present_modes(&khr_surface_instance, &physical_device, &surface)?,
+        extended_color_spaces_enabled(&entry),
+    )?;
 
-    let graphics_queue_create_infos = construct_queue_create_info(
-        &graphics_queues,
-        &graphics_queue_counts,
-        &graphics_priorities,
+    debug!(
+        "Selected color format: {:?}",
+        swapchain_settings.format.format
     );
 
-    graphics_queue_create_infos
-        .iter()
-        .for_each(|dq| debug!("Graphics queue priorities: {:?} ", dq.p_queue_priorities));
-
-    let mut all_queue_create_info = Vec::new();
-    for queue in &transfer_queue_create_infos {
-        all_queue_create_info.push(*queue);
-    }
-    for queue in &graphics_queue_create_infos {
-        all_queue_create_info.push(*queue);
-    }
-
     let logical_device: Arc<Device> = Arc::new(make_logical_device(
         &instance,
         &physical_device,
-        &physical_ext_names,
+        &enabled_device,
         &all_queue_create_info,
-    ));
-
-    let graphics_queue: Queue = get_queue(
-        &logical_device,
-        graphics_queues[0], // graphics_queue_create_infos.first().unwrap(),
-    );
-
-    let transfer_queue: Queue = get_queue(&logical_device, transfer_queues[0]);
-
-    let xcb_surface_instance: ash::khr::xcb_surface::Instance =
-        ash::khr::xcb_surface::Instance::new(&entry, &instance);
-    let khr_surface_instance: ash::khr::surface::Instance =
-        ash::khr::surface::Instance::new(&entry, &instance);
-    let surface: SurfaceKHR = xcb_surface(&xcb_surface_instance, xcb_ptr, xcb_window);
+    )?);
+    requirements::publish(enabled_device);
 
-    let surface_present_mode =
-        select_preferred_presentation_mode(&khr_surface_instance, &physical_device, &surface);
+    debug::init_device(&instance, &logical_device);
 
-    let surface_capabilities: SurfaceCapabilitiesKHR = map_physical_device_to_surface_properties(
-        &khr_surface_instance,
-        &physical_device,
-        &surface,
-    );
-    let surface_formats: SurfaceFormatKHR =
-        find_formats_and_colorspaces(&khr_surface_instance, physical_device, &surface);
-
-    debug!("Selected color format: {:?}", surface_formats.format);
-
-    debug!("Checking queues for presentation-worthiness.");
-    let presentation_queues: Vec<u32> = select_presentation_queues(
-        &physical_device,
-        &surface,
-        &graphics_queues,
-        &khr_surface_instance,
-    );
-    debug!("Input graphics queues: {:?}", graphics_queues);
-    debug!("Presentation worthy queues: {:?}", presentation_queues);
+    // Without a transfer family of its own, the transfer queue is the graphics queue.
+    let graphics_queue: Queue = get_queue(&logical_device, graphics_family);
+    let transfer_queue: Queue = get_queue(&logical_device, transfer_family);
+    debug::name_object(graphics_queue, "graphics_queue");
+    if transfer_family != graphics_family {
+        debug::name_object(transfer_queue, "transfer_queue");
+    }
 
     let swapchain_device: ash::khr::swapchain::Device =
         ash::khr::swapchain::Device::new(&instance, &logical_device);
-    let swapchain: SwapchainKHR = make_swapchain(
+    let PresentationObjects {
+        swapchain,
+        images: swapchain_images,
+        views: swapchain_views,
+        graphics_pool,
+        transfer_pool,
+    } = match presentation_objects(
+        &logical_device,
         &swapchain_device,
         surface,
-        &surface_formats,
-        // &device_queue_create_info,
-        &graphics_queues,
+        &swapchain_settings,
+        graphics_family,
+        transfer_family,
         &surface_capabilities,
-        surface_present_mode,
-    );
-
-    let swapchain_images: Vec<Image> = swapchain_images(&swapchain_device, swapchain);
-    let swapchain_views: Vec<ImageView> =
-        image_views(&logical_device, &swapchain_images, surface_formats.format);
+    ) {
+        Ok(objects) => objects,
+        Err(msg) => {
+            unsafe { logical_device.destroy_device(None) };
+            return Err(msg);
+        }
+    };
 
     // let mut graphics_queue_command_pools = Vec::new();
     // for queue_family in &graphics_queues {
@@ -212,45 +278,45 @@ pub fn default(xcb_ptr: *mut xcb_connection_t, xcb_window: &Window) -> VkContext
     // for queue_family in &transfer_queues {
     //     transfer_queue_command_pools.push(build_pools(*queue_family, &logical_device));
     // }
-    let graphics_pool = build_pools(*graphics_queues.first().unwrap(), &logical_device);
-    let transfer_pool = build_pools(*transfer_queues.first().unwrap(), &logical_device);
+    debug::name_object(graphics_pool, "graphics_pool");
+    debug::name_object(transfer_pool, "transfer_pool");
 
     crate::graphics::pools::init(
         graphics_pool,
-        graphics_queues[0],
+        graphics_family,
         transfer_pool,
-        transfer_queues[0],
+        transfer_family,
         logical_device.clone(),
     );
-    crate::graphics::shaders::init(logical_device.clone());
-
-    // let buffers = allocate_command_buffer(
-    //     graphics_queue_command_pools.first().unwrap(),
-    //     &logical_device,
-    // );
+    crate::graphics::descriptors::init(
+        logical_device.clone(),
+        crate::graphics::render::FRAMES_IN_FLIGHT,
+    );
+    crate::graphics::shaders::init(logical_device.clone())?;
+    crate::graphics::pipeline_cache::init(logical_device.clone(), &physical_device_properties);
+    crate::graphics::pipelines::init(logical_device.clone())?;
+    crate::graphics::postprocess::init(logical_device.clone())?;
+    crate::graphics::targets::init(logical_device.clone());
+    crate::graphics::compute::init(logical_device.clone());
+    crate::graphics::render::init();
 
     graphics::swapchain::init(
         swapchain,
         swapchain_device,
         swapchain_images,
         swapchain_views,
-        surface_formats,
+        swapchain_settings,
     );
 
-    VkContext {
+    Ok(VkContext {
         entry,
         instance,
         physical_device,
         physical_memory_properties,
-        physical_ext_names,
         // device_queue_create_info,
-        graphics_priorities,
-        graphics_counts: graphics_queue_counts,
-        graphics_queues,
+        graphics_family,
         // graphics_queue_create_infos,
-        transfer_queues,
-        transfer_counts: transfer_queue_counts,
-        transfer_priorities,
+        transfer_family,
         // transfer_queue_create_infos,
         logical_device,
         graphics_queue,
@@ -258,6 +324,9 @@ pub fn default(xcb_ptr: *mut xcb_connection_t, xcb_window: &Window) -> VkContext
         khr_surface_instance,
         surface,
         surface_capabilities,
+        xcb_ptr,
+        xcb_window,
+        torn_down: false,
         // surface_formats,
         // presentation_queues,
         // swapchain_device,
@@ -267,39 +336,81 @@ pub fn default(xcb_ptr: *mut xcb_connection_t, xcb_window: &Window) -> VkContext
         // graphics_queue_command_pools,
         // transfer_queue_command_pools,
         // buffers,
-    }
+    })
 }
 
-fn fill_queue_bits(
-    queue_family_properties: &[QueueFamilyProperties],
-    desired_queue_count: u32,
-    include_queue_types: &QueueFlags,
-    exclude_queue_types: &QueueFlags,
-) -> (Vec<u32>, Vec<u32>, Vec<Vec<f32>>) {
-    debug!(
-        "Selecting {} queues of type {:?}, excluding {:?}",
-        desired_queue_count, include_queue_types, exclude_queue_types
-    );
-    let queue_indices = select_queue_families(
-        queue_family_properties,
-        include_queue_types,
-        exclude_queue_types,
-    );
-
-    let mut counts = Vec::new();
+struct PresentationObjects {
+    swapchain: SwapchainKHR,
+    images: Vec<Image>,
+    views: Vec<ImageView>,
+    graphics_pool: CommandPool,
+    transfer_pool: CommandPool,
+}
 
-    for index in &queue_indices {
-        let temp = queue_family_properties.get(*index as usize).unwrap();
-        let count = std::cmp::min(desired_queue_count, temp.queue_count);
-        counts.push(count);
-    }
+// *** presentation_objects(device, swapchain_device, surface, ...) -> Result<PresentationObjects, DustError>
+//
+// The swapchain with its images and views, and the command pools: what the logical device is
+// needed for, and can fail, before the engine's modules are set up.  Whatever was made before a
+// failure is destroyed again; the device itself is the caller's.
+//
+fn presentation_objects(
+    device: &Device,
+    swapchain_device: &ash::khr::swapchain::Device,
+    surface: SurfaceKHR,
+    settings: &SwapchainSettings,
+    graphics_family: u32,
+    transfer_family: u32,
+    surface_capabilities: &SurfaceCapabilitiesKHR,
+) -> Result<PresentationObjects, DustError> {
+    let swapchain = make_swapchain(
+        swapchain_device,
+        surface,
+        settings,
+        &[graphics_family],
+        surface_capabilities,
+        SwapchainKHR::null(),
+    )?;
+
+    let images_and_views = swapchain_images(swapchain_device, swapchain).and_then(|images| {
+        let views = image_views(device, &images, settings.format.format)?;
+        Ok((images, views))
+    });
+    let (images, views) = match images_and_views {
+        Ok(made) => made,
+        Err(msg) => {
+            unsafe { swapchain_device.destroy_swapchain(swapchain, None) };
+            return Err(msg);
+        }
+    };
 
-    let mut queue_group_priorities = Vec::<Vec<f32>>::new();
-    for count in &counts {
-        queue_group_priorities.push(vec![0.5; *count as usize]);
+    let pools = build_pools(graphics_family, device).and_then(|graphics_pool| {
+        match build_pools(transfer_family, device) {
+            Ok(transfer_pool) => Ok((graphics_pool, transfer_pool)),
+            Err(msg) => {
+                unsafe { device.destroy_command_pool(graphics_pool, None) };
+                Err(msg)
+            }
+        }
+    });
+
+    match pools {
+        Ok((graphics_pool, transfer_pool)) => Ok(PresentationObjects {
+            swapchain,
+            images,
+            views,
+            graphics_pool,
+            transfer_pool,
+        }),
+        Err(msg) => {
+            unsafe {
+                for view in views {
+                    device.destroy_image_view(view, None);
+                }
+                swapchain_device.destroy_swapchain(swapchain, None);
+            }
+            Err(msg)
+        }
     }
-
-    (queue_indices, counts, queue_group_priorities)
 }
 
 #[cfg(all(target_os = "windows", not(target_os = "linux")))]
@@ -312,24 +423,165 @@ pub fn default() {
 
 impl Drop for VkContext {
     fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+// The choices a game makes before the Vulkan context exists.  Anything left unset keeps whatever
+// the module it belongs to already holds - the engine default, unless it was set there directly.
+pub struct VkContextBuilder {
+    requirements: Option<DeviceRequirements>,
+    device: Option<DeviceOverride>,
+    present_mode: Option<PresentMode>,
+    swapchain_config: Option<SwapchainConfig>,
+    frame_cap: Option<u32>,
+    shader_dir: Option<PathBuf>,
+}
+
+impl VkContextBuilder {
+    // Extensions, features and limits the device has to offer, on top of the engine's own.
+    pub fn requirements(mut self, requirements: DeviceRequirements) -> VkContextBuilder {
+        self.requirements = Some(requirements);
+        self
+    }
+
+    // A device to take over the best scoring one.  DUST_DEVICE still wins over it.
+    pub fn device(mut self, device: DeviceOverride) -> VkContextBuilder {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: PresentMode) -> VkContextBuilder {
+        self.present_mode = Some(present_mode);
+        self
+    }
+
+    pub fn swapchain_config(mut self, swapchain_config: SwapchainConfig) -> VkContextBuilder {
+        self.swapchain_config = Some(swapchain_config);
+        self
+    }
+
+    // At most this many frames a second, whatever the present mode allows.
+    pub fn frame_cap(mut self, frame_cap: u32) -> VkContextBuilder {
+        self.frame_cap = Some(frame_cap);
+        self
+    }
+
+    // Where to load the compiled shaders from instead of beside the executable.
+    pub fn shader_dir(mut self, shader_dir: PathBuf) -> VkContextBuilder {
+        self.shader_dir = Some(shader_dir);
+        self
+    }
+
+    // Takes the device, present mode, frame cap and shader directory from a loaded configuration.
+    pub fn config(mut self, config: &GraphicsConfig) -> VkContextBuilder {
+        self.device = config.device.clone();
+        self.present_mode = Some(config.present_mode);
+        self.frame_cap = Some(config.frame_cap);
+        self.shader_dir = config.shader_dir.clone();
+        self
+    }
+
+    // *** build(self, xcb_ptr: *mut xcb_connection_t, xcb_window: &Window) -> Result<VkContext, DustError>
+    //
+    // Hands the choices to their modules and creates the context for xcb_window, which has to
+    // stay open for as long as the context lives.  Nothing is left behind if this fails.
+    //
+    pub fn build(
+        self,
+        xcb_ptr: *mut xcb_connection_t,
+        xcb_window: &Window,
+    ) -> Result<VkContext, DustError> {
+        if let Some(requirements) = self.requirements {
+            requirements::set_requirements(requirements);
+        }
+        if self.device.is_some() {
+            device_selection::set_override(self.device);
+        }
+        if let Some(present_mode) = self.present_mode {
+            swapchain::set_present_mode(present_mode);
+        }
+        if let Some(swapchain_config) = self.swapchain_config {
+            swapchain::set_config(swapchain_config);
+        }
+        if self.frame_cap.is_some() {
+            frame_limiter::set_frame_cap(self.frame_cap);
+        }
+        if self.shader_dir.is_some() {
+            shaders::set_shader_dir(self.shader_dir);
+        }
+
+        default(xcb_ptr, xcb_window)
+    }
+}
+
+impl VkContext {
+    pub fn builder() -> VkContextBuilder {
+        VkContextBuilder {
+            requirements: None,
+            device: None,
+            present_mode: None,
+            swapchain_config: None,
+            frame_cap: None,
+            shader_dir: None,
+        }
+    }
+
+    // *** recover(&mut self) -> Result<Vec<Semaphore>, DustError>
+    //
+    // Starts over after a DustError that is_lost(): tears down the context and everything made
+    // from it, chooses a device again - not necessarily the same one - and makes a new surface for
+    // the same window.  Resident textures are uploaded again; the semaphores returned signal when
+    // they are ready and belong in the next frame's images_ready.  Any other image made on the old
+    // device has to be dropped before this is called.  If the rebuild fails the context stays torn
+    // down and unusable, and recover can be tried again.
+    //
+    pub fn recover(&mut self) -> Result<Vec<Semaphore>, DustError> {
+        warn!("Rebuilding the Vulkan context.");
+        self.teardown();
+
+        let xcb_window = self.xcb_window;
+        *self = create(self.entry.clone(), self.xcb_ptr, &xcb_window)?;
+
+        info!("Vulkan context rebuilt; uploading the resident textures again.");
+        crate::graphics::resident::restore(self)
+    }
+
+    // Destroys everything made from the context once the device has finished with it, in the
+    // reverse of the order build made it.  Only the first call does anything.
+    fn teardown(&mut self) {
+        if self.torn_down {
+            return;
+        }
+
+        // A lost device never goes idle, but it is still torn down the same way.
+        if let Err(msg) = unsafe { self.logical_device.device_wait_idle() } {
+            warn!("The device did not go idle before teardown: {:?}", msg);
+        }
+
         debug!("Killing Vulkan objects.");
+        crate::graphics::resident::release();
+        crate::graphics::render::destroy(self);
         unsafe {
-            // self.buffers.clear();
-            // self.swapchain_device
-            // .destroy_swapchain(self.swapchain, None);
             crate::graphics::swapchain::destroy(self);
-            crate::graphics::pools::destroy(self);
+            crate::graphics::compute::destroy();
+            crate::graphics::targets::destroy();
+            crate::graphics::postprocess::destroy();
+            crate::graphics::pipelines::destroy();
+            crate::graphics::pipeline_cache::destroy();
             crate::graphics::shaders::destroy(self);
+            crate::graphics::descriptors::destroy();
+            crate::graphics::pools::destroy(self);
             self.khr_surface_instance
                 .destroy_surface(self.surface, None);
             self.logical_device.destroy_device(None);
+            debug::destroy();
             self.instance.destroy_instance(None);
         };
+        self.torn_down = true;
         debug!("Vulkan objects destroyed.");
     }
-}
 
-impl VkContext {
     pub fn match_memory_type(
         &self,
         filter: u32,
@@ -347,29 +599,129 @@ impl VkContext {
         }
         Err(DustError::NoMatchingMemoryType)
     }
+
+    // The present modes the surface supports on this device.
+    pub fn surface_present_modes(&self) -> Result<Vec<PresentModeKHR>, DustError> {
+        supported_present_modes(
+            &self.khr_surface_instance,
+            &self.physical_device,
+            &self.surface,
+        )
+    }
+
+    // *** build_swapchain(&self, swapchain_device, settings, old_swapchain) -> Result<..., DustError>
+    //
+    // Makes a replacement swapchain for the context's surface, with its images and views.
+    // old_swapchain is retired by the new one, but is still the caller's to destroy.
+    //
+    pub fn build_swapchain(
+        &self,
+        swapchain_device: &ash::khr::swapchain::Device,
+        settings: &SwapchainSettings,
+        old_swapchain: SwapchainKHR,
+    ) -> Result<(SwapchainKHR, Vec<Image>, Vec<ImageView>), DustError> {
+        let swapchain = make_swapchain(
+            swapchain_device,
+            self.surface,
+            settings,
+            &[self.graphics_family],
+            &self.surface_capabilities,
+            old_swapchain,
+        )?;
+        let images = swapchain_images(swapchain_device, swapchain)?;
+        let views = image_views(&self.logical_device, &images, settings.format.format)?;
+
+        Ok((swapchain, images, views))
+    }
+
+    // *** depth_format(&self) -> Result<Format, DustError>
+    //
+    // The most precise depth format the device can use as an attachment with optimal tiling.
+    // Vulkan requires D16_UNORM at the very least, so a conformant driver always finds something.
+    //
+    pub fn depth_format(&self) -> Result<Format, DustError> {
+        let candidates = [
+            Format::D32_SFLOAT,
+            Format::D32_SFLOAT_S8_UINT,
+            Format::D24_UNORM_S8_UINT,
+            Format::D16_UNORM,
+        ];
+
+        for format in candidates {
+            let properties = unsafe {
+                self.instance
+                    .get_physical_device_format_properties(self.physical_device, format)
+            };
+            if properties
+                .optimal_tiling_features
+                .contains(FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            {
+                debug!("Using {:?} for depth attachments", format);
+                return Ok(format);
+            }
+        }
+
+        Err(DustError::NoSuitableDevice(String::from(
+            "the device supports none of the depth attachment formats",
+        )))
+    }
+
+    // *** max_sample_count(&self, wanted: SampleCountFlags) -> SampleCountFlags
+    //
+    // The highest sample count up to wanted that the device supports for both colour and depth
+    // framebuffer attachments.  Falls back to TYPE_1, which is always supported.
+    //
+    pub fn max_sample_count(&self, wanted: SampleCountFlags) -> SampleCountFlags {
+        let limits = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+                .limits
+        };
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        let candidates = [
+            SampleCountFlags::TYPE_64,
+            SampleCountFlags::TYPE_32,
+            SampleCountFlags::TYPE_16,
+            SampleCountFlags::TYPE_8,
+            SampleCountFlags::TYPE_4,
+            SampleCountFlags::TYPE_2,
+        ];
+
+        candidates
+            .into_iter()
+            .find(|candidate| {
+                candidate.as_raw() <= wanted.as_raw() && supported.contains(*candidate)
+            })
+            .unwrap_or(SampleCountFlags::TYPE_1)
+    }
 }
 
-fn init() -> ash::Entry {
+fn init() -> Result<ash::Entry, DustError> {
     debug!("Starting initialization");
     match unsafe { ash::Entry::load() } {
-        Ok(entry) => entry,
-        Err(msg) => {
-            panic!("The Vulkan initialization process has failed: {}", msg);
-        }
+        Ok(entry) => Ok(entry),
+        Err(msg) => Err(DustError::LoaderUnavailable(msg.to_string())),
     }
 }
 
 #[cfg(all(target_os = "linux", not(target_os = "windows")))]
-fn instance(entry: &ash::Entry) -> ash::Instance {
+fn instance(entry: &ash::Entry) -> Result<ash::Instance, DustError> {
     scan(entry);
 
     debug!("Starting instance creation...");
     let app_name = CString::new("Dust for Linux").unwrap();
-    let xcb_ext_name = [
+    let mut xcb_ext_name = vec![
         // khr_surface_name.as_c_str().as_ptr(),
         ash::vk::KHR_SURFACE_NAME.as_ptr(),
         ash::vk::KHR_XCB_SURFACE_NAME.as_ptr(),
     ];
+    xcb_ext_name.extend(debug::instance_extensions(entry));
+    if extended_color_spaces_enabled(entry) {
+        xcb_ext_name.push(ash::vk::EXT_SWAPCHAIN_COLORSPACE_NAME.as_ptr());
+    }
+    let layer_names = debug::instance_layers(entry);
 
     debug!("Extension names setup...");
 
@@ -377,18 +729,23 @@ fn instance(entry: &ash::Entry) -> ash::Instance {
 
     debug!("App info struct filled");
 
-    let instance_info = InstanceCreateInfo::default()
+    let mut messenger_info = debug::messenger_create_info();
+    let mut instance_info = InstanceCreateInfo::default()
         .application_info(&app_info)
-        .enabled_extension_names(&xcb_ext_name);
+        .enabled_extension_names(&xcb_ext_name)
+        .enabled_layer_names(&layer_names);
+    if debug::enabled(entry) {
+        instance_info = instance_info.push_next(&mut messenger_info);
+    }
 
     debug!("instance_info struct filled");
 
     match unsafe { entry.create_instance(&instance_info, None) } {
         Ok(instance) => {
             debug!("Instance successfully created?");
-            instance
+            Ok(instance)
         }
-        Err(msg) => panic!("Instance creation failed: {}", msg),
+        Err(msg) => Err(DustError::vulkan("creating the instance", msg)),
     }
 }
 
@@ -416,120 +773,30 @@ fn xcb_surface(
     instance: &ash::khr::xcb_surface::Instance,
     xcb_ptr: *mut xcb_connection_t,
     xcb_window: &Window,
-) -> SurfaceKHR {
+) -> Result<SurfaceKHR, DustError> {
     let xcb_void: *mut std::ffi::c_void = xcb_ptr as *mut c_void;
     let surface_info_struct = XcbSurfaceCreateInfoKHR::default()
         .window(xcb_window.resource_id())
         .connection(xcb_void);
 
     match unsafe { instance.create_xcb_surface(&surface_info_struct, None) } {
-        Ok(surface) => surface,
-        Err(msg) => {
-            panic!("Surface creation (predictably) failed: {:?}", msg)
-        }
+        Ok(surface) => Ok(surface),
+        Err(msg) => Err(DustError::vulkan("creating the XCB surface", msg)),
     }
 }
 
-fn enumerate_physical_devs(instance: &Instance) -> PhysicalDevice {
-    let mut p_devs: Vec<PhysicalDevice> =
-        if let Ok(enumerable) = unsafe { instance.enumerate_physical_devices() } {
-            enumerable
-        } else {
-            panic!("Unable to retrieve the physical devices associated with this instance.");
-        };
-
-    if p_devs.is_empty() {
-        panic!("There are no detected Vulkan compatible physical devices: cannot proceed.");
-    }
-
-    let mut best_pd: PhysicalDevice = p_devs.pop().unwrap();
-    let pd_props = unsafe { instance.get_physical_device_properties(best_pd) };
-    let mut best_score = shitty_score_physical_device_properties(&pd_props);
-    while let Some(temp) = p_devs.pop() {
-        let pd_props = unsafe { instance.get_physical_device_properties(temp) };
-        let score = shitty_score_physical_device_properties(&pd_props);
-        if score > best_score {
-            best_score = score;
-            best_pd = temp;
-            debug!(
-                "Swapping in device {} as best device",
-                pd_props.device_name_as_c_str().unwrap().to_str().unwrap()
-            )
-        }
-    }
-
-    best_pd
-}
-
-fn shitty_score_physical_device_properties(device_props: &PhysicalDeviceProperties) -> usize {
-    let mut score = 1;
-
-    match device_props.device_type {
-        PhysicalDeviceType::DISCRETE_GPU => {
-            score *= 10;
-        }
-        PhysicalDeviceType::INTEGRATED_GPU => {
-            score *= 5;
-        }
-        PhysicalDeviceType::CPU => {
-            score *= 1;
-        }
-        _ => score *= 0,
-    };
-
-    match device_props.vendor_id {
-        0x1002 | 0x1022 => {
-            score += 5;
-        }
-        0x10de => {
-            score += 3;
-        }
-        0x8086 => {
-            score += 2;
-        }
-        _ => {
-            score += 1;
-        }
-    }
-
-    score
-}
-
-fn select_preferred_presentation_mode(
+fn supported_present_modes(
     instance: &ash::khr::surface::Instance,
     physical_device: &PhysicalDevice,
     surface: &SurfaceKHR,
-) -> PresentModeKHR {
-    let modes = match unsafe {
-        instance.get_physical_device_surface_present_modes(*physical_device, *surface)
-    } {
-        Ok(modes) => modes,
-        Err(msg) => {
-            panic!("Could not get supported presentation modes: {:?}", msg);
-        }
-    };
-
-    let mut best_mode = PresentModeKHR::SHARED_CONTINUOUS_REFRESH;
-    let mut best_rating = usize::MAX;
-
-    for mode in modes {
-        let new_rating = priority_mode(&mode);
-        if new_rating < best_rating {
-            best_rating = new_rating;
-            best_mode = mode;
-        }
-    }
-
-    best_mode
-}
-
-fn priority_mode(mode: &PresentModeKHR) -> usize {
-    match mode {
-        &PresentModeKHR::MAILBOX => 1,
-        &PresentModeKHR::FIFO => 2,
-        &PresentModeKHR::FIFO_RELAXED => 3,
-        &PresentModeKHR::IMMEDIATE => 4,
-        _ => 10,
+) -> Result<Vec<PresentModeKHR>, DustError> {
+    match unsafe { instance.get_physical_device_surface_present_modes(*physical_device, *surface) }
+    {
+        Ok(modes) => Ok(modes),
+        Err(msg) => Err(DustError::vulkan(
+            "querying the surface's present modes",
+            msg,
+        )),
     }
 }
 
@@ -537,15 +804,13 @@ fn map_physical_device_to_surface_properties(
     instance: &ash::khr::surface::Instance,
     device: &PhysicalDevice,
     surface: &SurfaceKHR,
-) -> SurfaceCapabilitiesKHR {
+) -> Result<SurfaceCapabilitiesKHR, DustError> {
     match unsafe { instance.get_physical_device_surface_capabilities(*device, *surface) } {
-        Ok(surface_props) => surface_props,
-        Err(msg) => {
-            panic!(
-                "Unable to get the physical device-surface capabilities: {}",
-                msg
-            );
-        }
+        Ok(surface_props) => Ok(surface_props),
+        Err(msg) => Err(DustError::vulkan(
+            "querying the surface's capabilities",
+            msg,
+        )),
     }
 }
 
@@ -556,86 +821,31 @@ fn get_physical_memory_properties(
     unsafe { instance.get_physical_device_memory_properties(*physical_device) }
 }
 
-fn find_extensions_supported_by_pdev(instance: &Instance, p_dev: PhysicalDevice) -> Vec<String> {
-    let mut extension_list = Vec::new();
-    match unsafe { instance.enumerate_device_extension_properties(p_dev) } {
-        Ok(props) => {
-            debug!("Supported layers for physical device: ");
-            for prop in props {
-                let extension_name = unsafe { CStr::from_ptr(prop.extension_name.as_ptr()) };
-                match extension_name.to_str() {
-                    Ok(extension_name_str) => {
-                        if is_wanted_extension(extension_name_str) {
-                            extension_list.push(extension_name_str.to_string())
-                        }
-                        debug!("\t{}", extension_name_str);
-                    }
-                    Err(_) => {
-                        debug!("\tUnconvertable extension name.");
-                    }
-                }
-            }
-        }
-        Err(msg) => {
-            error!("enumerate_device_extension_properties emitted an error when used with the selected physical device: {:?}", msg);
-        }
-    }
-
-    extension_list
-}
-
-// Just for now, to shut clippy up, this is matches.
-// However, in the future we may want additional extensions
-// to be loaded and I don't know how well matches! is going
-// to scale across maybe a lot of branches.
-fn is_wanted_extension(ext_name: &str) -> bool {
-    matches!(ext_name, "VK_KHR_swapchain")
-}
-
 fn make_logical_device(
     instance: &Instance,
     p_dev: &PhysicalDevice,
-    exts: &[String],
+    enabled: &requirements::EnabledDevice,
     queue_selection: &[DeviceQueueCreateInfo],
-) -> Device {
-    // This initial copy operation is required to give the CStrings a long-lived
-    // place to stay.  Previous tries resulted in garbage being captured for the
-    // extension names, because the CStrings are being allocated on the heap, then
-    // dropped at the end of an arbitrary scope (either as a result of a closure or
-    // because of the end of a loop scope, etc).  This meant that the raw pointers
-    // we are capturing to give to Vulkan are up for grabs and are being reused
-    // almost immediately - leading to the destruction of the text data.
-    let cstr_temp_exts: Vec<CString> = exts
+) -> Result<Device, DustError> {
+    let exts_arr: Vec<*const i8> = enabled
+        .extensions
         .iter()
-        .map(|ext| CString::new(ext.as_str()))
-        .filter(Result::is_ok)
-        .map(Result::unwrap)
-        .collect();
-    let exts_arr: Vec<*const i8> = cstr_temp_exts
-        .iter()
-        .map(CString::as_c_str)
-        .map(|c_str| c_str.as_ptr())
+        .map(|extension| extension.as_ptr())
         .collect();
 
-    // let physical_features = setup_physical_features(instance);
-    let physical_features = unsafe { instance.get_physical_device_features(*p_dev) };
-    let mut sync2_create_info =
-        PhysicalDeviceSynchronization2Features::default().synchronization2(true);
+    // Only what negotiation settled on: the required features, and the optional ones the device
+    // turned out to have.
+    let mut feature_set = enabled.feature_set();
+    let mut features = feature_set.chain();
 
     let create_info = DeviceCreateInfo::default()
-        .push_next(&mut sync2_create_info)
+        .push_next(&mut features)
         .queue_create_infos(queue_selection)
-        .enabled_extension_names(&exts_arr)
-        .enabled_features(&physical_features);
+        .enabled_extension_names(&exts_arr);
 
     match unsafe { instance.create_device(*p_dev, &create_info, None) } {
-        Ok(device) => device,
-        Err(msg) => {
-            panic!(
-                "Could not construct logical device for physical device and options: {:?}",
-                msg
-            )
-        }
+        Ok(device) => Ok(device),
+        Err(msg) => Err(DustError::vulkan("creating the logical device", msg)),
     }
 }
 
@@ -651,118 +861,6 @@ fn show_queue_family_properties(queue_families: &[QueueFamilyProperties]) {
     }
 }
 
-fn select_transfer_queues(queue_families: &[QueueFamilyProperties]) -> Vec<u32> {
-    select_queue_families(queue_families, &QueueFlags::TRANSFER, &QueueFlags::GRAPHICS)
-    // let mut transfer_indices = Vec::new();
-    //
-    // for (index, element) in queue_families.iter().enumerate() {
-    //     if element.queue_flags.contains(QueueFlags::TRANSFER)
-    //         && !element.queue_flags.contains(QueueFlags::GRAPHICS)
-    //     {
-    //         debug!("Found pure transfer queue.");
-    //         transfer_indices.push(index as u32);
-    //     }
-    // }
-    //
-    // transfer_indices
-}
-
-fn select_queue_families(
-    queue_families: &[QueueFamilyProperties],
-    include_types: &QueueFlags,
-    exclude_types: &QueueFlags,
-) -> Vec<u32> {
-    let mut transfer_indices = Vec::new();
-
-    debug!(
-        "Attempting to select queues including family type {:?}, excluding {:?}",
-        include_types, exclude_types
-    );
-
-    for (index, element) in queue_families.iter().enumerate() {
-        if element.queue_flags & *include_types == *include_types
-            && element.queue_flags & *exclude_types == QueueFlags::empty()
-        // if element.queue_flags.contains(*include_types)
-        //     && !element.queue_flags.contains(*exclude_types)
-        {
-            debug!("Found pure transfer queue.");
-            transfer_indices.push(index as u32);
-        }
-    }
-
-    transfer_indices
-}
-
-fn choose_transfer_queue_counts(
-    queue_families: &[QueueFamilyProperties],
-    transfer_indices: &[u32],
-) -> Vec<u32> {
-    let mut counts = Vec::new();
-
-    for index in transfer_indices {
-        let temp = queue_families.get(*index as usize).unwrap();
-        let count = std::cmp::min(5, temp.queue_count);
-        counts.push(count);
-    }
-
-    counts
-}
-
-fn select_graphics_queues(queue_families: &[QueueFamilyProperties]) -> Vec<u32> {
-    select_queue_families(queue_families, &QueueFlags::TRANSFER, &QueueFlags::empty())
-    // let mut graphics_indices = Vec::new();
-    //
-    // for (index, element) in queue_families.iter().enumerate() {
-    //     if element.queue_flags.contains(QueueFlags::GRAPHICS) {
-    //         debug!("Found graphics queue.");
-    //         graphics_indices.push(index as u32);
-    //     }
-    // }
-    //
-    // graphics_indices
-}
-
-fn choose_graphics_queue_counts(
-    _queue_families: &[QueueFamilyProperties],
-    queue_indices: &[u32],
-) -> Vec<u32> {
-    let mut graphics_counts = Vec::new();
-
-    for _index in queue_indices {
-        // let temp = queue_families[(*index) as usize];
-        graphics_counts.push(1);
-    }
-
-    graphics_counts
-}
-
-fn construct_queue_create_info<'a, 'b>(
-    queue_indices: &[u32],
-    queue_counts: &[u32],
-    priorities: &'a [Vec<f32>],
-) -> Vec<DeviceQueueCreateInfo<'b>>
-where
-    'a: 'b,
-{
-    let mut queue_create_infos = Vec::new();
-
-    for (iter_index, queue_index) in queue_indices.iter().enumerate() {
-        let queue_count = queue_counts.get(iter_index).unwrap();
-        let queue_priorities = priorities.get(iter_index).unwrap();
-
-        debug!("Creating queue request struct for queue_index {}; requesting {} queues at priorities {:?}", queue_index, queue_count, queue_priorities);
-        let mut queue_create_info = DeviceQueueCreateInfo::default()
-            .queue_family_index(*queue_index)
-            .queue_priorities(queue_priorities);
-        queue_create_info.queue_count = *queue_count;
-
-        debug!("queue_priority: {:?}", queue_create_info.p_queue_priorities);
-        queue_create_infos.push(queue_create_info);
-    }
-
-    queue_create_infos
-}
-
 fn get_queue(device: &Device, reference_info: u32) -> Queue {
     // let family_index = reference_info.queue_family_index;
     let queue_index = 0;
@@ -770,144 +868,86 @@ fn get_queue(device: &Device, reference_info: u32) -> Queue {
     unsafe { device.get_device_queue(reference_info, queue_index) }
 }
 
-fn select_presentation_queues(
-    device: &'_ PhysicalDevice,
-    surface: &'_ SurfaceKHR,
-    queues_to_test: &[u32],
-    instance: &ash::khr::surface::Instance,
-) -> Vec<u32> {
-    debug!("Filtering selected queues for those that are presentation queues");
-    debug!(
-        "{} queues have been presented for review.",
-        queues_to_test.len()
-    );
-    let mut presentation_queues = Vec::new();
-
-    for queue_family in queues_to_test {
-        debug!("  Testing queue {}", queue_family);
-        match unsafe {
-            instance.get_physical_device_surface_support(*device, *queue_family, *surface)
-        } {
-            Ok(true) => {
-                debug!(
-                    "Queue index {} supports writing to a surface.",
-                    queue_family
-                );
-                presentation_queues.push(*queue_family);
-            }
-            Ok(false) => {
-                debug!(
-                    "Queue index {} does not support writing to a surface.",
-                    queue_family
-                )
-            }
-            Err(msg) => {
-                error!("Querying the physical device's support for surface-writing queues generated error {:?}", msg)
-            }
-        }
-    }
-
-    presentation_queues
-}
-
-fn find_formats_and_colorspaces(
+fn supported_surface_formats(
     instance: &ash::khr::surface::Instance,
     p_dev: PhysicalDevice,
     surface: &SurfaceKHR,
-) -> ash::vk::SurfaceFormatKHR {
-    let formats = match unsafe { instance.get_physical_device_surface_formats(p_dev, *surface) } {
-        Ok(formats) => formats,
-        Err(msg) => {
-            panic!(
-                "Querying physical device & surface for supported formats failed: {}",
-                msg
-            );
-        }
-    };
-
-    match formats.iter().find(|format| {
-        format.format == ash::vk::Format::R8G8B8A8_SRGB
-            || format.format == ash::vk::Format::B8G8R8A8_SRGB
-    }) {
-        Some(format) => *format,
-        None => {
-            panic!("No 32-bit SRGB format was discovered - aborting launch.");
+) -> Result<Vec<SurfaceFormatKHR>, DustError> {
+    match unsafe { instance.get_physical_device_surface_formats(p_dev, *surface) } {
+        Ok(formats) => {
+            debug!("Surface formats: {:?}", formats);
+            Ok(formats)
         }
+        Err(msg) => Err(DustError::vulkan("querying the surface's formats", msg)),
     }
 }
 
-fn test_capabilities(surface_capabilities: &SurfaceCapabilitiesKHR) {
-    if surface_capabilities.min_image_count < 2 {
-        panic!("Double buffering unsupported by the surface.");
-    }
-    if !surface_capabilities
-        .supported_usage_flags
-        .contains(ImageUsageFlags::TRANSFER_DST)
-    {
-        panic!("Unable to use surface as a transfer destination.")
+// VK_EXT_swapchain_colorspace is only asked for when the swapchain configuration wants HDR, and
+// only enabled when the loader has it; the HDR colour spaces are out of reach otherwise.
+fn extended_color_spaces_enabled(entry: &Entry) -> bool {
+    if !crate::graphics::swapchain::wants_extended_color_spaces() {
+        return false;
     }
-    if !surface_capabilities
-        .supported_transforms
-        .contains(SurfaceTransformFlagsKHR::IDENTITY)
-    {
-        panic!("Unable to display image with 0° rotation.");
-    }
-    if !surface_capabilities
-        .supported_composite_alpha
-        .contains(CompositeAlphaFlagsKHR::OPAQUE)
-    {
-        panic!("Unable to display opaque/non-transparent image.");
+
+    match unsafe { entry.enumerate_instance_extension_properties(None) } {
+        Ok(extensions) => extensions.iter().any(|extension| {
+            extension
+                .extension_name_as_c_str()
+                .is_ok_and(|name| name == ash::vk::EXT_SWAPCHAIN_COLORSPACE_NAME)
+        }),
+        Err(msg) => {
+            error!("Unable to list instance extensions: {:?}", msg);
+            false
+        }
     }
 }
 
 fn make_swapchain(
     device: &ash::khr::swapchain::Device,
     surface: SurfaceKHR,
-    formatting: &SurfaceFormatKHR,
+    settings: &SwapchainSettings,
     queue_families: &[u32],
     surface_capabilities: &SurfaceCapabilitiesKHR,
-    present_mode: PresentModeKHR,
-) -> SwapchainKHR {
+    old_swapchain: SwapchainKHR,
+) -> Result<SwapchainKHR, DustError> {
     let swapchain_info = SwapchainCreateInfoKHR::default()
         .flags(SwapchainCreateFlagsKHR::empty())
         .surface(surface)
-        .min_image_count(surface_capabilities.min_image_count)
-        .image_format(formatting.format)
-        .image_color_space(formatting.color_space)
+        .min_image_count(settings.image_count)
+        .image_format(settings.format.format)
+        .image_color_space(settings.format.color_space)
         .image_extent(surface_capabilities.current_extent)
         .image_array_layers(1)
         .image_usage(ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::COLOR_ATTACHMENT)
         .image_sharing_mode(SharingMode::EXCLUSIVE)
         .queue_family_indices(queue_families)
-        .pre_transform(surface_capabilities.current_transform)
-        .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
-        .present_mode(present_mode)
+        .pre_transform(settings.pre_transform)
+        .composite_alpha(settings.composite_alpha)
+        .present_mode(settings.present_mode)
+        .old_swapchain(old_swapchain)
         .clipped(true);
 
     match unsafe { device.create_swapchain(&swapchain_info, None) } {
-        Ok(sc) => sc,
-        Err(msg) => {
-            panic!("Swapchain creation failed: {:?}", msg);
-        }
+        Ok(sc) => Ok(sc),
+        Err(msg) => Err(DustError::vulkan("creating the swapchain", msg)),
     }
 }
 
 fn swapchain_images(
     device: &ash::khr::swapchain::Device,
     swapchain: ash::vk::SwapchainKHR,
-) -> Vec<Image> {
+) -> Result<Vec<Image>, DustError> {
     match unsafe { device.get_swapchain_images(swapchain) } {
-        Ok(images) => images,
-        Err(msg) => {
-            panic!(
-                "Could not retrieve the collection of swapchian images: {:?}",
-                msg
-            )
-        }
+        Ok(images) => Ok(images),
+        Err(msg) => Err(DustError::vulkan("retrieving the swapchain images", msg)),
     }
 }
 
-fn image_views(device: &ash::Device, images: &[Image], surface_format: Format) -> Vec<ImageView> {
+fn image_views(
+    device: &ash::Device,
+    images: &[Image],
+    surface_format: Format,
+) -> Result<Vec<ImageView>, DustError> {
     let mut views = Vec::with_capacity(images.len());
 
     for image in images {
@@ -932,38 +972,25 @@ fn image_views(device: &ash::Device, images: &[Image], surface_format: Format) -
         match unsafe { device.create_image_view(&create_info, None) } {
             Ok(view) => views.push(view),
             Err(msg) => {
-                panic!("An image view creation failed: {:?}", msg);
+                for view in views {
+                    unsafe { device.destroy_image_view(view, None) };
+                }
+                return Err(DustError::vulkan("creating a swapchain image view", msg));
             }
         }
     }
 
-    views
+    Ok(views)
 }
 
-fn build_pools(queue_family: u32, device: &ash::Device) -> CommandPool {
+fn build_pools(queue_family: u32, device: &ash::Device) -> Result<CommandPool, DustError> {
     let pool_create_info = CommandPoolCreateInfo::default()
         .flags(CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(queue_family);
 
     match unsafe { device.create_command_pool(&pool_create_info, None) } {
-        Ok(pool) => pool,
-        Err(msg) => {
-            panic!("The command pool creation step failed: {:?}", msg);
-        }
-    }
-}
-
-fn allocate_command_buffer(command_pool: &CommandPool, device: &ash::Device) -> Vec<CommandBuffer> {
-    let buffer_info = CommandBufferAllocateInfo::default()
-        .command_pool(*command_pool)
-        .level(CommandBufferLevel::PRIMARY)
-        .command_buffer_count(1);
-
-    match unsafe { device.allocate_command_buffers(&buffer_info) } {
-        Ok(buffer) => buffer,
-        Err(msg) => {
-            panic!("Command buffer allocation from pool failed: {:?}", msg);
-        }
+        Ok(pool) => Ok(pool),
+        Err(msg) => Err(DustError::vulkan("creating a command pool", msg)),
     }
 }
 
@@ -989,7 +1016,8 @@ fn scan(vk_entry: &Entry) {
     let vk_layer_props = match unsafe { vk_entry.enumerate_instance_layer_properties() } {
         Ok(props) => props,
         Err(msg) => {
-            panic!("ERR: {}", msg);
+            error!("Unable to list the instance layers: {}", msg);
+            return;
         }
     };
 
@@ -1029,7 +1057,7 @@ fn scan(vk_entry: &Entry) {
                 }
             }
             Err(msg) => {
-                panic!("ERR for {:?}: {}", layer_name.unwrap(), msg)
+                error!("Unable to list the extensions of {:?}: {}", layer_name, msg)
             }
         }
     }
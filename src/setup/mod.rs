@@ -1,4 +1,8 @@
+pub mod config;
+pub mod debug;
+pub mod device_selection;
 pub mod instance;
 pub mod key_mapper;
+pub mod requirements;
 pub mod xcb_keymapper;
 pub mod xcb_window;
@@ -0,0 +1,568 @@
+use std::{
+    ffi::{CStr, CString},
+    sync::{Mutex, OnceLock},
+};
+
+use ash::{
+    vk::{
+        self, Bool32, PhysicalDevice, PhysicalDeviceFeatures, PhysicalDeviceFeatures2,
+        PhysicalDeviceLimits, PhysicalDeviceProperties, PhysicalDeviceVulkan11Features,
+        PhysicalDeviceVulkan12Features, PhysicalDeviceVulkan13Features,
+    },
+    Instance,
+};
+use log::info;
+
+use crate::dust_errors::DustError;
+
+// A device feature the engine may ask for, wherever in the core, 1.1, 1.2 or 1.3 feature structs
+// it lives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    // Core
+    SamplerAnisotropy,
+    FillModeNonSolid,
+    WideLines,
+    IndependentBlend,
+    MultiDrawIndirect,
+    ShaderInt64,
+    ShaderStorageImageExtendedFormats,
+    // Vulkan 1.1
+    ShaderDrawParameters,
+    // Vulkan 1.2
+    TimelineSemaphore,
+    DescriptorIndexing,
+    RuntimeDescriptorArray,
+    DescriptorBindingPartiallyBound,
+    DescriptorBindingVariableDescriptorCount,
+    ShaderSampledImageArrayNonUniformIndexing,
+    BufferDeviceAddress,
+    ScalarBlockLayout,
+    // Vulkan 1.3
+    Synchronization2,
+    DynamicRendering,
+    Maintenance4,
+}
+
+impl DeviceFeature {
+    fn field<'a>(&self, set: &'a mut FeatureSet) -> &'a mut Bool32 {
+        match self {
+            DeviceFeature::SamplerAnisotropy => &mut set.core.sampler_anisotropy,
+            DeviceFeature::FillModeNonSolid => &mut set.core.fill_mode_non_solid,
+            DeviceFeature::WideLines => &mut set.core.wide_lines,
+            DeviceFeature::IndependentBlend => &mut set.core.independent_blend,
+            DeviceFeature::MultiDrawIndirect => &mut set.core.multi_draw_indirect,
+            DeviceFeature::ShaderInt64 => &mut set.core.shader_int64,
+            DeviceFeature::ShaderStorageImageExtendedFormats => {
+                &mut set.core.shader_storage_image_extended_formats
+            }
+            DeviceFeature::ShaderDrawParameters => &mut set.v11.shader_draw_parameters,
+            DeviceFeature::TimelineSemaphore => &mut set.v12.timeline_semaphore,
+            DeviceFeature::DescriptorIndexing => &mut set.v12.descriptor_indexing,
+            DeviceFeature::RuntimeDescriptorArray => &mut set.v12.runtime_descriptor_array,
+            DeviceFeature::DescriptorBindingPartiallyBound => {
+                &mut set.v12.descriptor_binding_partially_bound
+            }
+            DeviceFeature::DescriptorBindingVariableDescriptorCount => {
+                &mut set.v12.descriptor_binding_variable_descriptor_count
+            }
+            DeviceFeature::ShaderSampledImageArrayNonUniformIndexing => {
+                &mut set.v12.shader_sampled_image_array_non_uniform_indexing
+            }
+            DeviceFeature::BufferDeviceAddress => &mut set.v12.buffer_device_address,
+            DeviceFeature::ScalarBlockLayout => &mut set.v12.scalar_block_layout,
+            DeviceFeature::Synchronization2 => &mut set.v13.synchronization2,
+            DeviceFeature::DynamicRendering => &mut set.v13.dynamic_rendering,
+            DeviceFeature::Maintenance4 => &mut set.v13.maintenance4,
+        }
+    }
+}
+
+// A device limit that can be given a minimum.  Float limits are compared as floats, the rest as
+// whole numbers; both fit in an f64.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceLimit {
+    MaxImageDimension2D,
+    MaxPushConstantsSize,
+    MaxBoundDescriptorSets,
+    MaxPerStageDescriptorSampledImages,
+    MaxPerStageDescriptorStorageImages,
+    MaxComputeWorkGroupInvocations,
+    MaxColorAttachments,
+    MaxSamplerAnisotropy,
+}
+
+impl DeviceLimit {
+    fn value(&self, limits: &PhysicalDeviceLimits) -> f64 {
+        match self {
+            DeviceLimit::MaxImageDimension2D => limits.max_image_dimension2_d as f64,
+            DeviceLimit::MaxPushConstantsSize => limits.max_push_constants_size as f64,
+            DeviceLimit::MaxBoundDescriptorSets => limits.max_bound_descriptor_sets as f64,
+            DeviceLimit::MaxPerStageDescriptorSampledImages => {
+                limits.max_per_stage_descriptor_sampled_images as f64
+            }
+            DeviceLimit::MaxPerStageDescriptorStorageImages => {
+                limits.max_per_stage_descriptor_storage_images as f64
+            }
+            DeviceLimit::MaxComputeWorkGroupInvocations => {
+                limits.max_compute_work_group_invocations as f64
+            }
+            DeviceLimit::MaxColorAttachments => limits.max_color_attachments as f64,
+            DeviceLimit::MaxSamplerAnisotropy => limits.max_sampler_anisotropy as f64,
+        }
+    }
+}
+
+// *** DeviceRequirements
+//
+// What the engine needs from a device, and what it will use if it is there.  A device missing
+// anything required is not used; anything optional is enabled when present and quietly left off
+// when not.  Check enabled() at runtime before relying on an optional extension or feature.
+#[derive(Clone, Debug)]
+pub struct DeviceRequirements {
+    pub api_version: u32,
+    pub required_extensions: Vec<&'static CStr>,
+    pub optional_extensions: Vec<&'static CStr>,
+    pub required_features: Vec<DeviceFeature>,
+    pub optional_features: Vec<DeviceFeature>,
+    pub min_limits: Vec<(DeviceLimit, f64)>,
+}
+
+impl DeviceRequirements {
+    // Nothing but the API version the instance asks for; the 1.3 feature struct is chained into
+    // every query, so 1.3 is the floor.
+    pub fn new() -> DeviceRequirements {
+        DeviceRequirements {
+            api_version: vk::make_api_version(0, 1, 3, 0),
+            required_extensions: Vec::new(),
+            optional_extensions: Vec::new(),
+            required_features: Vec::new(),
+            optional_features: Vec::new(),
+            min_limits: Vec::new(),
+        }
+    }
+
+    // *** engine() -> DeviceRequirements
+    //
+    // What the renderer as it stands needs: a swapchain, synchronization2 for its barriers, the
+    // extended storage image formats the palette resolve writes (r8ui, rgba16f), and room for its
+    // push constants and descriptor sets.  The rest is used when available.
+    //
+    pub fn engine() -> DeviceRequirements {
+        DeviceRequirements::new()
+            .require_extension(ash::khr::swapchain::NAME)
+            .require_feature(DeviceFeature::Synchronization2)
+            .require_feature(DeviceFeature::ShaderStorageImageExtendedFormats)
+            .optional_feature(DeviceFeature::TimelineSemaphore)
+            .optional_feature(DeviceFeature::DynamicRendering)
+            .optional_feature(DeviceFeature::DescriptorIndexing)
+            .optional_feature(DeviceFeature::SamplerAnisotropy)
+            .min_limit(DeviceLimit::MaxPushConstantsSize, 128.0)
+            .min_limit(DeviceLimit::MaxBoundDescriptorSets, 4.0)
+            .min_limit(DeviceLimit::MaxImageDimension2D, 4096.0)
+    }
+
+    pub fn require_extension(mut self, name: &'static CStr) -> DeviceRequirements {
+        self.required_extensions.push(name);
+        self
+    }
+
+    pub fn optional_extension(mut self, name: &'static CStr) -> DeviceRequirements {
+        self.optional_extensions.push(name);
+        self
+    }
+
+    pub fn require_feature(mut self, feature: DeviceFeature) -> DeviceRequirements {
+        self.required_features.push(feature);
+        self
+    }
+
+    pub fn optional_feature(mut self, feature: DeviceFeature) -> DeviceRequirements {
+        self.optional_features.push(feature);
+        self
+    }
+
+    pub fn min_limit(mut self, limit: DeviceLimit, minimum: f64) -> DeviceRequirements {
+        self.min_limits.push((limit, minimum));
+        self
+    }
+}
+
+impl Default for DeviceRequirements {
+    fn default() -> DeviceRequirements {
+        DeviceRequirements::engine()
+    }
+}
+
+static REQUIREMENTS: OnceLock<Mutex<DeviceRequirements>> = OnceLock::new();
+static ENABLED: OnceLock<Mutex<Option<EnabledDevice>>> = OnceLock::new();
+
+// *** set_requirements(requirements: DeviceRequirements)
+//
+// Replaces the engine's requirements.  Must be called before the Vulkan context is created.
+//
+pub fn set_requirements(requirements: DeviceRequirements) {
+    *REQUIREMENTS
+        .get_or_init(|| Mutex::new(DeviceRequirements::engine()))
+        .lock()
+        .unwrap() = requirements;
+}
+
+pub fn requirements() -> DeviceRequirements {
+    REQUIREMENTS
+        .get_or_init(|| Mutex::new(DeviceRequirements::engine()))
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+// The core and 1.1 - 1.3 feature structs, unchained.  Only ever chained together for as long as
+// a query or device creation takes.
+pub struct FeatureSet {
+    core: PhysicalDeviceFeatures,
+    v11: PhysicalDeviceVulkan11Features<'static>,
+    v12: PhysicalDeviceVulkan12Features<'static>,
+    v13: PhysicalDeviceVulkan13Features<'static>,
+}
+
+impl FeatureSet {
+    fn empty() -> FeatureSet {
+        FeatureSet {
+            core: PhysicalDeviceFeatures::default(),
+            v11: PhysicalDeviceVulkan11Features::default(),
+            v12: PhysicalDeviceVulkan12Features::default(),
+            v13: PhysicalDeviceVulkan13Features::default(),
+        }
+    }
+
+    fn query(instance: &Instance, physical_device: PhysicalDevice) -> FeatureSet {
+        let mut set = FeatureSet::empty();
+        let mut features2 = PhysicalDeviceFeatures2::default()
+            .push_next(&mut set.v11)
+            .push_next(&mut set.v12)
+            .push_next(&mut set.v13);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+        set.core = features2.features;
+
+        set.v11.p_next = std::ptr::null_mut();
+        set.v12.p_next = std::ptr::null_mut();
+        set.v13.p_next = std::ptr::null_mut();
+        set
+    }
+
+    fn has(&mut self, feature: DeviceFeature) -> bool {
+        *feature.field(self) == vk::TRUE
+    }
+
+    fn enable(&mut self, feature: DeviceFeature) {
+        *feature.field(self) = vk::TRUE;
+    }
+
+    // *** chain(&mut self) -> PhysicalDeviceFeatures2
+    //
+    // The features as one chain, ready to push onto a DeviceCreateInfo.  Borrows the set for as
+    // long as the chain is in use.
+    //
+    pub fn chain(&mut self) -> PhysicalDeviceFeatures2<'_> {
+        PhysicalDeviceFeatures2::default()
+            .features(self.core)
+            .push_next(&mut self.v11)
+            .push_next(&mut self.v12)
+            .push_next(&mut self.v13)
+    }
+}
+
+// *** EnabledDevice
+//
+// What negotiation settled on for the chosen device: the extensions and features to enable - all
+// the required ones, plus whichever optional ones it has - and its limits.  Published once the
+// logical device exists, for the rest of the engine to consult through enabled().
+#[derive(Clone, Debug)]
+pub struct EnabledDevice {
+    pub api_version: u32,
+    pub extensions: Vec<CString>,
+    pub features: Vec<DeviceFeature>,
+    pub limits: PhysicalDeviceLimits,
+}
+
+impl EnabledDevice {
+    pub fn has_extension(&self, name: &CStr) -> bool {
+        self.extensions
+            .iter()
+            .any(|extension| extension.as_c_str() == name)
+    }
+
+    pub fn has_feature(&self, feature: DeviceFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn feature_set(&self) -> FeatureSet {
+        let mut set = FeatureSet::empty();
+        for feature in &self.features {
+            set.enable(*feature);
+        }
+        set
+    }
+}
+
+// *** negotiate(instance, physical_device, requirements) -> Result<EnabledDevice, DustError>
+//
+// Checks physical_device against requirements.  Every shortfall is collected, so one failure
+// reports all of them in a DustError::MissingRequirements, rather than the first one found or a
+// bare error out of create_device.
+//
+pub fn negotiate(
+    instance: &Instance,
+    physical_device: PhysicalDevice,
+    requirements: &DeviceRequirements,
+) -> Result<EnabledDevice, DustError> {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    // The feature structs cannot be queried reliably below 1.3.
+    check_version(requirements.api_version, properties.api_version)?;
+
+    let mut missing = Vec::new();
+    let available_extensions =
+        match unsafe { instance.enumerate_device_extension_properties(physical_device) } {
+            Ok(extensions) => extensions,
+            Err(msg) => {
+                missing.push(format!("a list of its extensions ({:?})", msg));
+                Vec::new()
+            }
+        };
+    let offered: Vec<&CStr> = available_extensions
+        .iter()
+        .filter_map(|extension| extension.extension_name_as_c_str().ok())
+        .collect();
+
+    let available_features = FeatureSet::query(instance, physical_device);
+    settle(
+        requirements,
+        &properties,
+        &offered,
+        available_features,
+        missing,
+    )
+}
+
+fn check_version(required: u32, offered: u32) -> Result<(), DustError> {
+    if offered >= required {
+        return Ok(());
+    }
+
+    Err(DustError::MissingRequirements(vec![format!(
+        "Vulkan {}.{} (device offers {}.{})",
+        vk::api_version_major(required),
+        vk::api_version_minor(required),
+        vk::api_version_major(offered),
+        vk::api_version_minor(offered),
+    )]))
+}
+
+// What negotiate() makes of what the device offers, adding to whatever is already missing.
+fn settle(
+    requirements: &DeviceRequirements,
+    properties: &PhysicalDeviceProperties,
+    offered: &[&CStr],
+    mut available_features: FeatureSet,
+    mut missing: Vec<String>,
+) -> Result<EnabledDevice, DustError> {
+    let mut extensions = Vec::new();
+    for name in &requirements.required_extensions {
+        if offered.contains(name) {
+            extensions.push(CString::from(*name));
+        } else {
+            missing.push(format!("extension {:?}", name));
+        }
+    }
+    for name in &requirements.optional_extensions {
+        if offered.contains(name) {
+            extensions.push(CString::from(*name));
+        }
+    }
+
+    let mut features = Vec::new();
+    for feature in &requirements.required_features {
+        if available_features.has(*feature) {
+            features.push(*feature);
+        } else {
+            missing.push(format!("feature {:?}", feature));
+        }
+    }
+    for feature in &requirements.optional_features {
+        if available_features.has(*feature) {
+            features.push(*feature);
+        }
+    }
+
+    for (limit, minimum) in &requirements.min_limits {
+        let value = limit.value(&properties.limits);
+        if value < *minimum {
+            missing.push(format!(
+                "{:?} of {} (device offers {})",
+                limit, minimum, value
+            ));
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(DustError::MissingRequirements(missing));
+    }
+
+    Ok(EnabledDevice {
+        api_version: properties.api_version,
+        extensions,
+        features,
+        limits: properties.limits,
+    })
+}
+
+// Makes the negotiated set visible through enabled().  Called by device creation, and again each
+// time the device is rebuilt - possibly on a different physical device.
+pub fn publish(enabled: EnabledDevice) {
+    info!("Enabled device extensions: {:?}", enabled.extensions);
+    info!("Enabled device features: {:?}", enabled.features);
+
+    *ENABLED.get_or_init(|| Mutex::new(None)).lock().unwrap() = Some(enabled);
+}
+
+// *** enabled() -> Result<EnabledDevice, DustError>
+//
+// The extensions, features and limits the logical device was created with.  NotInitialized until
+// a logical device has been created.
+//
+pub fn enabled() -> Result<EnabledDevice, DustError> {
+    match ENABLED
+        .get()
+        .and_then(|enabled| enabled.lock().unwrap().clone())
+    {
+        Some(enabled) => Ok(enabled),
+        None => Err(DustError::NotInitialized("the logical device")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(max_push_constants_size: u32) -> PhysicalDeviceProperties {
+        PhysicalDeviceProperties {
+            api_version: vk::make_api_version(0, 1, 3, 280),
+            limits: PhysicalDeviceLimits {
+                max_push_constants_size,
+                max_bound_descriptor_sets: 8,
+                max_image_dimension2_d: 16384,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn features(available: &[DeviceFeature]) -> FeatureSet {
+        let mut set = FeatureSet::empty();
+        for feature in available {
+            set.enable(*feature);
+        }
+        set
+    }
+
+    fn missing(result: Result<EnabledDevice, DustError>) -> Vec<String> {
+        match result {
+            Err(DustError::MissingRequirements(missing)) => missing,
+            other => panic!("expected missing requirements, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_old_device_is_turned_down_on_its_version() {
+        let required = vk::make_api_version(0, 1, 3, 0);
+        assert!(check_version(required, vk::make_api_version(0, 1, 3, 0)).is_ok());
+        assert!(check_version(required, vk::make_api_version(0, 1, 4, 0)).is_ok());
+        match check_version(required, vk::make_api_version(0, 1, 2, 198)) {
+            Err(DustError::MissingRequirements(missing)) => {
+                assert_eq!(
+                    missing,
+                    vec![String::from("Vulkan 1.3 (device offers 1.2)")]
+                );
+            }
+            other => panic!("expected missing requirements, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn enables_the_required_and_whatever_optional_is_there() {
+        let requirements = DeviceRequirements::engine();
+        let enabled = settle(
+            &requirements,
+            &device(256),
+            &[ash::khr::swapchain::NAME, ash::ext::memory_budget::NAME],
+            features(&[
+                DeviceFeature::Synchronization2,
+                DeviceFeature::ShaderStorageImageExtendedFormats,
+                DeviceFeature::DynamicRendering,
+                DeviceFeature::WideLines,
+            ]),
+            Vec::new(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            enabled.extensions,
+            vec![CString::from(ash::khr::swapchain::NAME)]
+        );
+        assert_eq!(
+            enabled.features,
+            vec![
+                DeviceFeature::Synchronization2,
+                DeviceFeature::ShaderStorageImageExtendedFormats,
+                DeviceFeature::DynamicRendering,
+            ]
+        );
+        assert!(enabled.has_extension(ash::khr::swapchain::NAME));
+        assert!(!enabled.has_feature(DeviceFeature::TimelineSemaphore));
+        assert_eq!(enabled.limits.max_push_constants_size, 256);
+    }
+
+    #[test]
+    fn reports_every_shortfall_at_once() {
+        let requirements = DeviceRequirements::engine();
+        let missing = missing(settle(
+            &requirements,
+            &device(64),
+            &[],
+            features(&[DeviceFeature::Synchronization2]),
+            vec![String::from(
+                "a list of its extensions (ERROR_OUT_OF_HOST_MEMORY)",
+            )],
+        ));
+
+        assert_eq!(
+            missing,
+            vec![
+                String::from("a list of its extensions (ERROR_OUT_OF_HOST_MEMORY)"),
+                String::from("extension \"VK_KHR_swapchain\""),
+                String::from("feature ShaderStorageImageExtendedFormats"),
+                String::from("MaxPushConstantsSize of 128 (device offers 64)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_enabled_feature_set_holds_only_what_was_enabled() {
+        let enabled = EnabledDevice {
+            api_version: vk::make_api_version(0, 1, 3, 0),
+            extensions: Vec::new(),
+            features: vec![
+                DeviceFeature::SamplerAnisotropy,
+                DeviceFeature::Maintenance4,
+            ],
+            limits: PhysicalDeviceLimits::default(),
+        };
+
+        let mut set = enabled.feature_set();
+        assert!(set.has(DeviceFeature::SamplerAnisotropy));
+        assert!(set.has(DeviceFeature::Maintenance4));
+        assert!(!set.has(DeviceFeature::Synchronization2));
+        assert_eq!(set.core.sampler_anisotropy, vk::TRUE);
+        assert_eq!(set.v13.maintenance4, vk::TRUE);
+    }
+}
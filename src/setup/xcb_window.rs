@@ -1,21 +1,25 @@
-use core::panic;
-use std::sync::mpsc::SyncSender;
+use std::sync::{mpsc::SyncSender, Arc};
 use std::{time::Duration, u32};
 
 type Point = (i32, i32);
 type Rect = (u32, u32);
 
-use log::debug;
+// The type of the ClientMessage stop_event_loop sends.
+const STOP_ATOM_NAME: &[u8] = b"_DUST_STOP_EVENT_LOOP";
+
+use log::{debug, error};
 use xcb::{
     x::{self, ConfigWindow, Cw, Event, EventMask, MapWindow, Window},
     xkb::UseExtension,
-    Connection, Extension,
+    Connection, Extension, Xid,
 };
 use xkbcommon::xkb::{self, Keymap};
 
-use crate::input::input::KeyStroke;
+use crate::dust_errors::DustError;
+use crate::input::input::{InputEvent, KeyStroke};
+use crate::setup::config::WindowConfig;
 
-pub fn connect() -> (Connection, i32) {
+pub fn connect() -> Result<(Connection, i32), DustError> {
     let ext = [
         Extension::Dri2,
         Extension::Dri3,
@@ -28,8 +32,11 @@ pub fn connect() -> (Connection, i32) {
             debug!("Connected to screen number {}", screen_num);
             (conn, screen_num)
         }
-        Err(_) => {
-            panic!("Connection attempt failed.  oooh noeeees");
+        Err(msg) => {
+            return Err(DustError::WindowSystem(format!(
+                "unable to connect to the X server: {}",
+                msg
+            )));
         }
     };
 
@@ -43,11 +50,34 @@ pub fn connect() -> (Connection, i32) {
             debug!("XKB supported? {}", xkb_support.supported());
         }
         Err(msg) => {
-            panic!("XKB not supported: {}", msg);
+            return Err(DustError::WindowSystem(format!(
+                "the X server does not support XKB: {}",
+                msg
+            )));
         }
     }
 
-    conn
+    Ok(conn)
+}
+
+// *** open(config: &WindowConfig) -> Result<(Connection, Window), DustError>
+//
+// Connects to the X server and puts up a window on the configured monitor - fullscreen, unless
+// the configuration gives a resolution - returning once the window manager has sized it.  The
+// Vulkan context is built for the window and the connection's raw pointer; the connection itself
+// is shared, in an Arc, with the thread running event_loop.
+//
+pub fn open(config: &WindowConfig) -> Result<(Connection, Window), DustError> {
+    let (conn, screen_num) = connect()?;
+    extension_data(&conn);
+    let window = create_window(&conn, screen_num)?;
+    let (upper_left, monitor_size) = interrogate_randr(&conn, window, config.monitor.as_deref())?;
+    match config.resolution {
+        Some(window_size) => resize_window(&conn, window, upper_left, window_size, false)?,
+        None => resize_window(&conn, window, upper_left, monitor_size, true)?,
+    }
+
+    Ok((conn, window))
 }
 
 pub fn extension_data(conn: &Connection) {
@@ -74,20 +104,10 @@ pub fn extension_data(conn: &Connection) {
     }
 }
 
-pub fn resize_window(conn: &Connection, window_id: Window, upper_left: Point, dim: Rect) {
-    let net_wm_win_state_cookie = conn.send_request(&x::InternAtom {
-        only_if_exists: true,
-        name: b"_NET_WM_STATE",
-    });
-    let net_wm_win_type = conn.wait_for_reply(net_wm_win_state_cookie).unwrap().atom();
-    let net_wm_win_state_fs_cookie = conn.send_request(&x::InternAtom {
-        only_if_exists: true,
-        name: b"_NET_WM_STATE_FULLSCREEN",
-    });
-    let net_wm_win_state_fs = conn
-        .wait_for_reply(net_wm_win_state_fs_cookie)
-        .unwrap()
-        .atom();
+// Asks the window manager, through _NET_WM_STATE, to show the window fullscreen.
+fn make_fullscreen(conn: &Connection, window_id: Window) -> Result<(), DustError> {
+    let net_wm_win_type = intern_atom(conn, b"_NET_WM_STATE")?;
+    let net_wm_win_state_fs = intern_atom(conn, b"_NET_WM_STATE_FULLSCREEN")?;
 
     conn.send_request(&x::ChangeProperty {
         mode: x::PropMode::Replace,
@@ -97,6 +117,20 @@ pub fn resize_window(conn: &Connection, window_id: Window, upper_left: Point, di
         data: &[net_wm_win_state_fs],
     });
 
+    Ok(())
+}
+
+pub fn resize_window(
+    conn: &Connection,
+    window_id: Window,
+    upper_left: Point,
+    dim: Rect,
+    fullscreen: bool,
+) -> Result<(), DustError> {
+    if fullscreen {
+        make_fullscreen(conn, window_id)?;
+    }
+
     conn.send_request(&x::ConfigureWindow {
         window: window_id,
         value_list: &[
@@ -158,31 +192,39 @@ pub fn resize_window(conn: &Connection, window_id: Window, upper_left: Point, di
                 }
             }
             Err(msg) => {
-                panic!(
-                    "Trying to check the update status of the geometry failed: {:?}",
+                return Err(DustError::WindowSystem(format!(
+                    "unable to confirm the window's new size: {}",
                     msg
-                )
+                )));
             }
         }
     }
 
     debug!("Window resized.");
+
+    Ok(())
 }
 
-fn deconstruct_parent(conn: &Connection, display_num: &i32) -> (Window, u32, u8) {
+fn deconstruct_parent(
+    conn: &Connection,
+    display_num: &i32,
+) -> Result<(Window, u32, u8), DustError> {
     if let Some(root) = conn
         .get_setup()
         .roots()
         .nth(display_num.unsigned_abs() as usize)
     {
-        (root.root(), root.root_visual(), root.root_depth())
+        Ok((root.root(), root.root_visual(), root.root_depth()))
     } else {
-        panic!("Unable to capture parent window data");
+        Err(DustError::WindowSystem(format!(
+            "the X server has no screen {}",
+            display_num
+        )))
     }
 }
 
-pub fn create_window(conn: &Connection, display_num: i32) -> Window {
-    let (parent_win, parent_vis, parent_depth) = deconstruct_parent(conn, &display_num);
+pub fn create_window(conn: &Connection, display_num: i32) -> Result<Window, DustError> {
+    let (parent_win, parent_vis, parent_depth) = deconstruct_parent(conn, &display_num)?;
     let window_id: x::Window = conn.generate_id();
 
     let our_window = x::CreateWindow {
@@ -201,6 +243,7 @@ pub fn create_window(conn: &Connection, display_num: i32) -> Window {
             Cw::EventMask(
                 EventMask::KEY_PRESS
                     | EventMask::KEY_RELEASE
+                    | EventMask::FOCUS_CHANGE
                     | EventMask::BUTTON_PRESS
                     | EventMask::BUTTON_RELEASE
                     | EventMask::POINTER_MOTION,
@@ -209,86 +252,244 @@ pub fn create_window(conn: &Connection, display_num: i32) -> Window {
     };
 
     conn.send_request_checked(&our_window);
-    conn.flush();
 
-    window_id
+    // Have the window manager's close button ask, through a ClientMessage, rather than kill the
+    // connection.
+    let wm_protocols = intern_atom(conn, b"WM_PROTOCOLS")?;
+    let wm_delete_window = intern_atom(conn, b"WM_DELETE_WINDOW")?;
+    conn.send_request(&x::ChangeProperty {
+        mode: x::PropMode::Replace,
+        window: window_id,
+        property: wm_protocols,
+        r#type: x::ATOM_ATOM,
+        data: &[wm_delete_window],
+    });
+    if let Err(msg) = conn.flush() {
+        return Err(DustError::WindowSystem(format!(
+            "unable to send the new window to the X server: {}",
+            msg
+        )));
+    }
+
+    Ok(window_id)
+}
+
+fn intern_atom(conn: &Connection, name: &[u8]) -> Result<x::Atom, DustError> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: false,
+        name,
+    });
+    match conn.wait_for_reply(cookie) {
+        Ok(reply) => Ok(reply.atom()),
+        Err(msg) => Err(DustError::WindowSystem(format!(
+            "unable to intern {}: {}",
+            String::from_utf8_lossy(name),
+            msg
+        ))),
+    }
 }
 
-pub fn interrogate_randr(conn: &Connection, window_id: Window) -> (Point, Rect) {
+// *** interrogate_randr(conn: &Connection, window_id: Window, monitor: Option<&str>) -> Result<(Point, Rect), DustError>
+//
+// Where the monitor called monitor is, and how big - or the primary monitor's, given None.  An
+// unknown name is an error listing the monitors there are.
+//
+pub fn interrogate_randr(
+    conn: &Connection,
+    window_id: Window,
+    monitor: Option<&str>,
+) -> Result<(Point, Rect), DustError> {
     let monitor_cookie = conn.send_request(&xcb::randr::GetMonitors {
         window: window_id,
         get_active: true,
     });
 
-    match conn.wait_for_reply(monitor_cookie) {
-        Ok(reply) => {
-            if let Some(primary_monitor) = reply.monitors().find(|monitor| monitor.primary()) {
-                debug!("Monitor name: {:?}", primary_monitor.name());
-                debug!("Primary? {}", primary_monitor.primary());
-                debug!("Automatic? {}", primary_monitor.automatic());
-                debug!(
-                    "Width x height (px): {} x {}",
-                    primary_monitor.width(),
-                    primary_monitor.height()
-                );
-                debug!(
-                    "Width x height (mm): {} x {}",
-                    primary_monitor.width_in_millimeters(),
-                    primary_monitor.height_in_millimeters()
-                );
-                debug!(
-                    "What's this x and y? x: {}, y: {}",
-                    primary_monitor.x(),
-                    primary_monitor.y()
-                );
-                (
-                    (primary_monitor.x() as i32, primary_monitor.y() as i32),
-                    (
-                        primary_monitor.width() as u32,
-                        primary_monitor.height() as u32,
-                    ),
-                )
-            } else {
-                panic!("No monitor flagged as primary.");
-            }
+    let reply = match conn.wait_for_reply(monitor_cookie) {
+        Ok(reply) => reply,
+        Err(msg) => {
+            return Err(DustError::WindowSystem(format!(
+                "unable to retrieve monitor data for analysis: {:?}",
+                msg
+            )));
         }
+    };
+
+    let mut names = Vec::new();
+    for candidate in reply.monitors() {
+        let name = atom_name(conn, candidate.name());
+        let chosen = match monitor {
+            Some(wanted) => name == wanted,
+            None => candidate.primary(),
+        };
+        if !chosen {
+            names.push(name);
+            continue;
+        }
+
+        debug!("Monitor name: {}", name);
+        debug!("Primary? {}", candidate.primary());
+        debug!("Automatic? {}", candidate.automatic());
+        debug!(
+            "Width x height (px): {} x {}",
+            candidate.width(),
+            candidate.height()
+        );
+        debug!(
+            "Width x height (mm): {} x {}",
+            candidate.width_in_millimeters(),
+            candidate.height_in_millimeters()
+        );
+        debug!(
+            "What's this x and y? x: {}, y: {}",
+            candidate.x(),
+            candidate.y()
+        );
+        return Ok((
+            (candidate.x() as i32, candidate.y() as i32),
+            (candidate.width() as u32, candidate.height() as u32),
+        ));
+    }
+
+    Err(DustError::WindowSystem(match monitor {
+        Some(wanted) => format!(
+            "there is no monitor called {}; the monitors are {}",
+            wanted,
+            names.join(", ")
+        ),
+        None => String::from("no monitor is flagged as primary"),
+    }))
+}
+
+fn atom_name(conn: &Connection, atom: x::Atom) -> String {
+    let cookie = conn.send_request(&x::GetAtomName { atom });
+    match conn.wait_for_reply(cookie) {
+        Ok(reply) => reply.name().to_utf8().into_owned(),
         Err(msg) => {
-            panic!("Unable to retrieve monitor data for analysis: {:?}", msg);
+            debug!("No name for atom {:?}: {:?}", atom, msg);
+            String::new()
         }
     }
 }
 
-pub fn event_loop(conn: Connection, _sender: SyncSender<KeyStroke>) {
+// *** event_loop(conn: Arc<Connection>, sender: SyncSender<InputEvent>)
+//
+// Runs on its own thread, passing key presses, releases, focus changes and close requests to the
+// game until stop_event_loop is called, the connection fails or the game drops its receiver.
+//
+pub fn event_loop(conn: Arc<Connection>, sender: SyncSender<InputEvent>) {
     let keymap = interrogate_keymaps(&conn);
     let state = xkb::State::new(&keymap);
+    let atoms = intern_atom(&conn, b"WM_PROTOCOLS").and_then(|wm_protocols| {
+        let wm_delete_window = intern_atom(&conn, b"WM_DELETE_WINDOW")?;
+        let stop = intern_atom(&conn, STOP_ATOM_NAME)?;
+        Ok((wm_protocols, wm_delete_window, stop))
+    });
+    // Returning drops sender, which the game loop takes for the window closing.
+    let (wm_protocols, wm_delete_window, stop) = match atoms {
+        Ok(atoms) => atoms,
+        Err(msg) => {
+            error!("The event thread cannot run: {}", msg);
+            return;
+        }
+    };
     loop {
-        match conn.wait_for_event() {
-            Ok(event) => {
-                match event {
-                    xcb::Event::X(Event::KeyPress(key)) => {
-                        debug!(
-                            "Single key: {:?}",
-                            state.key_get_one_sym(xkb::Keycode::new(key.detail() as u32))
-                        );
-                        match keymap.key_get_name(xkb::Keycode::new(key.detail() as u32)) {
-                            Some(sym) => {
-                                debug!("Key pressed: {}", sym);
-                            }
-                            None => {
-                                debug!("Key pressed with no corresponding symbol name in the map.");
-                            }
+        let input_event = match conn.wait_for_event() {
+            Ok(event) => match event {
+                xcb::Event::X(Event::KeyPress(key)) => {
+                    let key_stroke = key_stroke(&state, key.detail());
+                    match keymap.key_get_name(xkb::Keycode::new(key.detail() as u32)) {
+                        Some(sym) => {
+                            debug!("Key pressed: {} ({:?})", sym, key_stroke);
+                        }
+                        None => {
+                            debug!("Key pressed with no corresponding symbol name in the map.");
                         }
                     }
-                    _ => {
-                        debug!("Event received: {:?}", event);
+                    InputEvent::KeyDown(key_stroke)
+                }
+                xcb::Event::X(Event::KeyRelease(key)) => {
+                    InputEvent::KeyUp(key_stroke(&state, key.detail()))
+                }
+                xcb::Event::X(Event::FocusIn(_)) => InputEvent::FocusGained,
+                xcb::Event::X(Event::FocusOut(_)) => InputEvent::FocusLost,
+                xcb::Event::X(Event::ClientMessage(message)) => {
+                    if message.r#type() == stop {
+                        debug!("Event thread asked to stop.");
+                        break;
                     }
-                };
-            }
+                    match message.data() {
+                        x::ClientMessageData::Data32([atom, ..])
+                            if message.r#type() == wm_protocols
+                                && atom == wm_delete_window.resource_id() =>
+                        {
+                            InputEvent::CloseRequested
+                        }
+                        _ => {
+                            debug!("Client message received: {:?}", message);
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    debug!("Event received: {:?}", event);
+                    continue;
+                }
+            },
             Err(msg) => {
                 debug!("woops there it is: {:?}", msg);
                 break;
             }
+        };
+
+        if sender.send(input_event).is_err() {
+            debug!("The game has stopped listening for input.");
+            break;
+        }
+    }
+}
+
+// *** stop_event_loop(conn: &Connection, window: Window)
+//
+// Wakes the event thread with a message only it understands, so that it returns and can be
+// joined.  Sent to window with an empty event mask, which X delivers to the window's creator - us.
+//
+pub fn stop_event_loop(conn: &Connection, window: Window) {
+    let stop = match intern_atom(conn, STOP_ATOM_NAME) {
+        Ok(stop) => stop,
+        Err(msg) => {
+            error!("Unable to stop the event thread: {}", msg);
+            return;
         }
+    };
+    conn.send_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(window),
+        event_mask: EventMask::NO_EVENT,
+        event: &x::ClientMessageEvent::new(window, stop, x::ClientMessageData::Data32([0; 5])),
+    });
+    if let Err(msg) = conn.flush() {
+        debug!("Flush failed?  {:?}", msg);
+    }
+}
+
+// Takes the window down.  The Vulkan surface made for it has to be gone first.
+pub fn close_window(conn: &Connection, window: Window) {
+    conn.send_request(&x::DestroyWindow { window });
+    if let Err(msg) = conn.flush() {
+        debug!("Flush failed?  {:?}", msg);
+    }
+    debug!("Window destroyed.");
+}
+
+fn key_stroke(state: &xkb::State, keycode: u8) -> KeyStroke {
+    let keysym = state
+        .key_get_one_sym(xkb::Keycode::new(keycode as u32))
+        .raw();
+    match keysym {
+        xkb::keysyms::KEY_Caps_Lock
+        | xkb::keysyms::KEY_Num_Lock
+        | xkb::keysyms::KEY_Scroll_Lock => KeyStroke::ToggleKey(keysym),
+        _ => KeyStroke::Key(keysym),
     }
 }
 